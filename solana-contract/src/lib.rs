@@ -1,20 +1,46 @@
 #![feature(int_roundings)]
 
 use anchor_lang::prelude::*;
+use anchor_lang::solana_program::bpf_loader_upgradeable;
 use anchor_lang::solana_program::program::invoke;
 use anchor_lang::solana_program::system_instruction;
+use anchor_spl::token::{self, Mint, Token, TokenAccount, Transfer};
 
 declare_id!("4HzFUvrz1bZTVNHMVaBgXmJbfiPpxCX3VpH4YH7FyvvB");
 
-const PREFIX_STATE: &str = "state";
-const PREFIX_CONFIG: &str = "config";
-const PREFIX_BALANCE: &str = "balance";
-const PREFIX_ORDER: &str = "order";
-const PREFIX_RECEIPT: &str = "receipt";
-const PREFIX_CCFEE: &str = "ccfee";
-const PREFIX_COLLATERAL: &str = "collateral";
-const PREFIX_WITNESSES: &str = "witnesses";
-const ADMIN: &str = "BuTuA7YKzx5CUn3bALZcK97jQrFM94QfsBUaUdM6BCxm";
+pub const PREFIX_STATE: &str = "state";
+pub const PREFIX_CONFIG: &str = "config";
+pub const PREFIX_BALANCE: &str = "balance";
+pub const PREFIX_ORDER: &str = "order";
+pub const PREFIX_RECEIPT: &str = "receipt";
+pub const PREFIX_CCFEE: &str = "ccfee";
+pub const PREFIX_COLLATERAL: &str = "collateral";
+pub const PREFIX_WITNESSES: &str = "witnesses";
+pub const PREFIX_PROPOSAL: &str = "proposal";
+pub const PREFIX_PRICE: &str = "price";
+pub const PREFIX_VAULT: &str = "vault";
+pub const PREFIX_MORTGAGE: &str = "mortgage";
+pub const PREFIX_FREEZE: &str = "freeze";
+pub const PREFIX_RELEASE: &str = "release";
+pub const PREFIX_REINVEST: &str = "reinvest";
+pub const PREFIX_STAKE: &str = "stake";
+pub const PREFIX_STAKE_CONFIG: &str = "stake_config";
+pub const PREFIX_REWARD: &str = "reward";
+pub const PREFIX_ROLES: &str = "roles";
+pub const PREFIX_PENDING: &str = "pending";
+pub const PREFIX_TREASURY: &str = "treasury";
+pub const PREFIX_WITHDRAWAL: &str = "withdrawal";
+pub const PREFIX_RESERVATION: &str = "reservation";
+pub const MAX_INSTALLMENTS: u8 = 12;
+pub const CONFIG_DELAY: u64 = 2 * 86400;
+pub const MAX_GRACE: u64 = 30 * 86400;
+pub const MAX_CCFEE: u64 = 1_000_000_000;
+pub const PAUSE_BORROW: u64 = 1 << 0;
+pub const PAUSE_PLACE_ORDER: u64 = 1 << 1;
+pub const PAUSE_MULTISIG_COLLATERAL: u64 = 1 << 2;
+pub const PAUSE_EXTEND_LOAN: u64 = 1 << 3;
+pub const PAUSE_FREEZE_COLLATERAL: u64 = 1 << 4;
+pub const PAUSE_MULTISIG_RELEASE: u64 = 1 << 5;
 
 #[program]
 pub mod solana_lending {
@@ -24,28 +50,111 @@ pub mod solana_lending {
         let global = &mut ctx.accounts.global_state;
         global.curr_order_sn = 1;
         global.curr_receipt_sn = 1;
+        global.curr_proposal_sn = 1;
+        global.curr_freeze_sn = 1;
+        global.admin = ctx.accounts.payer.key();
+        let treasury = &mut ctx.accounts.treasury;
+        treasury.threshold = 1;
+        treasury.curr_withdrawal_sn = 1;
         Ok(())
     }
 
-    #[access_control(only_admin(&ctx.accounts.payer))]
-    pub fn set_config(
-        ctx: Context<SetConfig>,
-        min_ir: u64,
-        max_ir: u64,
-        penalty_ir: u64,
-        penalty_days: u64,
-        commission_rate: u64,
-        cycle: u64,
-        deadline: u64
-    ) -> Result<()> {
+    #[access_control(only_admin(&ctx.accounts.payer, &ctx.accounts.global))]
+    pub fn propose_admin(ctx: Context<UpdateAdmin>, new_admin: Pubkey) -> Result<()> {
+        let global = &mut ctx.accounts.global;
+        global.pending_admin = new_admin;
+
+        emit!(EventProposeAdmin {
+            admin: global.admin,
+            pending_admin: new_admin,
+        });
+        Ok(())
+    }
+
+    pub fn accept_admin(ctx: Context<UpdateAdmin>) -> Result<()> {
+        let global = &mut ctx.accounts.global;
+        let payer = &ctx.accounts.payer;
+        require!(global.pending_admin != Pubkey::default() && global.pending_admin == payer.key(), MyError::NoOperationPermission);
+        let old_admin = global.admin;
+        global.admin = payer.key();
+        global.pending_admin = Pubkey::default();
+
+        emit!(EventAcceptAdmin {
+            old_admin,
+            new_admin: global.admin,
+        });
+        Ok(())
+    }
+
+    #[access_control(has_role(&ctx.accounts.payer, &ctx.accounts.roles, Role::Pauser))]
+    pub fn set_pause(ctx: Context<SetPause>, paused: u64) -> Result<()> {
+        let global = &mut ctx.accounts.global;
+        global.paused = paused;
+
+        emit!(EventSetPause {
+            paused,
+        });
+        Ok(())
+    }
+
+    #[access_control(only_admin(&ctx.accounts.payer, &ctx.accounts.global))]
+    pub fn grant_role(ctx: Context<UpdateRoles>, role: Role, key: Pubkey) -> Result<()> {
+        let members = ctx.accounts.roles.members_mut(role);
+        require!(!members.contains(&key), MyError::RoleAlreadyGranted);
+        members.push(key);
+
+        emit!(EventGrantRole {
+            role,
+            key,
+        });
+        Ok(())
+    }
+
+    #[access_control(only_admin(&ctx.accounts.payer, &ctx.accounts.global))]
+    pub fn revoke_role(ctx: Context<UpdateRoles>, role: Role, key: Pubkey) -> Result<()> {
+        let members = ctx.accounts.roles.members_mut(role);
+        let index = members.iter().position(|&k| k == key).ok_or(MyError::RoleNotGranted)?;
+        members.remove(index);
+
+        emit!(EventRevokeRole {
+            role,
+            key,
+        });
+        Ok(())
+    }
+
+    // Config changes only take effect CONFIG_DELAY after being queued, so existing orders and
+    // loans get notice. The first config after deployment can be applied right away.
+    #[access_control(has_role(&ctx.accounts.payer, &ctx.accounts.roles, Role::ConfigManager))]
+    pub fn queue_config(ctx: Context<QueueConfig>, params: ConfigParams) -> Result<()> {
+        params.validate()?;
+
+        let now = ctx.accounts.clock.unix_timestamp as u64;
+        let eta = if ctx.accounts.config.initialized { now + CONFIG_DELAY } else { now };
+        let pending = &mut ctx.accounts.pending_config;
+        pending.params = params;
+        pending.eta = eta;
+
+        emit!(EventConfigQueued {
+            params,
+            eta,
+        });
+        Ok(())
+    }
+
+    #[access_control(has_role(&ctx.accounts.payer, &ctx.accounts.roles, Role::ConfigManager))]
+    pub fn apply_config(ctx: Context<ApplyConfig>) -> Result<()> {
+        let pending = &ctx.accounts.pending_config;
+        require!(ctx.accounts.clock.unix_timestamp as u64 >= pending.eta, MyError::ChangeStillLocked);
         let config = &mut ctx.accounts.config;
-        config.min_ir = min_ir;
-        config.max_ir = max_ir;
-        config.penalty_ir = penalty_ir;
-        config.penalty_days = penalty_days;
-        config.commission_rate = commission_rate;
-        config.cycle = cycle;
-        config.deadline = deadline;
+        let old = config.params();
+        config.set_params(&pending.params);
+        config.initialized = true;
+
+        emit!(EventConfigChanged {
+            old,
+            new: pending.params,
+        });
         Ok(())
     }
 
@@ -72,7 +181,8 @@ pub mod solana_lending {
         Ok(())
     }
 
-    pub fn place_order(ctx: Context<PlaceOrder>, amount: u64, rate: u64) -> Result<()> {
+    #[access_control(not_paused(&ctx.accounts.global, PAUSE_PLACE_ORDER))]
+    pub fn place_order(ctx: Context<PlaceOrder>, amount: u64, rate: u64, term: u64, installments: u8) -> Result<()> {
         let order = &mut ctx.accounts.order;
         let user_balance = &mut ctx.accounts.user_balance;
         let global = &mut ctx.accounts.global;
@@ -80,10 +190,15 @@ pub mod solana_lending {
     
         require!(user_balance.amount >= amount, MyError::InsufficientUserBalance);
         require!(rate >= config.min_ir && rate <= config.max_ir, MyError::IllegalInterestRate);
+        require!(term >= config.min_term && term <= config.max_term, MyError::IllegalLoanTerm);
+        require!((1..=MAX_INSTALLMENTS).contains(&installments), MyError::IllegalInstallments);
         order.sn = global.curr_order_sn;
         order.lender = ctx.accounts.payer.key();
         order.balance = amount;
         order.rate = rate;
+        order.term = term;
+        order.installments = installments;
+        order.mint = Pubkey::default();
         user_balance.amount -= amount;
         global.curr_order_sn += 1;
         
@@ -92,6 +207,9 @@ pub mod solana_lending {
             lender: ctx.accounts.payer.key(),
             balance: amount,
             rate: rate,
+            mint: order.mint,
+            term,
+            installments,
         });
 
         **ctx.accounts.user_balance.to_account_info().try_borrow_mut_lamports()? -= amount;
@@ -99,20 +217,29 @@ pub mod solana_lending {
         Ok(())
     }
 
+    // Reserved balance stays on the order until the reservation is borrowed or released; the
+    // order is closed once nothing is reserved.
     pub fn cancel_order(ctx: Context<CancelOrder>, order_sn: u64) -> Result<()> {
-        let order = &ctx.accounts.order;
+        let order = &mut ctx.accounts.order;
         let user_balance = &mut ctx.accounts.user_balance;
         require!(order.lender == *ctx.accounts.payer.key, MyError::NoOrderFound);
-        user_balance.amount += order.balance;
+        require!(order.mint == Pubkey::default(), MyError::MintMismatch);
+        let returned = order.available();
+        user_balance.amount += returned;
+        order.balance = order.reserved;
         
         emit!(EventCancelOrder {
             order_sn: order_sn,
             lender: ctx.accounts.payer.key(),
-            balance: order.balance,
+            balance: returned,
+            reserved: order.reserved,
         });
 
-        **ctx.accounts.order.to_account_info().try_borrow_mut_lamports()? -= order.balance;
-        **ctx.accounts.user_balance.to_account_info().try_borrow_mut_lamports()? += order.balance;
+        **ctx.accounts.order.to_account_info().try_borrow_mut_lamports()? -= returned;
+        **ctx.accounts.user_balance.to_account_info().try_borrow_mut_lamports()? += returned;
+        if ctx.accounts.order.reserved == 0 {
+            ctx.accounts.order.close(ctx.accounts.payer.to_account_info())?;
+        }
         Ok(())
     }
 
@@ -126,11 +253,14 @@ pub mod solana_lending {
         Ok(())
     }
 
-    #[access_control(is_witness(&ctx.accounts.payer, &ctx.accounts.witnesses))]
+    #[access_control(is_witness(&ctx.accounts.payer, &ctx.accounts.witnesses) not_paused(&ctx.accounts.global, PAUSE_MULTISIG_COLLATERAL))]
     pub fn multisig_collateral(ctx: Context<MultisigCollateral>, chainid: u32, collateral_sn: u64, source: [u8; 20], token: [u8; 20], frozen: u64, order_sn: u64, borrower: Pubkey, amount: u64) -> Result<()> {
+        let now = ctx.accounts.clock.unix_timestamp as u64;
+        let expiry = now + ctx.accounts.config.multisig_timeout;
         let multisig = &mut ctx.accounts.multisig;
         let witnesses = &mut ctx.accounts.witnesses;
         let payer = &ctx.accounts.payer;
+        ctx.accounts.reward.witness = payer.key();
         let collateral = Collateral {
             chainid,
             collateral_sn,
@@ -150,21 +280,31 @@ pub mod solana_lending {
                 });
                 multisig.branches = branches;
                 multisig.status = MultisigStatus::InProgress;
+                multisig.start = now;
+                multisig.threshold = witnesses.multisig_threshold as u64;
+                multisig.witnesses = witnesses.v.clone();
+                multisig.evaluate(witnesses);
+
+                // The first signature earmarks the amount on the order unless the borrower already
+                // reserved, or the order is gone or cannot cover it.
+                let order = &ctx.accounts.order;
+                if multisig.reservation.amount == 0 && !order.data_is_empty() {
+                    let mut order = Account::<Order>::try_from(order)?;
+                    if order.available() >= amount {
+                        reserve(multisig, &mut order, chainid, collateral_sn, borrower, amount, expiry);
+                        order.exit(&crate::ID)?;
+                    }
+                }
             }
             MultisigStatus::InProgress => {
+                require!(multisig.witnesses.contains(payer.key), MyError::WitnessNotInRound);
                 for branch in multisig.branches.iter() {
                     if let Some(_) = branch.signers.iter().find(|s| s == &payer.key) {
                         return Err(MyError::AlreadySigned.into());
                     }
                 }
                 match multisig.branches.iter_mut().find(|b| b.collateral == collateral) {
-                    Some(branch) => {
-                        branch.signers.push(payer.key());
-                        if branch.signers.len() == witnesses.multisig_threshold {
-                            multisig.status = MultisigStatus::ReachConsensus;
-                            multisig.branches.retain(|b| b.signers.len() == witnesses.multisig_threshold);
-                        }
-                    }
+                    Some(branch) => branch.signers.push(payer.key()),
                     None => {
                         multisig.branches.push(CollateralWithSigners {
                             collateral,
@@ -172,12 +312,14 @@ pub mod solana_lending {
                         });
                     }
                 }
+                multisig.evaluate(witnesses);
             }
             _ => {}
         }
         Ok(())
     }
 
+    #[access_control(not_paused(&ctx.accounts.global, PAUSE_BORROW))]
     pub fn borrow(ctx: Context<Borrow>, _chainid: u32, _collateral_sn: u64) -> Result<()> {
         let multisig = &mut ctx.accounts.multisig;
         let order = &mut ctx.accounts.order;
@@ -186,8 +328,14 @@ pub mod solana_lending {
         
         require!(multisig.status == MultisigStatus::ReachConsensus, MyError::CannotBorrowWithoutConsensus);
         let Collateral { chainid, collateral_sn: c_sn, source, token, frozen, borrower, order_sn, amount } = multisig.branches[0].collateral;
-        require!(order.balance >= amount, MyError::InsufficientOrderBalance);
+        let signers = multisig.valid_signers(&multisig.branches[0].signers, &ctx.accounts.witnesses);
+        require!(signers.len() as u64 >= multisig.threshold, MyError::ConsensusLost);
+        let own = multisig.own_reservation();
+        require!(order.available() + own >= amount, MyError::InsufficientOrderBalance);
+        require!(order.mint == Pubkey::default(), MyError::MintMismatch);
         multisig.status = MultisigStatus::Finished;
+        multisig.reservation.amount -= own;
+        order.reserved -= own;
 
         receipt.sn = global.curr_receipt_sn;
         receipt.borrower = borrower;
@@ -200,6 +348,11 @@ pub mod solana_lending {
         receipt.amount = amount;
         receipt.time = ctx.accounts.clock.unix_timestamp as u64;
         receipt.rate = order.rate;
+        receipt.mint = order.mint;
+        receipt.term = order.term;
+        receipt.grace = ctx.accounts.config.grace;
+        receipt.installments = order.installments;
+        receipt.signers = signers;
         global.curr_receipt_sn += 1;
         order.balance -= amount;
 
@@ -217,6 +370,10 @@ pub mod solana_lending {
             rate: receipt.rate,
             order_sn,
             order_balance: order.balance,
+            mint: receipt.mint,
+            term: receipt.term,
+            installments: receipt.installments,
+            grace: receipt.grace,
         });
 
         **ctx.accounts.order.to_account_info().try_borrow_mut_lamports()? -= amount;
@@ -224,32 +381,182 @@ pub mod solana_lending {
         Ok(())
     }
 
+    // Consensus only counts signers that are still witnesses, so removing witnesses mid-round can
+    // undo it. Anyone can re-evaluate a round after the witness set changed; expiring it does too.
+    pub fn recount_collateral(ctx: Context<RecountCollateral>, chainid: u32, collateral_sn: u64) -> Result<()> {
+        let multisig = &mut ctx.accounts.multisig;
+        require!(matches!(multisig.status, MultisigStatus::InProgress | MultisigStatus::ReachConsensus), MyError::MultisigNotInProgress);
+        multisig.evaluate(&ctx.accounts.witnesses);
+
+        emit!(EventRecountCollateral {
+            chainid,
+            collateral_sn,
+            consensus: multisig.status == MultisigStatus::ReachConsensus,
+        });
+        Ok(())
+    }
+
+    // A witness that signed the wrong collateral, e.g. after a source-chain reorg, withdraws its
+    // signature so it can sign again. Branches left without signers are dropped.
+    #[access_control(is_witness(&ctx.accounts.payer, &ctx.accounts.witnesses))]
+    pub fn revoke_collateral_signature(ctx: Context<RevokeCollateralSignature>, chainid: u32, collateral_sn: u64) -> Result<()> {
+        let multisig = &mut ctx.accounts.multisig;
+        let payer = ctx.accounts.payer.key();
+        require!(multisig.status == MultisigStatus::InProgress, MyError::MultisigNotInProgress);
+        let branch = multisig.branches.iter_mut().find(|b| b.signers.contains(&payer)).ok_or(MyError::NotSigned)?;
+        branch.signers.retain(|s| *s != payer);
+
+        emit!(EventRevokeCollateralSignature {
+            chainid,
+            collateral_sn,
+            witness: payer,
+            collateral: branch.collateral,
+            signers: branch.signers.len() as u64,
+        });
+        multisig.branches.retain(|b| !b.signers.is_empty());
+        Ok(())
+    }
+
+    // Lets the borrower of a round the witnesses have signed earmark an order's balance when the
+    // first signature could not, or its reservation was released, so neither the lender nor
+    // other borrowers can take it before the round settles.
+    pub fn reserve_order(ctx: Context<ReserveOrder>, chainid: u32, collateral_sn: u64, _order_sn: u64, amount: u64) -> Result<()> {
+        let multisig = &mut ctx.accounts.multisig;
+        let order = &mut ctx.accounts.order;
+        let payer = ctx.accounts.payer.key();
+        let expiry = ctx.accounts.clock.unix_timestamp as u64 + ctx.accounts.config.multisig_timeout;
+        require!(matches!(multisig.status, MultisigStatus::InProgress | MultisigStatus::ReachConsensus), MyError::MultisigNotInProgress);
+        require!(multisig.reservation.amount == 0, MyError::AlreadyReserved);
+        // only a borrower and order the witnesses attested to can be reserved for
+        let collateral = multisig.branches.iter().map(|b| &b.collateral)
+            .find(|c| c.borrower == payer && c.order_sn == order.sn)
+            .ok_or(MyError::NoOperationPermission)?;
+        require!(amount > 0 && amount <= collateral.amount, MyError::IllegalReserveAmount);
+        require!(order.available() >= amount, MyError::InsufficientOrderBalance);
+        reserve(multisig, order, chainid, collateral_sn, payer, amount, expiry);
+        Ok(())
+    }
+
+    // Returns a reservation to the order once it has expired or its round can no longer use it.
+    pub fn release_reservation(ctx: Context<ReleaseReservation>, chainid: u32, collateral_sn: u64) -> Result<()> {
+        let multisig = &mut ctx.accounts.multisig;
+        let order = &mut ctx.accounts.order;
+        let now = ctx.accounts.clock.unix_timestamp as u64;
+        let settled = matches!(multisig.status, MultisigStatus::Finished | MultisigStatus::Expired | MultisigStatus::Failed);
+        require!(multisig.reservation.amount > 0, MyError::NoReservation);
+        require!(settled || now >= multisig.reservation.expiry, MyError::ReservationStillActive);
+        let amount = multisig.reservation.amount;
+        order.reserved -= amount;
+        multisig.reservation.amount = 0;
+
+        emit!(EventReleaseReservation {
+            chainid,
+            collateral_sn,
+            order_sn: order.sn,
+            amount,
+        });
+        Ok(())
+    }
+
+    // A round can reach consensus on an order that was since cancelled, closed or drained below the
+    // agreed amount. Anyone can then fail it so the source chain unfreezes the collateral.
+    pub fn fail_borrow(ctx: Context<FailBorrow>, _chainid: u32, _collateral_sn: u64) -> Result<()> {
+        let multisig = &mut ctx.accounts.multisig;
+        require!(multisig.status == MultisigStatus::ReachConsensus, MyError::CannotBorrowWithoutConsensus);
+        let Collateral { chainid, collateral_sn, borrower, order_sn, amount, .. } = multisig.branches[0].collateral;
+        let order = &ctx.accounts.order;
+        if !order.data_is_empty() {
+            let order: Account<Order> = Account::try_from(order)?;
+            require!(order.available() + multisig.own_reservation() < amount, MyError::OrderCanFillBorrow);
+        }
+        multisig.status = MultisigStatus::Failed;
+
+        emit!(EventBorrowFailed {
+            chainid,
+            collateral_sn,
+            borrower,
+            order_sn,
+            amount,
+        });
+        Ok(())
+    }
+
+    // Witnesses that split across branches can leave a round without consensus forever. Once it has
+    // been open for `multisig_timeout`, anyone can reject it so the source chain unfreezes the
+    // collateral; the round can then be cleared like a finished one.
+    pub fn expire_collateral(ctx: Context<ExpireCollateral>, chainid: u32, collateral_sn: u64) -> Result<()> {
+        let multisig = &mut ctx.accounts.multisig;
+        let now = ctx.accounts.clock.unix_timestamp as u64;
+        // a witness removal may have undone the consensus, which borrow can then never use
+        if multisig.status == MultisigStatus::ReachConsensus {
+            multisig.evaluate(&ctx.accounts.witnesses);
+        }
+        require!(multisig.status == MultisigStatus::InProgress, MyError::MultisigNotInProgress);
+        require!(now >= multisig.start + ctx.accounts.config.multisig_timeout, MyError::MultisigNotExpired);
+        multisig.status = MultisigStatus::Expired;
+
+        emit!(EventCollateralRejected {
+            chainid,
+            collateral_sn,
+            start: multisig.start,
+            time: now,
+        });
+        Ok(())
+    }
+
     pub fn clear_collateral(ctx: Context<ClearCollateral>, _chainid: u32, _collateral_sn: u64) -> Result<()> {
         let multisig = &mut ctx.accounts.multisig;
-        require!(multisig.status == MultisigStatus::Finished, MyError::CannotClearUnfinishedMultisig);
+        require!(matches!(multisig.status, MultisigStatus::Finished | MultisigStatus::Expired | MultisigStatus::Failed), MyError::CannotClearUnfinishedMultisig);
+        multisig.witnesses.clear();
         multisig.branches.clear();
         Ok(())
     }
 
+    pub fn enable_auto_reinvest(ctx: Context<EnableAutoReinvest>, order_sn: u64) -> Result<()> {
+        let order = &ctx.accounts.order;
+        let reinvest = &mut ctx.accounts.reinvest;
+        require!(order.lender == *ctx.accounts.payer.key, MyError::NoOrderFound);
+        reinvest.order_sn = order_sn;
+
+        emit!(EventAutoReinvest {
+            lender: order.lender,
+            mint: order.mint,
+            order_sn,
+        });
+        Ok(())
+    }
+
+    pub fn disable_auto_reinvest(ctx: Context<DisableAutoReinvest>, mint: Pubkey) -> Result<()> {
+        emit!(EventAutoReinvest {
+            lender: ctx.accounts.payer.key(),
+            mint,
+            order_sn: 0,
+        });
+        Ok(())
+    }
+
     pub fn repay(ctx: Context<Repay>, receipt_sn: u64) -> Result<()> {
+        let remaining = scheduled_total(&ctx.accounts.receipt) - ctx.accounts.receipt.repaid;
+        process_repay(ctx, receipt_sn, remaining)
+    }
+
+    pub fn repay_partial(ctx: Context<Repay>, receipt_sn: u64, amount: u64) -> Result<()> {
+        process_repay(ctx, receipt_sn, amount)
+    }
+
+    #[access_control(not_paused(&ctx.accounts.global, PAUSE_EXTEND_LOAN))]
+    pub fn extend_loan(ctx: Context<ExtendLoan>, receipt_sn: u64, rate: u64) -> Result<()> {
         let receipt = &mut ctx.accounts.receipt;
         let config = &ctx.accounts.config;
         let ccfee = &ctx.accounts.cc_fee;
         let lender_balance = &mut ctx.accounts.lender_balance;
         require!(receipt.borrower == *ctx.accounts.payer.key, MyError::NoReceiptFound);
-        
-        let mut amount = receipt.amount + (receipt.amount * receipt.rate as u64) / 10000;
-        let now = ctx.accounts.clock.unix_timestamp as u64;
-        if now > receipt.time + config.cycle {
-            let mut overdue_days = (now - receipt.time - config.cycle) / 86400 + 1;
-            if overdue_days > config.penalty_days {
-                overdue_days = config.penalty_days;
-            }
-            amount += (receipt.amount * config.penalty_ir) / 1000 * overdue_days;
-        }
-        let commission = (amount - receipt.amount) * config.commission_rate / 100;
+        require!(receipt.mint == Pubkey::default(), MyError::MintMismatch);
+        let lender_signed = matches!(&ctx.accounts.lender, Some(l) if l.key() == receipt.lender);
+
+        let (amount, commission) = extension(receipt, config, ctx.accounts.clock.unix_timestamp as u64, rate, lender_signed)?;
         lender_balance.amount += amount - commission;
-        
+
         invoke(
             &system_instruction::transfer(ctx.accounts.payer.key, ctx.accounts.lender_balance.to_account_info().key, amount + ccfee.fee),
             &[
@@ -259,38 +566,32 @@ pub mod solana_lending {
             ],
         )?;
         **ctx.accounts.lender_balance.to_account_info().try_borrow_mut_lamports()? -= commission + ccfee.fee;
-        **ctx.accounts.admin.try_borrow_mut_lamports()? += commission + ccfee.fee;
-        
-        emit!(EventRepaySuccess {
-            receipt_sn: receipt_sn,
-            borrower: receipt.borrower,
-            lender: receipt.lender,
-            amount: amount,
-            income: amount - commission,
-            chainid: receipt.chainid,
-            c_sn: receipt.c_sn,
-            source: receipt.source,
-            token: receipt.token,
-            frozen: receipt.frozen,
-        });
+        **ctx.accounts.treasury.to_account_info().try_borrow_mut_lamports()? += commission;
+        ctx.accounts.treasury.commission += commission;
+        **ctx.accounts.reward_pool.to_account_info().try_borrow_mut_lamports()? += ccfee.fee;
+        credit_rewards(&mut ctx.accounts.reward_pool, &mut ctx.accounts.treasury, ctx.remaining_accounts, receipt_sn, &ctx.accounts.receipt.signers, ccfee.fee)?;
+
+        emit_extension(&ctx.accounts.receipt, receipt_sn, amount, commission);
         Ok(())
     }
 
     pub fn liquidate(ctx: Context<Liquidate>, receipt_sn: u64, receiver: [u8; 20]) -> Result<()> {
         let receipt = &mut ctx.accounts.receipt;
-        let config = &ctx.accounts.config;
         let ccfee = &ctx.accounts.cc_fee;   
         require!(receipt.lender == *ctx.accounts.payer.key, MyError::NoReceiptFound);
-        require!(ctx.accounts.clock.unix_timestamp as u64 > receipt.time + config.deadline, MyError::DeadlineNotMeet);
+        require!(ctx.accounts.clock.unix_timestamp as u64 > next_due(receipt) + receipt.grace, MyError::DeadlineNotMeet);
 
         invoke(
-            &system_instruction::transfer(ctx.accounts.payer.key, ctx.accounts.admin.to_account_info().key, ccfee.fee),
+            &system_instruction::transfer(ctx.accounts.payer.key, ctx.accounts.reward_pool.to_account_info().key, ccfee.fee),
             &[
                 ctx.accounts.payer.to_account_info(),
-                ctx.accounts.admin.to_account_info(),
+                ctx.accounts.reward_pool.to_account_info(),
                 ctx.accounts.system_program.to_account_info(),
             ],
         )?;
+        credit_rewards(&mut ctx.accounts.reward_pool, &mut ctx.accounts.treasury, ctx.remaining_accounts, receipt_sn, &receipt.signers, ccfee.fee)?;
+        ctx.accounts.treasury.rent += receipt.to_account_info().lamports();
+
         emit!(EventLiquidate {
             receipt_sn: receipt_sn,
             borrower: receipt.borrower,
@@ -298,143 +599,1849 @@ pub mod solana_lending {
             chainid: receipt.chainid,
             c_sn: receipt.c_sn,
             source: receipt.source,
-            frozen: receipt.frozen,
+            frozen: receipt.frozen - receipt.released,
             receiver: receiver,
         });
         Ok(())
     }
 
-    #[access_control(only_admin(&ctx.accounts.payer))]
-    pub fn set_ccfee(ctx: Context<SetCCFee>, _chainid: u32, fee: u64) -> Result<()> {
+    #[access_control(has_role(&ctx.accounts.payer, &ctx.accounts.roles, Role::FeeManager))]
+    pub fn queue_ccfee(ctx: Context<QueueCCFee>, chainid: u32, fee: u64) -> Result<()> {
+        require!(fee <= MAX_CCFEE, MyError::IllegalCCFee);
+        let eta = ctx.accounts.clock.unix_timestamp as u64 + CONFIG_DELAY;
+        let pending = &mut ctx.accounts.pending_ccfee;
+        pending.fee = fee;
+        pending.eta = eta;
+
+        emit!(EventCCFeeQueued {
+            chainid,
+            fee,
+            eta,
+        });
+        Ok(())
+    }
+
+    #[access_control(has_role(&ctx.accounts.payer, &ctx.accounts.roles, Role::FeeManager))]
+    pub fn apply_ccfee(ctx: Context<ApplyCCFee>, chainid: u32) -> Result<()> {
+        let pending = &ctx.accounts.pending_ccfee;
+        require!(ctx.accounts.clock.unix_timestamp as u64 >= pending.eta, MyError::ChangeStillLocked);
         let ccfee = &mut ctx.accounts.cc_fee;
-        ccfee.fee = fee;
+        let old_fee = ccfee.fee;
+        ccfee.fee = pending.fee;
+
+        emit!(EventCCFeeChanged {
+            chainid,
+            old_fee,
+            new_fee: ccfee.fee,
+        });
         Ok(())
     }
 
-    #[access_control(only_admin(&ctx.accounts.payer))]
-    pub fn add_witness(ctx: Context<UpdateWitnesses>, witness: Pubkey) -> Result<()> {
+    #[access_control(has_role(&ctx.accounts.payer, &ctx.accounts.roles, Role::WitnessManager))]
+    pub fn init_witnesses(ctx: Context<UpdateWitnesses>, v: Vec<Pubkey>) -> Result<()> {
         let witnesses = &mut ctx.accounts.witnesses;
-        if let Some(_) = witnesses.v.iter().find(|&&w| w == witness) {
-            return Err(MyError::WitnessAlreadyExist.into());   
+        require!(witnesses.v.is_empty(), MyError::WitnessesAlreadyInitialized);
+        for witness in v {
+            if witnesses.v.contains(&witness) {
+                return Err(MyError::WitnessAlreadyExist.into());
+            }
+            witnesses.v.push(witness);
         }
-        witnesses.v.push(witness);
         witnesses.multisig_threshold = (witnesses.v.len() * 2).div_ceil(3);
         Ok(())
     }
 
-    #[access_control(only_admin(&ctx.accounts.payer))]
-    pub fn remove_witness(ctx: Context<UpdateWitnesses>, witness: Pubkey) -> Result<()> {
+    #[access_control(is_witness(&ctx.accounts.payer, &ctx.accounts.witnesses))]
+    pub fn propose_witness_change(ctx: Context<ProposeWitnessChange>, action: WitnessAction, witness: Pubkey) -> Result<()> {
+        let proposal = &mut ctx.accounts.proposal;
         let witnesses = &mut ctx.accounts.witnesses;
-        witnesses.v.retain(|&w| w != witness);
-        witnesses.multisig_threshold = (witnesses.v.len() * 2).div_ceil(3);
+        let global = &mut ctx.accounts.global;
+        let min_stake = min_stake(&ctx.accounts.stake_config)?;
+        check_witness_change(action, &witness, witnesses, ctx.accounts.stake.as_deref(), min_stake)?;
+
+        proposal.sn = global.curr_proposal_sn;
+        proposal.action = action;
+        proposal.witness = witness;
+        proposal.executed = false;
+        proposal.signers = vec![ctx.accounts.payer.key()];
+        global.curr_proposal_sn += 1;
+
+        emit!(EventProposeWitnessChange {
+            proposal_sn: proposal.sn,
+            proposer: ctx.accounts.payer.key(),
+            action,
+            witness,
+        });
+
+        execute_witness_change(proposal, witnesses, ctx.accounts.stake.as_mut(), min_stake, &mut ctx.accounts.treasury)
+    }
+
+    #[access_control(is_witness(&ctx.accounts.payer, &ctx.accounts.witnesses))]
+    pub fn approve_witness_change(ctx: Context<ApproveWitnessChange>, proposal_sn: u64) -> Result<()> {
+        let proposal = &mut ctx.accounts.proposal;
+        let witnesses = &mut ctx.accounts.witnesses;
+        let payer = &ctx.accounts.payer;
+        require!(!proposal.executed, MyError::ProposalAlreadyExecuted);
+        if proposal.signers.contains(payer.key) {
+            return Err(MyError::AlreadySigned.into());
+        }
+        proposal.signers.push(payer.key());
+
+        emit!(EventApproveWitnessChange {
+            proposal_sn,
+            witness: payer.key(),
+            approvals: proposal.signers.len() as u64,
+        });
+
+        let min_stake = min_stake(&ctx.accounts.stake_config)?;
+        execute_witness_change(proposal, witnesses, ctx.accounts.stake.as_mut(), min_stake, &mut ctx.accounts.treasury)
+    }
+
+    pub fn claim_reward(ctx: Context<ClaimReward>) -> Result<()> {
+        let reward = &mut ctx.accounts.reward;
+        let payer = &ctx.accounts.payer;
+        let amount = reward.amount;
+        require!(amount > 0, MyError::NoRewardToClaim);
+        reward.amount = 0;
+
+        emit!(EventClaimReward {
+            witness: payer.key(),
+            amount,
+        });
+
+        **ctx.accounts.reward_pool.to_account_info().try_borrow_mut_lamports()? -= amount;
+        **ctx.accounts.payer.to_account_info().try_borrow_mut_lamports()? += amount;
         Ok(())
     }
-}
 
-pub fn only_admin<'info>(payer: &Signer<'info>) -> Result<()> {
-    if payer.key.to_string() != ADMIN {
-        return Err(MyError::NoOperationPermission.into());
+    #[access_control(only_admin(&ctx.accounts.payer, &ctx.accounts.global))]
+    pub fn set_treasury_threshold(ctx: Context<SetTreasuryThreshold>, threshold: u8) -> Result<()> {
+        require!(threshold > 0, MyError::IllegalThreshold);
+        ctx.accounts.treasury.threshold = threshold;
+        Ok(())
     }
-    Ok(())
-}
 
-pub fn is_witness<'info>(payer: &Signer<'info>, witnesses: &Account<'info, Witnesses>) -> Result<()> {
-    witnesses.v.iter().find(|&&w| w == payer.key()).ok_or(MyError::NoOperationPermission)?;
-    Ok(())
-}
+    #[access_control(has_role(&ctx.accounts.payer, &ctx.accounts.roles, Role::TreasuryWithdrawer))]
+    pub fn propose_treasury_withdrawal(
+        ctx: Context<ProposeTreasuryWithdrawal>,
+        category: TreasuryCategory,
+        mint: Pubkey,
+        amount: u64,
+        receiver: Pubkey,
+    ) -> Result<()> {
+        require!(mint == Pubkey::default() || category == TreasuryCategory::Commission, MyError::IllegalTreasuryCategory);
+        let treasury = &mut ctx.accounts.treasury;
+        let withdrawal = &mut ctx.accounts.withdrawal;
+        withdrawal.sn = treasury.curr_withdrawal_sn;
+        withdrawal.category = category;
+        withdrawal.mint = mint;
+        withdrawal.amount = amount;
+        withdrawal.receiver = receiver;
+        withdrawal.signers = vec![ctx.accounts.payer.key()];
+        treasury.curr_withdrawal_sn += 1;
 
-#[derive(Accounts)]
-pub struct Deposit<'info> {
-    #[account(init_if_needed, payer = payer, space = 8 + 8, seeds = [PREFIX_BALANCE.as_bytes(), payer.key().as_ref()], bump)]
-    pub user_balance: Account<'info, UserBalance>,
-    #[account(mut)]
-    pub payer: Signer<'info>,
-    pub system_program: Program<'info, System>,
-}
+        emit!(EventProposeTreasuryWithdrawal {
+            withdrawal_sn: withdrawal.sn,
+            category,
+            mint,
+            amount,
+            receiver,
+            proposer: ctx.accounts.payer.key(),
+        });
+        Ok(())
+    }
 
-#[derive(Accounts)]
-pub struct Withdraw<'info> {
-    #[account(mut, seeds = [PREFIX_BALANCE.as_bytes(), payer.key().as_ref()], bump)]
-    pub user_balance: Account<'info, UserBalance>,
-    #[account(mut)]
-    pub payer: Signer<'info>,
-    pub system_program: Program<'info, System>,
-}
+    #[access_control(has_role(&ctx.accounts.payer, &ctx.accounts.roles, Role::TreasuryWithdrawer))]
+    pub fn approve_treasury_withdrawal(ctx: Context<ApproveTreasuryWithdrawal>, withdrawal_sn: u64) -> Result<()> {
+        let withdrawal = &mut ctx.accounts.withdrawal;
+        let payer = &ctx.accounts.payer;
+        require!(!withdrawal.executed, MyError::ProposalAlreadyExecuted);
+        if withdrawal.signers.contains(payer.key) {
+            return Err(MyError::AlreadySigned.into());
+        }
+        withdrawal.signers.push(payer.key());
 
-#[derive(Accounts)]
-pub struct PlaceOrder<'info> {
-    #[account(init, payer = payer, space = 8 + 56, seeds = [PREFIX_ORDER.as_bytes(), global.curr_order_sn.to_le_bytes().as_ref()], bump)]
-    pub order: Account<'info, Order>,
-    #[account(mut, seeds = [PREFIX_BALANCE.as_bytes(), payer.key().as_ref()], bump)]
-    pub user_balance: Account<'info, UserBalance>,
-    #[account(mut, seeds = [PREFIX_STATE.as_bytes()], bump)]
-    pub global: Account<'info, GlobalState>,
-    #[account(seeds = [PREFIX_CONFIG.as_bytes()], bump)]
-    pub config: Account<'info, Config>,
-    #[account(mut)]
-    pub payer: Signer<'info>,
-    pub system_program: Program<'info, System>,
-}
+        emit!(EventApproveTreasuryWithdrawal {
+            withdrawal_sn,
+            signer: payer.key(),
+            approvals: withdrawal.signers.len() as u64,
+        });
+        Ok(())
+    }
 
-#[derive(Accounts)]
-#[instruction(order_sn: u64)]
-pub struct CancelOrder<'info> {
-    #[account(mut, close = payer, seeds = [PREFIX_ORDER.as_bytes(), order_sn.to_le_bytes().as_ref()], bump)]
-    pub order: Account<'info, Order>,
-    #[account(mut, seeds = [PREFIX_BALANCE.as_bytes(), payer.key().as_ref()], bump)]
-    pub user_balance: Account<'info, UserBalance>,
-    #[account(mut)]
-    pub payer: Signer<'info>,
-    pub system_program: Program<'info, System>,
-}
+    pub fn execute_treasury_withdrawal(ctx: Context<ExecuteTreasuryWithdrawal>, withdrawal_sn: u64) -> Result<()> {
+        let withdrawal = &mut ctx.accounts.withdrawal;
+        let treasury = &mut ctx.accounts.treasury;
+        require!(withdrawal.mint == Pubkey::default(), MyError::MintMismatch);
+        check_withdrawal(withdrawal, treasury, &ctx.accounts.roles)?;
+        let balance = treasury.balance_mut(withdrawal.category);
+        require!(*balance >= withdrawal.amount, MyError::InsufficientTreasuryBalance);
+        *balance -= withdrawal.amount;
+        withdrawal.executed = true;
 
-#[derive(Accounts)]
-#[instruction(order_sn: u64)]
-pub struct CloseOrder<'info> {
-    #[account(mut, close = lender, seeds = [PREFIX_ORDER.as_bytes(), order_sn.to_le_bytes().as_ref()], bump)]
-    pub order: Account<'info, Order>,
-    /// CHECK:
-    #[account(mut, constraint = *lender.key == order.lender)]
-    pub lender: AccountInfo<'info>,
-    #[account(mut)]
-    pub payer: Signer<'info>,
-    pub system_program: Program<'info, System>,
-}
+        **ctx.accounts.treasury.to_account_info().try_borrow_mut_lamports()? -= withdrawal.amount;
+        **ctx.accounts.receiver.try_borrow_mut_lamports()? += withdrawal.amount;
+        emit_withdrawal(withdrawal, withdrawal_sn);
+        Ok(())
+    }
 
-#[derive(Accounts)]
-#[instruction(chainid: u32, collateral_sn: u64)]
-pub struct Borrow<'info> {
+    pub fn execute_token_treasury_withdrawal(ctx: Context<ExecuteTokenTreasuryWithdrawal>, withdrawal_sn: u64) -> Result<()> {
+        let withdrawal = &mut ctx.accounts.withdrawal;
+        let treasury_balance = &mut ctx.accounts.treasury_balance;
+        require!(withdrawal.mint != Pubkey::default(), MyError::MintMismatch);
+        check_withdrawal(withdrawal, &ctx.accounts.treasury, &ctx.accounts.roles)?;
+        require!(treasury_balance.amount >= withdrawal.amount, MyError::InsufficientTreasuryBalance);
+        treasury_balance.amount -= withdrawal.amount;
+        withdrawal.executed = true;
+
+        let bump = *ctx.bumps.get("vault").unwrap();
+        transfer_from_vault(
+            &ctx.accounts.token_program,
+            &ctx.accounts.vault,
+            ctx.accounts.receiver.to_account_info(),
+            bump,
+            withdrawal.amount,
+        )?;
+        emit_withdrawal(withdrawal, withdrawal_sn);
+        Ok(())
+    }
+
+    #[access_control(has_role(&ctx.accounts.payer, &ctx.accounts.roles, Role::ConfigManager))]
+    pub fn set_stake_config(ctx: Context<SetStakeConfig>, min_stake: u64, unbonding_period: u64) -> Result<()> {
+        let stake_config = &mut ctx.accounts.stake_config;
+        stake_config.min_stake = min_stake;
+        stake_config.unbonding_period = unbonding_period;
+        Ok(())
+    }
+
+    pub fn deposit_stake(ctx: Context<DepositStake>, amount: u64) -> Result<()> {
+        let stake = &mut ctx.accounts.stake;
+        stake.witness = ctx.accounts.payer.key();
+        stake.amount += amount;
+
+        emit!(EventDepositStake {
+            witness: stake.witness,
+            amount,
+            total: stake.amount,
+        });
+
+        invoke(
+            &system_instruction::transfer(ctx.accounts.payer.key, ctx.accounts.stake.to_account_info().key, amount),
+            &[
+                ctx.accounts.payer.to_account_info(),
+                ctx.accounts.stake.to_account_info(),
+                ctx.accounts.system_program.to_account_info(),
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn unbond_stake(ctx: Context<UnbondStake>) -> Result<()> {
+        let stake = &mut ctx.accounts.stake;
+        require!(!ctx.accounts.witnesses.v.contains(&stake.witness), MyError::WitnessStillActive);
+        stake.unbonding_at = ctx.accounts.clock.unix_timestamp as u64 + ctx.accounts.stake_config.unbonding_period;
+
+        emit!(EventUnbondStake {
+            witness: stake.witness,
+            amount: stake.amount,
+            unlock_time: stake.unbonding_at,
+        });
+        Ok(())
+    }
+
+    pub fn withdraw_stake(ctx: Context<WithdrawStake>, amount: u64) -> Result<()> {
+        let stake = &mut ctx.accounts.stake;
+        let now = ctx.accounts.clock.unix_timestamp as u64;
+        require!(!ctx.accounts.witnesses.v.contains(&stake.witness), MyError::WitnessStillActive);
+        require!(stake.unbonding_at != 0 && now >= stake.unbonding_at, MyError::StakeStillBonded);
+        require!(stake.amount >= amount, MyError::InsufficientStake);
+        stake.amount -= amount;
+
+        emit!(EventWithdrawStake {
+            witness: stake.witness,
+            amount,
+        });
+
+        **ctx.accounts.stake.to_account_info().try_borrow_mut_lamports()? -= amount;
+        **ctx.accounts.payer.to_account_info().try_borrow_mut_lamports()? += amount;
+        Ok(())
+    }
+
+    #[access_control(is_witness(&ctx.accounts.payer, &ctx.accounts.witnesses))]
+    pub fn submit_price(ctx: Context<SubmitPrice>, chainid: u32, token: [u8; 20], price: u64) -> Result<()> {
+        let feed = &mut ctx.accounts.price_feed;
+        let witnesses = &ctx.accounts.witnesses;
+        let payer = &ctx.accounts.payer;
+        let now = ctx.accounts.clock.unix_timestamp as u64;
+        require!(price > 0, MyError::IllegalPrice);
+
+        feed.chainid = chainid;
+        feed.token = token;
+        feed.submissions.retain(|s| witnesses.v.contains(&s.witness));
+        match feed.submissions.iter_mut().find(|s| s.witness == *payer.key) {
+            Some(submission) => {
+                submission.price = price;
+                submission.time = now;
+            }
+            None => {
+                feed.submissions.push(PriceSubmission {
+                    witness: payer.key(),
+                    price,
+                    time: now,
+                });
+            }
+        }
+        let mut prices: Vec<u64> = feed.submissions.iter().map(|s| s.price).collect();
+        feed.price = median(&mut prices);
+        feed.time = now;
+
+        emit!(EventPriceSubmitted {
+            chainid,
+            token,
+            witness: payer.key(),
+            price,
+            median: feed.price,
+            time: now,
+        });
+        Ok(())
+    }
+
+    pub fn get_price(ctx: Context<GetPrice>, _chainid: u32, _token: [u8; 20], max_age: u64) -> Result<u64> {
+        let now = ctx.accounts.clock.unix_timestamp as u64;
+        ctx.accounts.price_feed.median_price(&ctx.accounts.witnesses, now, max_age)
+    }
+
+    pub fn deposit_token(ctx: Context<DepositToken>, amount: u64) -> Result<()> {
+        let user_balance = &mut ctx.accounts.user_balance;
+        user_balance.amount += amount;
+        token::transfer(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.payer_token.to_account_info(),
+                    to: ctx.accounts.vault.to_account_info(),
+                    authority: ctx.accounts.payer.to_account_info(),
+                },
+            ),
+            amount,
+        )?;
+        Ok(())
+    }
+
+    pub fn withdraw_token(ctx: Context<WithdrawToken>, amount: u64) -> Result<()> {
+        let user_balance = &mut ctx.accounts.user_balance;
+        require!(user_balance.amount >= amount, MyError::InsufficientUserBalance);
+        user_balance.amount -= amount;
+        transfer_from_vault(
+            &ctx.accounts.token_program,
+            &ctx.accounts.vault,
+            ctx.accounts.recipient.to_account_info(),
+            *ctx.bumps.get("vault").unwrap(),
+            amount,
+        )
+    }
+
+    #[access_control(not_paused(&ctx.accounts.global, PAUSE_PLACE_ORDER))]
+    pub fn place_token_order(ctx: Context<PlaceTokenOrder>, amount: u64, rate: u64, term: u64, installments: u8) -> Result<()> {
+        let order = &mut ctx.accounts.order;
+        let user_balance = &mut ctx.accounts.user_balance;
+        let global = &mut ctx.accounts.global;
+        let config = &ctx.accounts.config;
+
+        require!(user_balance.amount >= amount, MyError::InsufficientUserBalance);
+        require!(rate >= config.min_ir && rate <= config.max_ir, MyError::IllegalInterestRate);
+        require!(term >= config.min_term && term <= config.max_term, MyError::IllegalLoanTerm);
+        require!((1..=MAX_INSTALLMENTS).contains(&installments), MyError::IllegalInstallments);
+        order.sn = global.curr_order_sn;
+        order.lender = ctx.accounts.payer.key();
+        order.balance = amount;
+        order.rate = rate;
+        order.term = term;
+        order.installments = installments;
+        order.mint = ctx.accounts.mint.key();
+        user_balance.amount -= amount;
+        global.curr_order_sn += 1;
+
+        emit!(EventPlaceOrder {
+            order_sn: order.sn,
+            lender: ctx.accounts.payer.key(),
+            balance: amount,
+            rate,
+            mint: order.mint,
+            term,
+            installments,
+        });
+        Ok(())
+    }
+
+    pub fn cancel_token_order(ctx: Context<CancelTokenOrder>, order_sn: u64) -> Result<()> {
+        let order = &mut ctx.accounts.order;
+        let user_balance = &mut ctx.accounts.user_balance;
+        require!(order.lender == *ctx.accounts.payer.key, MyError::NoOrderFound);
+        let returned = order.available();
+        user_balance.amount += returned;
+        order.balance = order.reserved;
+
+        emit!(EventCancelOrder {
+            order_sn,
+            lender: ctx.accounts.payer.key(),
+            balance: returned,
+            reserved: order.reserved,
+        });
+        if ctx.accounts.order.reserved == 0 {
+            ctx.accounts.order.close(ctx.accounts.payer.to_account_info())?;
+        }
+        Ok(())
+    }
+
+    #[access_control(not_paused(&ctx.accounts.global, PAUSE_BORROW))]
+    pub fn borrow_token(ctx: Context<BorrowToken>, _chainid: u32, _collateral_sn: u64) -> Result<()> {
+        let multisig = &mut ctx.accounts.multisig;
+        let order = &mut ctx.accounts.order;
+        let receipt = &mut ctx.accounts.receipt;
+        let global = &mut ctx.accounts.global;
+
+        require!(multisig.status == MultisigStatus::ReachConsensus, MyError::CannotBorrowWithoutConsensus);
+        let Collateral { chainid, collateral_sn: c_sn, source, token, frozen, borrower, order_sn, amount } = multisig.branches[0].collateral;
+        let signers = multisig.valid_signers(&multisig.branches[0].signers, &ctx.accounts.witnesses);
+        require!(signers.len() as u64 >= multisig.threshold, MyError::ConsensusLost);
+        let own = multisig.own_reservation();
+        require!(order.available() + own >= amount, MyError::InsufficientOrderBalance);
+        multisig.status = MultisigStatus::Finished;
+        multisig.reservation.amount -= own;
+        order.reserved -= own;
+
+        receipt.sn = global.curr_receipt_sn;
+        receipt.borrower = borrower;
+        receipt.lender = order.lender;
+        receipt.source = source;
+        receipt.chainid = chainid;
+        receipt.c_sn = c_sn;
+        receipt.token = token;
+        receipt.frozen = frozen;
+        receipt.amount = amount;
+        receipt.time = ctx.accounts.clock.unix_timestamp as u64;
+        receipt.rate = order.rate;
+        receipt.mint = order.mint;
+        receipt.term = order.term;
+        receipt.grace = ctx.accounts.config.grace;
+        receipt.installments = order.installments;
+        receipt.signers = signers;
+        global.curr_receipt_sn += 1;
+        order.balance -= amount;
+
+        emit!(EventBorrowSuccess {
+            receipt_sn: receipt.sn,
+            borrower,
+            lender: receipt.lender,
+            source,
+            chainid,
+            c_sn,
+            token,
+            frozen,
+            amount,
+            time: receipt.time,
+            rate: receipt.rate,
+            order_sn,
+            order_balance: order.balance,
+            mint: receipt.mint,
+            term: receipt.term,
+            installments: receipt.installments,
+            grace: receipt.grace,
+        });
+
+        transfer_from_vault(
+            &ctx.accounts.token_program,
+            &ctx.accounts.vault,
+            ctx.accounts.recipient.to_account_info(),
+            *ctx.bumps.get("vault").unwrap(),
+            amount,
+        )
+    }
+
+    pub fn repay_token(ctx: Context<RepayToken>, receipt_sn: u64) -> Result<()> {
+        let remaining = scheduled_total(&ctx.accounts.receipt) - ctx.accounts.receipt.repaid;
+        process_repay_token(ctx, receipt_sn, remaining)
+    }
+
+    pub fn repay_token_partial(ctx: Context<RepayToken>, receipt_sn: u64, amount: u64) -> Result<()> {
+        process_repay_token(ctx, receipt_sn, amount)
+    }
+
+    #[access_control(not_paused(&ctx.accounts.global, PAUSE_EXTEND_LOAN))]
+    pub fn extend_token_loan(ctx: Context<ExtendTokenLoan>, receipt_sn: u64, rate: u64) -> Result<()> {
+        let receipt = &mut ctx.accounts.receipt;
+        let config = &ctx.accounts.config;
+        let ccfee = &ctx.accounts.cc_fee;
+        let lender_balance = &mut ctx.accounts.lender_balance;
+        require!(receipt.borrower == *ctx.accounts.payer.key, MyError::NoReceiptFound);
+        let lender_signed = matches!(&ctx.accounts.lender, Some(l) if l.key() == receipt.lender);
+
+        let (amount, commission) = extension(receipt, config, ctx.accounts.clock.unix_timestamp as u64, rate, lender_signed)?;
+        lender_balance.amount += amount - commission;
+        ctx.accounts.treasury_balance.amount += commission;
+
+        token::transfer(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.payer_token.to_account_info(),
+                    to: ctx.accounts.vault.to_account_info(),
+                    authority: ctx.accounts.payer.to_account_info(),
+                },
+            ),
+            amount,
+        )?;
+        invoke(
+            &system_instruction::transfer(ctx.accounts.payer.key, ctx.accounts.reward_pool.to_account_info().key, ccfee.fee),
+            &[
+                ctx.accounts.payer.to_account_info(),
+                ctx.accounts.reward_pool.to_account_info(),
+                ctx.accounts.system_program.to_account_info(),
+            ],
+        )?;
+        credit_rewards(&mut ctx.accounts.reward_pool, &mut ctx.accounts.treasury, ctx.remaining_accounts, receipt_sn, &ctx.accounts.receipt.signers, ccfee.fee)?;
+
+        emit_extension(&ctx.accounts.receipt, receipt_sn, amount, commission);
+        Ok(())
+    }
+
+    pub fn deposit_collateral(ctx: Context<DepositCollateral>, amount: u64) -> Result<()> {
+        let user_collateral = &mut ctx.accounts.user_collateral;
+        user_collateral.amount += amount;
+        invoke(
+            &system_instruction::transfer(ctx.accounts.payer.key, ctx.accounts.user_collateral.to_account_info().key, amount),
+            &[
+                ctx.accounts.payer.to_account_info(),
+                ctx.accounts.user_collateral.to_account_info(),
+                ctx.accounts.system_program.to_account_info(),
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn withdraw_collateral(ctx: Context<WithdrawCollateral>, amount: u64) -> Result<()> {
+        let user_collateral = &mut ctx.accounts.user_collateral;
+        require!(user_collateral.amount >= amount, MyError::InsufficientUserBalance);
+        user_collateral.amount -= amount;
+        **ctx.accounts.user_collateral.to_account_info().try_borrow_mut_lamports()? -= amount;
+        **ctx.accounts.payer.to_account_info().try_borrow_mut_lamports()? += amount;
+        Ok(())
+    }
+
+    pub fn deposit_token_collateral(ctx: Context<DepositTokenCollateral>, amount: u64) -> Result<()> {
+        let user_collateral = &mut ctx.accounts.user_collateral;
+        user_collateral.amount += amount;
+        token::transfer(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.payer_token.to_account_info(),
+                    to: ctx.accounts.vault.to_account_info(),
+                    authority: ctx.accounts.payer.to_account_info(),
+                },
+            ),
+            amount,
+        )?;
+        Ok(())
+    }
+
+    pub fn withdraw_token_collateral(ctx: Context<WithdrawTokenCollateral>, amount: u64) -> Result<()> {
+        let user_collateral = &mut ctx.accounts.user_collateral;
+        require!(user_collateral.amount >= amount, MyError::InsufficientUserBalance);
+        user_collateral.amount -= amount;
+        transfer_from_vault(
+            &ctx.accounts.token_program,
+            &ctx.accounts.vault,
+            ctx.accounts.recipient.to_account_info(),
+            *ctx.bumps.get("vault").unwrap(),
+            amount,
+        )
+    }
+
+    // Frozen collateral stays in the owner's collateral account (or the mint vault) and only
+    // the free amount is reduced, so settlement moves it from there.
+    #[access_control(not_paused(&ctx.accounts.global, PAUSE_FREEZE_COLLATERAL))]
+    pub fn freeze_collateral(ctx: Context<FreezeCollateral>, mint: Pubkey, chainid: u32, order_sn: u64, frozen: u64, amount: u64, receiver: [u8; 20]) -> Result<()> {
+        let user_collateral = &mut ctx.accounts.user_collateral;
+        let freeze = &mut ctx.accounts.freeze;
+        let global = &mut ctx.accounts.global;
+        require!(user_collateral.amount >= frozen, MyError::InsufficientUserBalance);
+
+        freeze.sn = global.curr_freeze_sn;
+        freeze.owner = ctx.accounts.payer.key();
+        freeze.mint = mint;
+        freeze.frozen = frozen;
+        freeze.chainid = chainid;
+        freeze.order_sn = order_sn;
+        freeze.amount = amount;
+        freeze.receiver = receiver;
+        freeze.status = FreezeStatus::Frozen;
+        user_collateral.amount -= frozen;
+        global.curr_freeze_sn += 1;
+
+        emit!(EventFreezeCollateral {
+            freeze_sn: freeze.sn,
+            owner: freeze.owner,
+            mint,
+            frozen,
+            chainid,
+            order_sn,
+            amount,
+            receiver,
+        });
+        Ok(())
+    }
+
+    #[access_control(is_witness(&ctx.accounts.payer, &ctx.accounts.witnesses) not_paused(&ctx.accounts.global, PAUSE_MULTISIG_RELEASE))]
+    pub fn multisig_release(ctx: Context<MultisigRelease>, freeze_sn: u64, action: ReleaseAction, lender: Pubkey) -> Result<()> {
+        let multisig = &mut ctx.accounts.multisig;
+        let witnesses = &ctx.accounts.witnesses;
+        let payer = &ctx.accounts.payer;
+        let release = Release {
+            freeze_sn,
+            action,
+            lender,
+        };
+        match multisig.status {
+            // The collateral of an expired round is still frozen, so the next signature starts over.
+            MultisigStatus::Starting | MultisigStatus::Expired => {
+                multisig.branches = vec![ReleaseWithSigners {
+                    release,
+                    signers: vec![payer.key()],
+                }];
+                multisig.status = MultisigStatus::InProgress;
+                multisig.start = ctx.accounts.clock.unix_timestamp as u64;
+                multisig.threshold = witnesses.multisig_threshold as u64;
+                multisig.witnesses = witnesses.v.clone();
+                multisig.evaluate(witnesses);
+            }
+            MultisigStatus::InProgress => {
+                require!(multisig.witnesses.contains(payer.key), MyError::WitnessNotInRound);
+                if multisig.branches.iter().any(|b| b.signers.contains(payer.key)) {
+                    return Err(MyError::AlreadySigned.into());
+                }
+                match multisig.branches.iter_mut().find(|b| b.release == release) {
+                    Some(branch) => branch.signers.push(payer.key()),
+                    None => {
+                        multisig.branches.push(ReleaseWithSigners {
+                            release,
+                            signers: vec![payer.key()],
+                        });
+                    }
+                }
+                multisig.evaluate(witnesses);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    #[access_control(is_witness(&ctx.accounts.payer, &ctx.accounts.witnesses))]
+    pub fn revoke_release_signature(ctx: Context<RevokeReleaseSignature>, freeze_sn: u64) -> Result<()> {
+        let multisig = &mut ctx.accounts.multisig;
+        let payer = ctx.accounts.payer.key();
+        require!(multisig.status == MultisigStatus::InProgress, MyError::MultisigNotInProgress);
+        let branch = multisig.branches.iter_mut().find(|b| b.signers.contains(&payer)).ok_or(MyError::NotSigned)?;
+        branch.signers.retain(|s| *s != payer);
+
+        emit!(EventRevokeReleaseSignature {
+            freeze_sn,
+            witness: payer,
+            release: branch.release,
+            signers: branch.signers.len() as u64,
+        });
+        multisig.branches.retain(|b| !b.signers.is_empty());
+        Ok(())
+    }
+
+    // Like collateral rounds, a release round without consensus can be expired after
+    // `multisig_timeout`; witnesses then sign it again from scratch.
+    pub fn expire_release(ctx: Context<ExpireRelease>, freeze_sn: u64) -> Result<()> {
+        let multisig = &mut ctx.accounts.multisig;
+        let now = ctx.accounts.clock.unix_timestamp as u64;
+        // a witness removal may have undone the consensus, which the settlement can then never use
+        if multisig.status == MultisigStatus::ReachConsensus {
+            multisig.evaluate(&ctx.accounts.witnesses);
+        }
+        require!(multisig.status == MultisigStatus::InProgress, MyError::MultisigNotInProgress);
+        require!(now >= multisig.start + ctx.accounts.config.multisig_timeout, MyError::MultisigNotExpired);
+        multisig.status = MultisigStatus::Expired;
+
+        emit!(EventReleaseExpired {
+            freeze_sn,
+            start: multisig.start,
+            time: now,
+        });
+        Ok(())
+    }
+
+    pub fn unfreeze_collateral(ctx: Context<UnfreezeCollateral>, freeze_sn: u64) -> Result<()> {
+        let multisig = &mut ctx.accounts.multisig;
+        let freeze = &mut ctx.accounts.freeze;
+        require!(multisig.status == MultisigStatus::ReachConsensus, MyError::CannotSettleWithoutConsensus);
+        require!(multisig.valid_signers(&multisig.branches[0].signers, &ctx.accounts.witnesses).len() as u64 >= multisig.threshold, MyError::ConsensusLost);
+        require!(multisig.branches[0].release.action == ReleaseAction::Unfreeze, MyError::ReleaseActionMismatch);
+        require!(freeze.status == FreezeStatus::Frozen, MyError::CollateralNotFrozen);
+        multisig.status = MultisigStatus::Finished;
+        freeze.status = FreezeStatus::Released;
+        ctx.accounts.owner_collateral.amount += freeze.frozen;
+
+        emit!(EventUnfreezeCollateral {
+            freeze_sn,
+            owner: freeze.owner,
+            mint: freeze.mint,
+            frozen: freeze.frozen,
+        });
+        Ok(())
+    }
+
+    pub fn liquidate_collateral(ctx: Context<LiquidateCollateral>, freeze_sn: u64) -> Result<()> {
+        let multisig = &mut ctx.accounts.multisig;
+        let freeze = &mut ctx.accounts.freeze;
+        require!(multisig.status == MultisigStatus::ReachConsensus, MyError::CannotSettleWithoutConsensus);
+        require!(multisig.valid_signers(&multisig.branches[0].signers, &ctx.accounts.witnesses).len() as u64 >= multisig.threshold, MyError::ConsensusLost);
+        let Release { action, lender, .. } = multisig.branches[0].release;
+        require!(action == ReleaseAction::Liquidate, MyError::ReleaseActionMismatch);
+        require!(freeze.status == FreezeStatus::Frozen, MyError::CollateralNotFrozen);
+        multisig.status = MultisigStatus::Finished;
+        freeze.status = FreezeStatus::Liquidated;
+
+        ctx.accounts.lender_collateral.amount += freeze.frozen;
+
+        emit!(EventLiquidateCollateral {
+            freeze_sn,
+            owner: freeze.owner,
+            lender,
+            mint: freeze.mint,
+            frozen: freeze.frozen,
+        });
+
+        // Native collateral is held by the owner's collateral account, so move the lamports along.
+        if freeze.mint == Pubkey::default() {
+            **ctx.accounts.owner_collateral.to_account_info().try_borrow_mut_lamports()? -= freeze.frozen;
+            **ctx.accounts.lender_collateral.to_account_info().try_borrow_mut_lamports()? += freeze.frozen;
+        }
+        Ok(())
+    }
+}
+
+fn process_repay(ctx: Context<Repay>, receipt_sn: u64, pay: u64) -> Result<()> {
+    let receipt = &mut ctx.accounts.receipt;
+    let config = &ctx.accounts.config;
+    let ccfee = &ctx.accounts.cc_fee;
+    let lender_balance = &mut ctx.accounts.lender_balance;
+    require!(receipt.borrower == *ctx.accounts.payer.key, MyError::NoReceiptFound);
+    require!(receipt.mint == Pubkey::default(), MyError::MintMismatch);
+
+    let Repayment { amount, commission, release } = repayment(receipt, config, ctx.accounts.clock.unix_timestamp as u64, pay)?;
+    let reinvest_order = reinvest_target(&ctx.accounts.reinvest, &ctx.accounts.reinvest_order, &receipt.lender, &receipt.mint)?;
+    if reinvest_order.is_none() {
+        lender_balance.amount += amount - commission;
+    }
+
+    invoke(
+        &system_instruction::transfer(ctx.accounts.payer.key, ctx.accounts.lender_balance.to_account_info().key, amount + ccfee.fee),
+        &[
+            ctx.accounts.payer.to_account_info(),
+            ctx.accounts.lender_balance.to_account_info(),
+            ctx.accounts.system_program.to_account_info(),
+        ],
+    )?;
+    **ctx.accounts.lender_balance.to_account_info().try_borrow_mut_lamports()? -= commission + ccfee.fee;
+    **ctx.accounts.treasury.to_account_info().try_borrow_mut_lamports()? += commission;
+    ctx.accounts.treasury.commission += commission;
+    **ctx.accounts.reward_pool.to_account_info().try_borrow_mut_lamports()? += ccfee.fee;
+    credit_rewards(&mut ctx.accounts.reward_pool, &mut ctx.accounts.treasury, ctx.remaining_accounts, receipt_sn, &ctx.accounts.receipt.signers, ccfee.fee)?;
+
+    if let Some(mut order) = reinvest_order {
+        order.balance += amount - commission;
+        **ctx.accounts.lender_balance.to_account_info().try_borrow_mut_lamports()? -= amount - commission;
+        **order.to_account_info().try_borrow_mut_lamports()? += amount - commission;
+        emit_reinvest(&order, receipt_sn, amount - commission);
+        order.exit(&crate::ID)?;
+    }
+
+    if emit_repayment(&ctx.accounts.receipt, receipt_sn, amount, commission, release) {
+        ctx.accounts.treasury.rent += ctx.accounts.receipt.to_account_info().lamports();
+        ctx.accounts.receipt.close(ctx.accounts.treasury.to_account_info())?;
+    }
+    Ok(())
+}
+
+fn process_repay_token(ctx: Context<RepayToken>, receipt_sn: u64, pay: u64) -> Result<()> {
+    let receipt = &mut ctx.accounts.receipt;
+    let config = &ctx.accounts.config;
+    let ccfee = &ctx.accounts.cc_fee;
+    let lender_balance = &mut ctx.accounts.lender_balance;
+    require!(receipt.borrower == *ctx.accounts.payer.key, MyError::NoReceiptFound);
+
+    let Repayment { amount, commission, release } = repayment(receipt, config, ctx.accounts.clock.unix_timestamp as u64, pay)?;
+    match reinvest_target(&ctx.accounts.reinvest, &ctx.accounts.reinvest_order, &receipt.lender, &receipt.mint)? {
+        Some(mut order) => {
+            order.balance += amount - commission;
+            emit_reinvest(&order, receipt_sn, amount - commission);
+            order.exit(&crate::ID)?;
+        }
+        None => lender_balance.amount += amount - commission,
+    }
+    ctx.accounts.treasury_balance.amount += commission;
+
+    token::transfer(
+        CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.payer_token.to_account_info(),
+                to: ctx.accounts.vault.to_account_info(),
+                authority: ctx.accounts.payer.to_account_info(),
+            },
+        ),
+        amount,
+    )?;
+    invoke(
+        &system_instruction::transfer(ctx.accounts.payer.key, ctx.accounts.reward_pool.to_account_info().key, ccfee.fee),
+        &[
+            ctx.accounts.payer.to_account_info(),
+            ctx.accounts.reward_pool.to_account_info(),
+            ctx.accounts.system_program.to_account_info(),
+        ],
+    )?;
+    credit_rewards(&mut ctx.accounts.reward_pool, &mut ctx.accounts.treasury, ctx.remaining_accounts, receipt_sn, &ctx.accounts.receipt.signers, ccfee.fee)?;
+
+    if emit_repayment(&ctx.accounts.receipt, receipt_sn, amount, commission, release) {
+        ctx.accounts.treasury.rent += ctx.accounts.receipt.to_account_info().lamports();
+        ctx.accounts.receipt.close(ctx.accounts.treasury.to_account_info())?;
+    }
+    Ok(())
+}
+
+// Moves a bullet loan into its next cycle. The borrower pays the interest of the current cycle
+// plus any penalty interest now; the principal is carried over at `rate`, which may only differ
+// from the current rate with the lender's signature.
+fn extension(receipt: &mut LoanReceipt, config: &Config, now: u64, rate: u64, lender_signed: bool) -> Result<(u64, u64)> {
+    require!(receipt.installments <= 1 && receipt.repaid == 0, MyError::CannotExtendLoan);
+    require!(now <= receipt.time + receipt.term + receipt.grace, MyError::CannotExtendLoan);
+    if rate != receipt.rate {
+        require!(lender_signed, MyError::LenderApprovalRequired);
+        require!(rate >= config.min_ir && rate <= config.max_ir, MyError::IllegalInterestRate);
+    }
+
+    let mut amount = (receipt.amount * receipt.rate) / 10000;
+    if now > receipt.time + receipt.term {
+        let mut overdue_days = (now - receipt.time - receipt.term) / 86400 + 1;
+        if overdue_days > config.penalty_days {
+            overdue_days = config.penalty_days;
+        }
+        amount += (receipt.amount * config.penalty_ir) / 1000 * overdue_days;
+    }
+    let commission = amount * config.commission_rate / 100;
+    receipt.time += receipt.term;
+    receipt.rate = rate;
+    Ok((amount, commission))
+}
+
+fn emit_extension(receipt: &LoanReceipt, receipt_sn: u64, amount: u64, commission: u64) {
+    emit!(EventExtendLoan {
+        receipt_sn,
+        borrower: receipt.borrower,
+        lender: receipt.lender,
+        amount,
+        income: amount - commission,
+        chainid: receipt.chainid,
+        c_sn: receipt.c_sn,
+        source: receipt.source,
+        token: receipt.token,
+        frozen: receipt.frozen,
+        rate: receipt.rate,
+        time: receipt.time,
+        expiry: receipt.time + receipt.term + receipt.grace,
+    });
+}
+
+// A treasury withdrawal needs approvals from at least `threshold` current withdrawers.
+fn check_withdrawal(withdrawal: &TreasuryWithdrawal, treasury: &Treasury, roles: &Roles) -> Result<()> {
+    require!(!withdrawal.executed, MyError::ProposalAlreadyExecuted);
+    let withdrawers = roles.members(Role::TreasuryWithdrawer);
+    let approvals = withdrawal.signers.iter().filter(|s| withdrawers.contains(s)).count();
+    require!(approvals >= treasury.threshold as usize, MyError::CannotSettleWithoutConsensus);
+    Ok(())
+}
+
+fn emit_withdrawal(withdrawal: &TreasuryWithdrawal, withdrawal_sn: u64) {
+    emit!(EventTreasuryWithdrawal {
+        withdrawal_sn,
+        category: withdrawal.category,
+        mint: withdrawal.mint,
+        amount: withdrawal.amount,
+        receiver: withdrawal.receiver,
+    });
+}
+
+// Splits the cross-chain fee evenly among the witnesses that signed the loan's collateral.
+// The remainder of the division goes to the first signer. Each signer's reward account is
+// passed in `rewards`, in signer order. Fees of loans without recorded signers are moved
+// on to the treasury.
+fn credit_rewards<'info>(
+    reward_pool: &mut Account<'info, RewardPool>,
+    treasury: &mut Account<'info, Treasury>,
+    rewards: &[AccountInfo],
+    receipt_sn: u64,
+    signers: &[Pubkey],
+    fee: u64,
+) -> Result<()> {
+    if fee == 0 {
+        return Ok(());
+    }
+    if signers.is_empty() {
+        **reward_pool.to_account_info().try_borrow_mut_lamports()? -= fee;
+        **treasury.to_account_info().try_borrow_mut_lamports()? += fee;
+        treasury.ccfee += fee;
+        return Ok(());
+    }
+    let share = fee / signers.len() as u64;
+    for (i, signer) in signers.iter().enumerate() {
+        let amount = if i == 0 { fee - share * (signers.len() as u64 - 1) } else { share };
+        let info = rewards.get(i).ok_or(MyError::RewardAccountMismatch)?;
+        require!(*info.owner == ID, MyError::RewardAccountMismatch);
+        let mut data = info.try_borrow_mut_data()?;
+        let mut reward = WitnessReward::try_deserialize(&mut &data[..])?;
+        require!(reward.witness == *signer, MyError::RewardAccountMismatch);
+        reward.amount += amount;
+        reward.try_serialize(&mut &mut data[..])?;
+    }
+
+    emit!(EventCreditRewards {
+        receipt_sn,
+        fee,
+        share,
+        signers: signers.to_vec(),
+    });
+    Ok(())
+}
+
+// The lender's designated order if auto-reinvest is enabled and the order is still open.
+// Repayments fall back to the lender's balance once the order has been cancelled.
+fn reinvest_target<'info>(reinvest: &AccountInfo<'info>, order: &AccountInfo<'info>, lender: &Pubkey, mint: &Pubkey) -> Result<Option<Account<'info, Order>>> {
+    if reinvest.data_is_empty() {
+        return Ok(None);
+    }
+    let setting = Account::<AutoReinvest>::try_from(reinvest)?;
+    let (address, _) = Pubkey::find_program_address(&[PREFIX_ORDER.as_bytes(), setting.order_sn.to_le_bytes().as_ref()], &crate::ID);
+    require!(*order.key == address, MyError::ReinvestOrderMismatch);
+    if order.data_is_empty() {
+        return Ok(None);
+    }
+    let order = Account::<Order>::try_from(order)?;
+    require!(order.lender == *lender && order.mint == *mint, MyError::ReinvestOrderMismatch);
+    Ok(Some(order))
+}
+
+fn emit_reinvest(order: &Order, receipt_sn: u64, amount: u64) {
+    emit!(EventReinvest {
+        receipt_sn,
+        order_sn: order.sn,
+        lender: order.lender,
+        amount,
+        order_balance: order.balance,
+    });
+}
+
+// Emits the repayment event and returns whether the receipt has been repaid in full.
+fn emit_repayment(receipt: &LoanReceipt, receipt_sn: u64, amount: u64, commission: u64, release: u64) -> bool {
+    let remaining = scheduled_total(receipt) - receipt.repaid;
+    if remaining == 0 {
+        emit!(EventRepaySuccess {
+            receipt_sn,
+            borrower: receipt.borrower,
+            lender: receipt.lender,
+            amount,
+            income: amount - commission,
+            chainid: receipt.chainid,
+            c_sn: receipt.c_sn,
+            source: receipt.source,
+            token: receipt.token,
+            frozen: release,
+        });
+    } else {
+        emit!(EventPartialRepay {
+            receipt_sn,
+            borrower: receipt.borrower,
+            lender: receipt.lender,
+            amount,
+            income: amount - commission,
+            repaid: receipt.repaid,
+            remaining,
+            chainid: receipt.chainid,
+            c_sn: receipt.c_sn,
+            source: receipt.source,
+            token: receipt.token,
+            released: release,
+        });
+    }
+    remaining == 0
+}
+
+struct Repayment {
+    amount: u64,
+    commission: u64,
+    release: u64,
+}
+
+// Principal plus interest over the whole term, split evenly across the installments.
+fn scheduled_total(receipt: &LoanReceipt) -> u64 {
+    receipt.amount + (receipt.amount * receipt.rate) / 10000
+}
+
+// Cumulative scheduled amount and due time of the k-th installment (1-based).
+fn installment(receipt: &LoanReceipt, k: u64) -> (u64, u64) {
+    let n = receipt.installments.max(1) as u64;
+    let total = scheduled_total(receipt) as u128;
+    let cumulative = (total * k as u128 / n as u128) as u64;
+    (cumulative, receipt.time + receipt.term * k / n)
+}
+
+// Due time of the earliest installment that is not fully repaid. The receipt can be liquidated
+// once its grace period after this has passed.
+pub fn next_due(receipt: &LoanReceipt) -> u64 {
+    let n = receipt.installments.max(1) as u64;
+    (1..=n).map(|k| installment(receipt, k))
+        .find(|&(cumulative, _)| cumulative > receipt.repaid)
+        .map_or(receipt.time + receipt.term, |(_, due)| due)
+}
+
+// The outstanding scheduled amount of a receipt and the penalty owed on it if repaid in full at
+// `now`, as `repay` would charge them (the ccfee comes on top). For off-chain quotes.
+pub fn amount_due(receipt: &LoanReceipt, config: &Config, now: u64) -> Result<(u64, u64)> {
+    let remaining = scheduled_total(receipt) - receipt.repaid;
+    let Repayment { amount, .. } = repayment(&mut receipt.clone(), config, now, remaining)?;
+    Ok((remaining, amount - remaining))
+}
+
+// Applies `pay` of the scheduled total to the installments in order. Every overdue part bears
+// penalty interest on its principal share for each overdue day, capped at penalty_days.
+// The commission is drawn from the interest and penalty, and the frozen collateral is released
+// in proportion to the scheduled total repaid.
+fn repayment(receipt: &mut LoanReceipt, config: &Config, now: u64, pay: u64) -> Result<Repayment> {
+    let total = scheduled_total(receipt);
+    require!(pay > 0 && pay <= total - receipt.repaid, MyError::IllegalRepayAmount);
+    let principal_share = |scheduled: u64| (scheduled as u128 * receipt.amount as u128 / total as u128) as u64;
+
+    let mut penalty = 0;
+    let mut paid = receipt.repaid;
+    for k in 1..=receipt.installments.max(1) as u64 {
+        let (cumulative, due) = installment(receipt, k);
+        if paid >= receipt.repaid + pay {
+            break;
+        }
+        if cumulative <= paid {
+            continue;
+        }
+        let covered = cumulative.min(receipt.repaid + pay) - paid;
+        if now > due {
+            let mut overdue_days = (now - due) / 86400 + 1;
+            if overdue_days > config.penalty_days {
+                overdue_days = config.penalty_days;
+            }
+            penalty += (principal_share(covered) * config.penalty_ir) / 1000 * overdue_days;
+        }
+        paid += covered;
+    }
+
+    let principal = principal_share(receipt.repaid + pay) - principal_share(receipt.repaid);
+    let commission = (pay - principal + penalty) * config.commission_rate / 100;
+    let released = (receipt.frozen as u128 * (receipt.repaid + pay) as u128 / total as u128) as u64;
+    let release = released - receipt.released;
+    receipt.repaid += pay;
+    receipt.released = released;
+    Ok(Repayment {
+        amount: pay + penalty,
+        commission,
+        release,
+    })
+}
+
+fn reserve(multisig: &mut CollateralWithMultisig, order: &mut Order, chainid: u32, collateral_sn: u64, borrower: Pubkey, amount: u64, expiry: u64) {
+    order.reserved += amount;
+    multisig.reservation = Reservation {
+        order_sn: order.sn,
+        borrower,
+        amount,
+        expiry,
+    };
+    emit!(EventReserveOrder {
+        chainid,
+        collateral_sn,
+        order_sn: order.sn,
+        borrower,
+        amount,
+        expiry,
+    });
+}
+
+// Signers that were witnesses when the round started and still are.
+fn valid_signers(snapshot: &[Pubkey], signers: &[Pubkey], witnesses: &Witnesses) -> Vec<Pubkey> {
+    signers.iter().filter(|s| snapshot.contains(s) && witnesses.v.contains(s)).copied().collect()
+}
+
+// The branch consensus shared by collateral and release rounds: the first branch with enough
+// valid signers wins and the others are dropped, otherwise the round stays InProgress.
+fn evaluate_branches<B>(branches: &mut Vec<B>, signers: fn(&B) -> &Vec<Pubkey>, snapshot: &[Pubkey], threshold: u64, witnesses: &Witnesses) -> MultisigStatus {
+    match branches.iter().position(|b| valid_signers(snapshot, signers(b), witnesses).len() as u64 >= threshold) {
+        Some(i) => {
+            let branch = branches.swap_remove(i);
+            *branches = vec![branch];
+            MultisigStatus::ReachConsensus
+        }
+        None => MultisigStatus::InProgress,
+    }
+}
+
+fn transfer_from_vault<'info>(
+    token_program: &Program<'info, Token>,
+    vault: &Account<'info, TokenAccount>,
+    to: AccountInfo<'info>,
+    bump: u8,
+    amount: u64,
+) -> Result<()> {
+    let mint = vault.mint;
+    let seeds: &[&[u8]] = &[PREFIX_VAULT.as_bytes(), mint.as_ref(), &[bump]];
+    token::transfer(
+        CpiContext::new_with_signer(
+            token_program.to_account_info(),
+            Transfer {
+                from: vault.to_account_info(),
+                to,
+                authority: vault.to_account_info(),
+            },
+            &[seeds],
+        ),
+        amount,
+    )
+}
+
+pub fn only_admin<'info>(payer: &Signer<'info>, global: &GlobalState) -> Result<()> {
+    if payer.key() != global.admin {
+        return Err(MyError::NoOperationPermission.into());
+    }
+    Ok(())
+}
+
+fn median(prices: &mut [u64]) -> u64 {
+    prices.sort_unstable();
+    let len = prices.len();
+    let (lo, hi) = (prices[(len - 1) / 2], prices[len / 2]);
+    ((lo as u128 + hi as u128) / 2) as u64
+}
+
+// The stake a new witness needs, which is none until staking is configured.
+fn min_stake(stake_config: &AccountInfo) -> Result<u64> {
+    if stake_config.data_is_empty() {
+        return Ok(0);
+    }
+    Ok(Account::<StakeConfig>::try_from(stake_config)?.min_stake)
+}
+
+fn check_witness_change(action: WitnessAction, witness: &Pubkey, witnesses: &Witnesses, stake: Option<&WitnessStake>, min_stake: u64) -> Result<()> {
+    let exists = witnesses.v.contains(witness);
+    let staked = stake.map_or(0, |s| s.amount);
+    match action {
+        WitnessAction::Add => {
+            require!(!exists, MyError::WitnessAlreadyExist);
+            require!(staked >= min_stake, MyError::InsufficientStake);
+        }
+        WitnessAction::Remove => {
+            require!(exists, MyError::WitnessNotExist);
+            require!(witnesses.v.len() > 1, MyError::CannotRemoveLastWitness);
+        }
+        WitnessAction::Slash { amount } => {
+            require!(amount > 0 && staked >= amount, MyError::InsufficientStake);
+            require!(!exists || witnesses.v.len() > 1, MyError::CannotRemoveLastWitness);
+        }
+    }
+    Ok(())
+}
+
+// Applies the proposal once the approvals of current witnesses reach the threshold.
+// Approvals from witnesses removed in the meantime are not counted.
+// A slashed witness also leaves the witness set and its slashed stake goes to the treasury.
+fn execute_witness_change<'info>(
+    proposal: &mut WitnessProposal,
+    witnesses: &mut Witnesses,
+    stake: Option<&mut Account<'info, WitnessStake>>,
+    min_stake: u64,
+    treasury: &mut Account<'info, Treasury>,
+) -> Result<()> {
+    let approvals = proposal.signers.iter().filter(|s| witnesses.v.contains(s)).count();
+    if approvals < witnesses.multisig_threshold {
+        return Ok(());
+    }
+    check_witness_change(proposal.action, &proposal.witness, witnesses, stake.as_deref().map(|s| &**s), min_stake)?;
+    match proposal.action {
+        WitnessAction::Add => {
+            witnesses.v.push(proposal.witness);
+            if let Some(stake) = stake {
+                stake.unbonding_at = 0;
+            }
+        }
+        WitnessAction::Remove => witnesses.v.retain(|&w| w != proposal.witness),
+        WitnessAction::Slash { amount } => {
+            witnesses.v.retain(|&w| w != proposal.witness);
+            let stake = stake.ok_or(MyError::InsufficientStake)?;
+            stake.amount -= amount;
+            **stake.to_account_info().try_borrow_mut_lamports()? -= amount;
+            **treasury.to_account_info().try_borrow_mut_lamports()? += amount;
+            treasury.slashed += amount;
+            emit!(EventSlashStake {
+                proposal_sn: proposal.sn,
+                witness: proposal.witness,
+                amount,
+                remaining: stake.amount,
+            });
+        }
+    }
+    witnesses.multisig_threshold = (witnesses.v.len() * 2).div_ceil(3);
+    proposal.executed = true;
+
+    emit!(EventWitnessChanged {
+        proposal_sn: proposal.sn,
+        action: proposal.action,
+        witness: proposal.witness,
+        multisig_threshold: witnesses.multisig_threshold as u64,
+    });
+    Ok(())
+}
+
+pub fn has_role<'info>(payer: &Signer<'info>, roles: &Roles, role: Role) -> Result<()> {
+    if !roles.members(role).contains(payer.key) {
+        return Err(MyError::NoOperationPermission.into());
+    }
+    Ok(())
+}
+
+pub fn not_paused(global: &GlobalState, flag: u64) -> Result<()> {
+    if global.paused & flag != 0 {
+        return Err(MyError::InstructionPaused.into());
+    }
+    Ok(())
+}
+
+pub fn is_witness<'info>(payer: &Signer<'info>, witnesses: &Account<'info, Witnesses>) -> Result<()> {
+    witnesses.v.iter().find(|&&w| w == payer.key()).ok_or(MyError::NoOperationPermission)?;
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct Deposit<'info> {
+    #[account(init_if_needed, payer = payer, space = 8 + 8, seeds = [PREFIX_BALANCE.as_bytes(), payer.key().as_ref()], bump)]
+    pub user_balance: Account<'info, UserBalance>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct Withdraw<'info> {
+    #[account(mut, seeds = [PREFIX_BALANCE.as_bytes(), payer.key().as_ref()], bump)]
+    pub user_balance: Account<'info, UserBalance>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct PlaceOrder<'info> {
+    #[account(init, payer = payer, space = 8 + 105, seeds = [PREFIX_ORDER.as_bytes(), global.curr_order_sn.to_le_bytes().as_ref()], bump)]
+    pub order: Account<'info, Order>,
+    #[account(mut, seeds = [PREFIX_BALANCE.as_bytes(), payer.key().as_ref()], bump)]
+    pub user_balance: Account<'info, UserBalance>,
+    #[account(mut, seeds = [PREFIX_STATE.as_bytes()], bump)]
+    pub global: Account<'info, GlobalState>,
+    #[account(seeds = [PREFIX_CONFIG.as_bytes()], bump)]
+    pub config: Account<'info, Config>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(order_sn: u64)]
+pub struct CancelOrder<'info> {
+    #[account(mut, seeds = [PREFIX_ORDER.as_bytes(), order_sn.to_le_bytes().as_ref()], bump)]
+    pub order: Account<'info, Order>,
+    #[account(mut, seeds = [PREFIX_BALANCE.as_bytes(), payer.key().as_ref()], bump)]
+    pub user_balance: Account<'info, UserBalance>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(order_sn: u64)]
+pub struct CloseOrder<'info> {
+    #[account(mut, close = lender, seeds = [PREFIX_ORDER.as_bytes(), order_sn.to_le_bytes().as_ref()], bump)]
+    pub order: Account<'info, Order>,
+    /// CHECK:
+    #[account(mut, constraint = *lender.key == order.lender)]
+    pub lender: AccountInfo<'info>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(chainid: u32, collateral_sn: u64)]
+pub struct Borrow<'info> {
+    #[account(mut, seeds = [PREFIX_COLLATERAL.as_bytes(), chainid.to_le_bytes().as_ref(), collateral_sn.to_le_bytes().as_ref()], bump)]
+    pub multisig: Box<Account<'info, CollateralWithMultisig>>,
+    #[account(seeds = [PREFIX_WITNESSES.as_bytes()], bump)]
+    pub witnesses: Box<Account<'info, Witnesses>>,
+    #[account(init, payer = payer, space = 8 + 225 + 32 * multisig.branches[0].signers.len(), seeds = [PREFIX_RECEIPT.as_bytes(), global.curr_receipt_sn.to_le_bytes().as_ref()], bump)]
+    pub receipt: Account<'info, LoanReceipt>,
+    #[account(mut, seeds = [PREFIX_ORDER.as_bytes(), multisig.branches[0].collateral.order_sn.to_le_bytes().as_ref()], bump)]
+    pub order: Account<'info, Order>,
+    #[account(mut, seeds = [PREFIX_STATE.as_bytes()], bump)]
+    pub global: Account<'info, GlobalState>,
+    #[account(seeds = [PREFIX_CONFIG.as_bytes()], bump)]
+    pub config: Account<'info, Config>,
+    // a loan is only made on a chain it can be repaid from
+    #[account(seeds = [PREFIX_CCFEE.as_bytes(), chainid.to_le_bytes().as_ref()], bump)]
+    pub cc_fee: Account<'info, CCFee>,
+    /// CHECK: 
+    #[account(mut, constraint = *recipient.key == multisig.branches[0].collateral.borrower)]
+    pub recipient: AccountInfo<'info>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+    pub clock: Sysvar<'info, Clock>,
+}
+
+#[derive(Accounts)]
+#[instruction(order_sn: u64)]
+pub struct EnableAutoReinvest<'info> {
+    #[account(seeds = [PREFIX_ORDER.as_bytes(), order_sn.to_le_bytes().as_ref()], bump)]
+    pub order: Account<'info, Order>,
+    #[account(init_if_needed, payer = payer, space = 8 + 8, seeds = [PREFIX_REINVEST.as_bytes(), payer.key().as_ref(), order.mint.as_ref()], bump)]
+    pub reinvest: Account<'info, AutoReinvest>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(mint: Pubkey)]
+pub struct DisableAutoReinvest<'info> {
+    #[account(mut, close = payer, seeds = [PREFIX_REINVEST.as_bytes(), payer.key().as_ref(), mint.as_ref()], bump)]
+    pub reinvest: Account<'info, AutoReinvest>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(receipt_sn: u64)]
+pub struct Repay<'info> {
+    #[account(mut, seeds = [PREFIX_RECEIPT.as_bytes(), receipt_sn.to_le_bytes().as_ref()], bump)]
+    pub receipt: Account<'info, LoanReceipt>,
+    #[account(mut, seeds = [PREFIX_BALANCE.as_bytes(), receipt.lender.as_ref()], bump)]
+    pub lender_balance: Account<'info, UserBalance>,
+    /// CHECK: the lender's auto-reinvest setting, which may not exist
+    #[account(seeds = [PREFIX_REINVEST.as_bytes(), receipt.lender.as_ref(), receipt.mint.as_ref()], bump)]
+    pub reinvest: UncheckedAccount<'info>,
+    /// CHECK: checked against the auto-reinvest setting
+    #[account(mut)]
+    pub reinvest_order: UncheckedAccount<'info>,
+    #[account(seeds = [PREFIX_CONFIG.as_bytes()], bump)]
+    pub config: Account<'info, Config>,
+    #[account(mut, seeds = [PREFIX_TREASURY.as_bytes()], bump)]
+    pub treasury: Box<Account<'info, Treasury>>,
+    #[account(seeds = [PREFIX_CCFEE.as_bytes(), receipt.chainid.to_le_bytes().as_ref()], bump)]
+    pub cc_fee: Account<'info, CCFee>,
+    #[account(mut, seeds = [PREFIX_REWARD.as_bytes()], bump)]
+    pub reward_pool: Box<Account<'info, RewardPool>>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+    pub clock: Sysvar<'info, Clock>,
+}
+
+#[derive(Accounts)]
+#[instruction(receipt_sn: u64)]
+pub struct ExtendLoan<'info> {
+    #[account(mut, seeds = [PREFIX_RECEIPT.as_bytes(), receipt_sn.to_le_bytes().as_ref()], bump)]
+    pub receipt: Box<Account<'info, LoanReceipt>>,
+    #[account(mut, seeds = [PREFIX_BALANCE.as_bytes(), receipt.lender.as_ref()], bump)]
+    pub lender_balance: Box<Account<'info, UserBalance>>,
+    #[account(seeds = [PREFIX_CONFIG.as_bytes()], bump)]
+    pub config: Box<Account<'info, Config>>,
+    #[account(seeds = [PREFIX_STATE.as_bytes()], bump)]
+    pub global: Box<Account<'info, GlobalState>>,
+    #[account(mut, seeds = [PREFIX_TREASURY.as_bytes()], bump)]
+    pub treasury: Box<Account<'info, Treasury>>,
+    #[account(seeds = [PREFIX_CCFEE.as_bytes(), receipt.chainid.to_le_bytes().as_ref()], bump)]
+    pub cc_fee: Box<Account<'info, CCFee>>,
+    #[account(mut, seeds = [PREFIX_REWARD.as_bytes()], bump)]
+    pub reward_pool: Box<Account<'info, RewardPool>>,
+    pub lender: Option<Signer<'info>>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+    pub clock: Sysvar<'info, Clock>,
+}
+
+#[derive(Accounts)]
+#[instruction(receipt_sn: u64)]
+pub struct Liquidate<'info> {
+    #[account(mut, close = treasury, seeds = [PREFIX_RECEIPT.as_bytes(), receipt_sn.to_le_bytes().as_ref()], bump)]
+    pub receipt: Account<'info, LoanReceipt>,
+    #[account(mut, seeds = [PREFIX_TREASURY.as_bytes()], bump)]
+    pub treasury: Box<Account<'info, Treasury>>,
+    #[account(seeds = [PREFIX_CCFEE.as_bytes(), receipt.chainid.to_le_bytes().as_ref()], bump)]
+    pub cc_fee: Account<'info, CCFee>,
+    #[account(mut, seeds = [PREFIX_REWARD.as_bytes()], bump)]
+    pub reward_pool: Box<Account<'info, RewardPool>>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+    pub clock: Sysvar<'info, Clock>,
+}
+
+#[derive(Accounts)]
+pub struct Initialize<'info> {
+    #[account(init, payer = payer, space = 8 + 128, seeds = [PREFIX_STATE.as_bytes()], bump)]
+    pub global_state: Account<'info, GlobalState>,
+    #[account(init, payer = payer, space = 8, seeds = [PREFIX_REWARD.as_bytes()], bump)]
+    pub reward_pool: Box<Account<'info, RewardPool>>,
+    #[account(init, payer = payer, space = 8 + 64, seeds = [PREFIX_TREASURY.as_bytes()], bump)]
+    pub treasury: Box<Account<'info, Treasury>>,
+    // only the program's upgrade authority may initialize, so the deployment cannot be front-run
+    #[account(
+        seeds = [ID.as_ref()],
+        bump,
+        seeds::program = bpf_loader_upgradeable::ID,
+        constraint = program_data.upgrade_authority_address == Some(payer.key()) @ MyError::NoOperationPermission
+    )]
+    pub program_data: Account<'info, ProgramData>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateAdmin<'info> {
+    #[account(mut, seeds = [PREFIX_STATE.as_bytes()], bump)]
+    pub global: Account<'info, GlobalState>,
+    pub payer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetPause<'info> {
+    #[account(mut, seeds = [PREFIX_STATE.as_bytes()], bump)]
+    pub global: Account<'info, GlobalState>,
+    #[account(seeds = [PREFIX_ROLES.as_bytes()], bump)]
+    pub roles: Box<Account<'info, Roles>>,
+    pub payer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateRoles<'info> {
+    #[account(init_if_needed, payer = payer, space = 8 + 1024, seeds = [PREFIX_ROLES.as_bytes()], bump)]
+    pub roles: Box<Account<'info, Roles>>,
+    #[account(seeds = [PREFIX_STATE.as_bytes()], bump)]
+    pub global: Box<Account<'info, GlobalState>>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct QueueConfig<'info> {
+    #[account(init_if_needed, payer = payer, space = 8 + 80, seeds = [PREFIX_PENDING.as_bytes(), PREFIX_CONFIG.as_bytes()], bump)]
+    pub pending_config: Account<'info, PendingConfig>,
+    #[account(init_if_needed, payer = payer, space = 8 + 73, seeds = [PREFIX_CONFIG.as_bytes()], bump)]
+    pub config: Account<'info, Config>,
+    #[account(seeds = [PREFIX_ROLES.as_bytes()], bump)]
+    pub roles: Box<Account<'info, Roles>>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+    pub clock: Sysvar<'info, Clock>,
+}
+
+#[derive(Accounts)]
+pub struct ApplyConfig<'info> {
+    #[account(mut, close = payer, seeds = [PREFIX_PENDING.as_bytes(), PREFIX_CONFIG.as_bytes()], bump)]
+    pub pending_config: Account<'info, PendingConfig>,
+    #[account(mut, seeds = [PREFIX_CONFIG.as_bytes()], bump)]
+    pub config: Account<'info, Config>,
+    #[account(seeds = [PREFIX_ROLES.as_bytes()], bump)]
+    pub roles: Box<Account<'info, Roles>>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub clock: Sysvar<'info, Clock>,
+}
+
+#[derive(Accounts)]
+#[instruction(chainid: u32)]
+pub struct QueueCCFee<'info> {
+    #[account(init_if_needed, payer = payer, space = 8 + 16, seeds = [PREFIX_PENDING.as_bytes(), PREFIX_CCFEE.as_bytes(), chainid.to_le_bytes().as_ref()], bump)]
+    pub pending_ccfee: Account<'info, PendingCCFee>,
+    #[account(seeds = [PREFIX_ROLES.as_bytes()], bump)]
+    pub roles: Box<Account<'info, Roles>>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+    pub clock: Sysvar<'info, Clock>,
+}
+
+#[derive(Accounts)]
+#[instruction(chainid: u32)]
+pub struct ApplyCCFee<'info> {
+    #[account(mut, close = payer, seeds = [PREFIX_PENDING.as_bytes(), PREFIX_CCFEE.as_bytes(), chainid.to_le_bytes().as_ref()], bump)]
+    pub pending_ccfee: Account<'info, PendingCCFee>,
+    #[account(init_if_needed, payer = payer, space = 8 + 8, seeds = [PREFIX_CCFEE.as_bytes(), chainid.to_le_bytes().as_ref()], bump)]
+    pub cc_fee: Account<'info, CCFee>,
+    #[account(seeds = [PREFIX_ROLES.as_bytes()], bump)]
+    pub roles: Box<Account<'info, Roles>>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+    pub clock: Sysvar<'info, Clock>,
+}
+
+#[derive(Accounts)]
+#[instruction(chainid: u32, collateral_sn: u64, source: [u8; 20], token: [u8; 20], frozen: u64, order_sn: u64)]
+pub struct MultisigCollateral<'info> {
+    #[account(init_if_needed, payer = payer, space = 8 + 2048, seeds = [PREFIX_COLLATERAL.as_bytes(), chainid.to_le_bytes().as_ref(), collateral_sn.to_le_bytes().as_ref()], bump)]
+    pub multisig: Box<Account<'info, CollateralWithMultisig>>,
+    /// CHECK: may have been closed
+    #[account(mut, seeds = [PREFIX_ORDER.as_bytes(), order_sn.to_le_bytes().as_ref()], bump)]
+    pub order: AccountInfo<'info>,
+    #[account(seeds = [PREFIX_CONFIG.as_bytes()], bump)]
+    pub config: Box<Account<'info, Config>>,
+    #[account(seeds = [PREFIX_WITNESSES.as_bytes()], bump)]
+    pub witnesses: Account<'info, Witnesses>,
+    #[account(seeds = [PREFIX_STATE.as_bytes()], bump)]
+    pub global: Box<Account<'info, GlobalState>>,
+    #[account(init_if_needed, payer = payer, space = 8 + 40, seeds = [PREFIX_REWARD.as_bytes(), payer.key().as_ref()], bump)]
+    pub reward: Box<Account<'info, WitnessReward>>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+    pub clock: Sysvar<'info, Clock>,
+}
+
+#[derive(Accounts)]
+#[instruction(chainid: u32, collateral_sn: u64)]
+pub struct RecountCollateral<'info> {
     #[account(mut, seeds = [PREFIX_COLLATERAL.as_bytes(), chainid.to_le_bytes().as_ref(), collateral_sn.to_le_bytes().as_ref()], bump)]
     pub multisig: Box<Account<'info, CollateralWithMultisig>>,
-    #[account(init, payer = payer, space = 8 + 156, seeds = [PREFIX_RECEIPT.as_bytes(), global.curr_receipt_sn.to_le_bytes().as_ref()], bump)]
-    pub receipt: Account<'info, LoanReceipt>,
-    #[account(mut, seeds = [PREFIX_ORDER.as_bytes(), multisig.branches[0].collateral.order_sn.to_le_bytes().as_ref()], bump)]
+    #[account(seeds = [PREFIX_WITNESSES.as_bytes()], bump)]
+    pub witnesses: Account<'info, Witnesses>,
+    pub payer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(chainid: u32, collateral_sn: u64)]
+pub struct RevokeCollateralSignature<'info> {
+    #[account(mut, seeds = [PREFIX_COLLATERAL.as_bytes(), chainid.to_le_bytes().as_ref(), collateral_sn.to_le_bytes().as_ref()], bump)]
+    pub multisig: Box<Account<'info, CollateralWithMultisig>>,
+    #[account(seeds = [PREFIX_WITNESSES.as_bytes()], bump)]
+    pub witnesses: Account<'info, Witnesses>,
+    pub payer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(chainid: u32, collateral_sn: u64, order_sn: u64)]
+pub struct ReserveOrder<'info> {
+    #[account(mut, seeds = [PREFIX_COLLATERAL.as_bytes(), chainid.to_le_bytes().as_ref(), collateral_sn.to_le_bytes().as_ref()], bump)]
+    pub multisig: Box<Account<'info, CollateralWithMultisig>>,
+    #[account(mut, seeds = [PREFIX_ORDER.as_bytes(), order_sn.to_le_bytes().as_ref()], bump)]
+    pub order: Account<'info, Order>,
+    #[account(seeds = [PREFIX_CONFIG.as_bytes()], bump)]
+    pub config: Account<'info, Config>,
+    pub payer: Signer<'info>,
+    pub clock: Sysvar<'info, Clock>,
+}
+
+#[derive(Accounts)]
+#[instruction(chainid: u32, collateral_sn: u64)]
+pub struct ReleaseReservation<'info> {
+    #[account(mut, seeds = [PREFIX_COLLATERAL.as_bytes(), chainid.to_le_bytes().as_ref(), collateral_sn.to_le_bytes().as_ref()], bump)]
+    pub multisig: Box<Account<'info, CollateralWithMultisig>>,
+    #[account(mut, seeds = [PREFIX_ORDER.as_bytes(), multisig.reservation.order_sn.to_le_bytes().as_ref()], bump)]
+    pub order: Account<'info, Order>,
+    pub payer: Signer<'info>,
+    pub clock: Sysvar<'info, Clock>,
+}
+
+#[derive(Accounts)]
+#[instruction(chainid: u32, collateral_sn: u64)]
+pub struct FailBorrow<'info> {
+    #[account(mut, seeds = [PREFIX_COLLATERAL.as_bytes(), chainid.to_le_bytes().as_ref(), collateral_sn.to_le_bytes().as_ref()], bump)]
+    pub multisig: Box<Account<'info, CollateralWithMultisig>>,
+    /// CHECK: may have been closed
+    #[account(seeds = [PREFIX_ORDER.as_bytes(), multisig.branches[0].collateral.order_sn.to_le_bytes().as_ref()], bump)]
+    pub order: AccountInfo<'info>,
+    pub payer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(chainid: u32, collateral_sn: u64)]
+pub struct ExpireCollateral<'info> {
+    #[account(mut, seeds = [PREFIX_COLLATERAL.as_bytes(), chainid.to_le_bytes().as_ref(), collateral_sn.to_le_bytes().as_ref()], bump)]
+    pub multisig: Box<Account<'info, CollateralWithMultisig>>,
+    #[account(seeds = [PREFIX_CONFIG.as_bytes()], bump)]
+    pub config: Account<'info, Config>,
+    #[account(seeds = [PREFIX_WITNESSES.as_bytes()], bump)]
+    pub witnesses: Account<'info, Witnesses>,
+    pub payer: Signer<'info>,
+    pub clock: Sysvar<'info, Clock>,
+}
+
+#[derive(Accounts)]
+#[instruction(chainid: u32, collateral_sn: u64)]
+pub struct ClearCollateral<'info> {
+    #[account(mut, seeds = [PREFIX_COLLATERAL.as_bytes(), chainid.to_le_bytes().as_ref(), collateral_sn.to_le_bytes().as_ref()], bump, 
+        realloc = 8 + 81, realloc::payer = payer, realloc::zero = false)]
+    pub multisig: Box<Account<'info, CollateralWithMultisig>>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateWitnesses<'info> {
+    #[account(init_if_needed, payer = payer, space = 8 + 1024, seeds = [PREFIX_WITNESSES.as_bytes()], bump)]
+    pub witnesses: Box<Account<'info, Witnesses>>,
+    #[account(seeds = [PREFIX_ROLES.as_bytes()], bump)]
+    pub roles: Box<Account<'info, Roles>>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct DepositToken<'info> {
+    #[account(init_if_needed, payer = payer, space = 8 + 8, seeds = [PREFIX_BALANCE.as_bytes(), payer.key().as_ref(), mint.key().as_ref()], bump)]
+    pub user_balance: Account<'info, UserBalance>,
+    #[account(init_if_needed, payer = payer, token::mint = mint, token::authority = vault, seeds = [PREFIX_VAULT.as_bytes(), mint.key().as_ref()], bump)]
+    pub vault: Account<'info, TokenAccount>,
+    #[account(mut, token::mint = mint, token::authority = payer)]
+    pub payer_token: Account<'info, TokenAccount>,
+    pub mint: Account<'info, Mint>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawToken<'info> {
+    #[account(mut, seeds = [PREFIX_BALANCE.as_bytes(), payer.key().as_ref(), mint.key().as_ref()], bump)]
+    pub user_balance: Account<'info, UserBalance>,
+    #[account(mut, seeds = [PREFIX_VAULT.as_bytes(), mint.key().as_ref()], bump)]
+    pub vault: Account<'info, TokenAccount>,
+    #[account(mut, token::mint = mint)]
+    pub recipient: Account<'info, TokenAccount>,
+    pub mint: Account<'info, Mint>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct PlaceTokenOrder<'info> {
+    #[account(init, payer = payer, space = 8 + 105, seeds = [PREFIX_ORDER.as_bytes(), global.curr_order_sn.to_le_bytes().as_ref()], bump)]
     pub order: Account<'info, Order>,
+    #[account(mut, seeds = [PREFIX_BALANCE.as_bytes(), payer.key().as_ref(), mint.key().as_ref()], bump)]
+    pub user_balance: Account<'info, UserBalance>,
+    pub mint: Account<'info, Mint>,
     #[account(mut, seeds = [PREFIX_STATE.as_bytes()], bump)]
     pub global: Account<'info, GlobalState>,
-    /// CHECK: 
-    #[account(mut, constraint = *recipient.key == multisig.branches[0].collateral.borrower)]
-    pub recipient: AccountInfo<'info>,
+    #[account(seeds = [PREFIX_CONFIG.as_bytes()], bump)]
+    pub config: Account<'info, Config>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(order_sn: u64)]
+pub struct CancelTokenOrder<'info> {
+    #[account(mut, seeds = [PREFIX_ORDER.as_bytes(), order_sn.to_le_bytes().as_ref()], bump)]
+    pub order: Account<'info, Order>,
+    #[account(mut, seeds = [PREFIX_BALANCE.as_bytes(), payer.key().as_ref(), order.mint.as_ref()], bump)]
+    pub user_balance: Account<'info, UserBalance>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(chainid: u32, collateral_sn: u64)]
+pub struct BorrowToken<'info> {
+    #[account(mut, seeds = [PREFIX_COLLATERAL.as_bytes(), chainid.to_le_bytes().as_ref(), collateral_sn.to_le_bytes().as_ref()], bump)]
+    pub multisig: Box<Account<'info, CollateralWithMultisig>>,
+    #[account(seeds = [PREFIX_WITNESSES.as_bytes()], bump)]
+    pub witnesses: Box<Account<'info, Witnesses>>,
+    #[account(init, payer = payer, space = 8 + 225 + 32 * multisig.branches[0].signers.len(), seeds = [PREFIX_RECEIPT.as_bytes(), global.curr_receipt_sn.to_le_bytes().as_ref()], bump)]
+    pub receipt: Box<Account<'info, LoanReceipt>>,
+    #[account(mut, seeds = [PREFIX_ORDER.as_bytes(), multisig.branches[0].collateral.order_sn.to_le_bytes().as_ref()], bump)]
+    pub order: Box<Account<'info, Order>>,
+    #[account(mut, seeds = [PREFIX_VAULT.as_bytes(), order.mint.as_ref()], bump)]
+    pub vault: Box<Account<'info, TokenAccount>>,
+    #[account(mut, seeds = [PREFIX_STATE.as_bytes()], bump)]
+    pub global: Account<'info, GlobalState>,
+    #[account(seeds = [PREFIX_CONFIG.as_bytes()], bump)]
+    pub config: Box<Account<'info, Config>>,
+    #[account(seeds = [PREFIX_CCFEE.as_bytes(), chainid.to_le_bytes().as_ref()], bump)]
+    pub cc_fee: Box<Account<'info, CCFee>>,
+    #[account(mut, token::mint = order.mint, token::authority = multisig.branches[0].collateral.borrower)]
+    pub recipient: Box<Account<'info, TokenAccount>>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub clock: Sysvar<'info, Clock>,
+}
+
+#[derive(Accounts)]
+#[instruction(receipt_sn: u64)]
+pub struct RepayToken<'info> {
+    #[account(mut, seeds = [PREFIX_RECEIPT.as_bytes(), receipt_sn.to_le_bytes().as_ref()], bump)]
+    pub receipt: Box<Account<'info, LoanReceipt>>,
+    #[account(mut, seeds = [PREFIX_BALANCE.as_bytes(), receipt.lender.as_ref(), receipt.mint.as_ref()], bump)]
+    pub lender_balance: Box<Account<'info, UserBalance>>,
+    #[account(mut, seeds = [PREFIX_VAULT.as_bytes(), receipt.mint.as_ref()], bump)]
+    pub vault: Box<Account<'info, TokenAccount>>,
+    /// CHECK: the lender's auto-reinvest setting, which may not exist
+    #[account(seeds = [PREFIX_REINVEST.as_bytes(), receipt.lender.as_ref(), receipt.mint.as_ref()], bump)]
+    pub reinvest: UncheckedAccount<'info>,
+    /// CHECK: checked against the auto-reinvest setting
+    #[account(mut)]
+    pub reinvest_order: UncheckedAccount<'info>,
+    #[account(mut, token::mint = receipt.mint, token::authority = payer)]
+    pub payer_token: Box<Account<'info, TokenAccount>>,
+    #[account(seeds = [PREFIX_CONFIG.as_bytes()], bump)]
+    pub config: Box<Account<'info, Config>>,
+    #[account(mut, seeds = [PREFIX_TREASURY.as_bytes()], bump)]
+    pub treasury: Box<Account<'info, Treasury>>,
+    #[account(init_if_needed, payer = payer, space = 8 + 8, seeds = [PREFIX_BALANCE.as_bytes(), treasury.key().as_ref(), receipt.mint.as_ref()], bump)]
+    pub treasury_balance: Box<Account<'info, UserBalance>>,
+    #[account(seeds = [PREFIX_CCFEE.as_bytes(), receipt.chainid.to_le_bytes().as_ref()], bump)]
+    pub cc_fee: Box<Account<'info, CCFee>>,
+    #[account(mut, seeds = [PREFIX_REWARD.as_bytes()], bump)]
+    pub reward_pool: Box<Account<'info, RewardPool>>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub clock: Sysvar<'info, Clock>,
+}
+
+#[derive(Accounts)]
+#[instruction(receipt_sn: u64)]
+pub struct ExtendTokenLoan<'info> {
+    #[account(mut, seeds = [PREFIX_RECEIPT.as_bytes(), receipt_sn.to_le_bytes().as_ref()], bump)]
+    pub receipt: Box<Account<'info, LoanReceipt>>,
+    #[account(mut, seeds = [PREFIX_BALANCE.as_bytes(), receipt.lender.as_ref(), receipt.mint.as_ref()], bump)]
+    pub lender_balance: Box<Account<'info, UserBalance>>,
+    #[account(mut, seeds = [PREFIX_VAULT.as_bytes(), receipt.mint.as_ref()], bump)]
+    pub vault: Box<Account<'info, TokenAccount>>,
+    #[account(mut, token::mint = receipt.mint, token::authority = payer)]
+    pub payer_token: Box<Account<'info, TokenAccount>>,
+    #[account(seeds = [PREFIX_STATE.as_bytes()], bump)]
+    pub global: Box<Account<'info, GlobalState>>,
+    #[account(seeds = [PREFIX_CONFIG.as_bytes()], bump)]
+    pub config: Box<Account<'info, Config>>,
+    #[account(mut, seeds = [PREFIX_TREASURY.as_bytes()], bump)]
+    pub treasury: Box<Account<'info, Treasury>>,
+    #[account(init_if_needed, payer = payer, space = 8 + 8, seeds = [PREFIX_BALANCE.as_bytes(), treasury.key().as_ref(), receipt.mint.as_ref()], bump)]
+    pub treasury_balance: Box<Account<'info, UserBalance>>,
+    #[account(seeds = [PREFIX_CCFEE.as_bytes(), receipt.chainid.to_le_bytes().as_ref()], bump)]
+    pub cc_fee: Box<Account<'info, CCFee>>,
+    #[account(mut, seeds = [PREFIX_REWARD.as_bytes()], bump)]
+    pub reward_pool: Box<Account<'info, RewardPool>>,
+    pub lender: Option<Signer<'info>>,
     #[account(mut)]
     pub payer: Signer<'info>,
+    pub token_program: Program<'info, Token>,
     pub system_program: Program<'info, System>,
     pub clock: Sysvar<'info, Clock>,
 }
 
 #[derive(Accounts)]
-#[instruction(receipt_sn: u64)]
-pub struct Repay<'info> {
-    #[account(mut, close = admin, seeds = [PREFIX_RECEIPT.as_bytes(), receipt_sn.to_le_bytes().as_ref()], bump)]
-    pub receipt: Account<'info, LoanReceipt>,
+pub struct DepositCollateral<'info> {
+    #[account(init_if_needed, payer = payer, space = 8 + 8, seeds = [PREFIX_MORTGAGE.as_bytes(), payer.key().as_ref(), Pubkey::default().as_ref()], bump)]
+    pub user_collateral: Account<'info, UserBalance>,
     #[account(mut)]
-    pub lender_balance: Account<'info, UserBalance>,
-    #[account(seeds = [PREFIX_CONFIG.as_bytes()], bump)]
-    pub config: Account<'info, Config>,
-    /// CHECK:
-    #[account(mut, constraint = admin.key.to_string() == ADMIN)]
-    pub admin: AccountInfo<'info>,
-    #[account(seeds = [PREFIX_CCFEE.as_bytes(), receipt.chainid.to_le_bytes().as_ref()], bump)]
-    pub cc_fee: Account<'info, CCFee>,
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawCollateral<'info> {
+    #[account(mut, seeds = [PREFIX_MORTGAGE.as_bytes(), payer.key().as_ref(), Pubkey::default().as_ref()], bump)]
+    pub user_collateral: Account<'info, UserBalance>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct DepositTokenCollateral<'info> {
+    #[account(init_if_needed, payer = payer, space = 8 + 8, seeds = [PREFIX_MORTGAGE.as_bytes(), payer.key().as_ref(), mint.key().as_ref()], bump)]
+    pub user_collateral: Account<'info, UserBalance>,
+    #[account(init_if_needed, payer = payer, token::mint = mint, token::authority = vault, seeds = [PREFIX_VAULT.as_bytes(), mint.key().as_ref()], bump)]
+    pub vault: Account<'info, TokenAccount>,
+    #[account(mut, token::mint = mint, token::authority = payer)]
+    pub payer_token: Account<'info, TokenAccount>,
+    pub mint: Account<'info, Mint>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawTokenCollateral<'info> {
+    #[account(mut, seeds = [PREFIX_MORTGAGE.as_bytes(), payer.key().as_ref(), mint.key().as_ref()], bump)]
+    pub user_collateral: Account<'info, UserBalance>,
+    #[account(mut, seeds = [PREFIX_VAULT.as_bytes(), mint.key().as_ref()], bump)]
+    pub vault: Account<'info, TokenAccount>,
+    #[account(mut, token::mint = mint)]
+    pub recipient: Account<'info, TokenAccount>,
+    pub mint: Account<'info, Mint>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+#[instruction(mint: Pubkey)]
+pub struct FreezeCollateral<'info> {
+    #[account(mut, seeds = [PREFIX_MORTGAGE.as_bytes(), payer.key().as_ref(), mint.as_ref()], bump)]
+    pub user_collateral: Account<'info, UserBalance>,
+    #[account(init, payer = payer, space = 8 + 128, seeds = [PREFIX_FREEZE.as_bytes(), global.curr_freeze_sn.to_le_bytes().as_ref()], bump)]
+    pub freeze: Account<'info, FrozenCollateral>,
+    #[account(mut, seeds = [PREFIX_STATE.as_bytes()], bump)]
+    pub global: Account<'info, GlobalState>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(freeze_sn: u64)]
+pub struct MultisigRelease<'info> {
+    #[account(init_if_needed, payer = payer, space = 8 + 2048, seeds = [PREFIX_RELEASE.as_bytes(), freeze_sn.to_le_bytes().as_ref()], bump)]
+    pub multisig: Box<Account<'info, ReleaseWithMultisig>>,
+    #[account(seeds = [PREFIX_WITNESSES.as_bytes()], bump)]
+    pub witnesses: Box<Account<'info, Witnesses>>,
+    #[account(seeds = [PREFIX_STATE.as_bytes()], bump)]
+    pub global: Box<Account<'info, GlobalState>>,
     #[account(mut)]
     pub payer: Signer<'info>,
     pub system_program: Program<'info, System>,
@@ -442,119 +2449,519 @@ pub struct Repay<'info> {
 }
 
 #[derive(Accounts)]
-#[instruction(receipt_sn: u64)]
-pub struct Liquidate<'info> {
-    #[account(mut, close = admin, seeds = [PREFIX_RECEIPT.as_bytes(), receipt_sn.to_le_bytes().as_ref()], bump)]
-    pub receipt: Account<'info, LoanReceipt>,
+#[instruction(freeze_sn: u64)]
+pub struct RevokeReleaseSignature<'info> {
+    #[account(mut, seeds = [PREFIX_RELEASE.as_bytes(), freeze_sn.to_le_bytes().as_ref()], bump)]
+    pub multisig: Box<Account<'info, ReleaseWithMultisig>>,
+    #[account(seeds = [PREFIX_WITNESSES.as_bytes()], bump)]
+    pub witnesses: Account<'info, Witnesses>,
+    pub payer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(freeze_sn: u64)]
+pub struct ExpireRelease<'info> {
+    #[account(mut, seeds = [PREFIX_RELEASE.as_bytes(), freeze_sn.to_le_bytes().as_ref()], bump)]
+    pub multisig: Box<Account<'info, ReleaseWithMultisig>>,
     #[account(seeds = [PREFIX_CONFIG.as_bytes()], bump)]
     pub config: Account<'info, Config>,
-    /// CHECK:
-    #[account(mut, constraint = admin.key.to_string() == ADMIN)]
-    pub admin: AccountInfo<'info>,
-    #[account(seeds = [PREFIX_CCFEE.as_bytes(), receipt.chainid.to_le_bytes().as_ref()], bump)]
-    pub cc_fee: Account<'info, CCFee>,
+    #[account(seeds = [PREFIX_WITNESSES.as_bytes()], bump)]
+    pub witnesses: Account<'info, Witnesses>,
+    pub payer: Signer<'info>,
+    pub clock: Sysvar<'info, Clock>,
+}
+
+#[derive(Accounts)]
+#[instruction(freeze_sn: u64)]
+pub struct UnfreezeCollateral<'info> {
+    #[account(mut, seeds = [PREFIX_RELEASE.as_bytes(), freeze_sn.to_le_bytes().as_ref()], bump)]
+    pub multisig: Box<Account<'info, ReleaseWithMultisig>>,
+    #[account(seeds = [PREFIX_WITNESSES.as_bytes()], bump)]
+    pub witnesses: Box<Account<'info, Witnesses>>,
+    #[account(mut, seeds = [PREFIX_FREEZE.as_bytes(), freeze_sn.to_le_bytes().as_ref()], bump)]
+    pub freeze: Box<Account<'info, FrozenCollateral>>,
+    #[account(mut, seeds = [PREFIX_MORTGAGE.as_bytes(), freeze.owner.as_ref(), freeze.mint.as_ref()], bump)]
+    pub owner_collateral: Box<Account<'info, UserBalance>>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(freeze_sn: u64)]
+pub struct LiquidateCollateral<'info> {
+    #[account(mut, seeds = [PREFIX_RELEASE.as_bytes(), freeze_sn.to_le_bytes().as_ref()], bump)]
+    pub multisig: Box<Account<'info, ReleaseWithMultisig>>,
+    #[account(seeds = [PREFIX_WITNESSES.as_bytes()], bump)]
+    pub witnesses: Box<Account<'info, Witnesses>>,
+    #[account(mut, seeds = [PREFIX_FREEZE.as_bytes(), freeze_sn.to_le_bytes().as_ref()], bump)]
+    pub freeze: Box<Account<'info, FrozenCollateral>>,
+    #[account(mut, seeds = [PREFIX_MORTGAGE.as_bytes(), freeze.owner.as_ref(), freeze.mint.as_ref()], bump)]
+    pub owner_collateral: Box<Account<'info, UserBalance>>,
+    #[account(init_if_needed, payer = payer, space = 8 + 8, seeds = [PREFIX_MORTGAGE.as_bytes(), multisig.branches[0].release.lender.as_ref(), freeze.mint.as_ref()], bump)]
+    pub lender_collateral: Box<Account<'info, UserBalance>>,
     #[account(mut)]
     pub payer: Signer<'info>,
     pub system_program: Program<'info, System>,
-    pub clock: Sysvar<'info, Clock>,
 }
 
 #[derive(Accounts)]
-pub struct Initialize<'info> {
-    #[account(init, payer = payer, space = 8 + 64, seeds = [PREFIX_STATE.as_bytes()], bump)]
-    pub global_state: Account<'info, GlobalState>,
+#[instruction(action: WitnessAction, witness: Pubkey)]
+pub struct ProposeWitnessChange<'info> {
+    #[account(init, payer = payer, space = 8 + 1024, seeds = [PREFIX_PROPOSAL.as_bytes(), global.curr_proposal_sn.to_le_bytes().as_ref()], bump)]
+    pub proposal: Box<Account<'info, WitnessProposal>>,
+    #[account(mut, seeds = [PREFIX_WITNESSES.as_bytes()], bump)]
+    pub witnesses: Box<Account<'info, Witnesses>>,
+    #[account(mut, seeds = [PREFIX_STATE.as_bytes()], bump)]
+    pub global: Account<'info, GlobalState>,
+    #[account(mut, seeds = [PREFIX_STAKE.as_bytes(), witness.as_ref()], bump)]
+    pub stake: Option<Account<'info, WitnessStake>>,
+    /// CHECK: does not exist until staking is configured
+    #[account(seeds = [PREFIX_STAKE_CONFIG.as_bytes()], bump)]
+    pub stake_config: AccountInfo<'info>,
+    #[account(mut, seeds = [PREFIX_TREASURY.as_bytes()], bump)]
+    pub treasury: Box<Account<'info, Treasury>>,
     #[account(mut)]
     pub payer: Signer<'info>,
     pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct SetConfig<'info> {
-    #[account(init_if_needed, payer = payer, space = 8 + 64, seeds = [PREFIX_CONFIG.as_bytes()], bump)]
-    pub config: Account<'info, Config>,
+#[instruction(proposal_sn: u64)]
+pub struct ApproveWitnessChange<'info> {
+    #[account(mut, seeds = [PREFIX_PROPOSAL.as_bytes(), proposal_sn.to_le_bytes().as_ref()], bump)]
+    pub proposal: Box<Account<'info, WitnessProposal>>,
+    #[account(mut, seeds = [PREFIX_WITNESSES.as_bytes()], bump)]
+    pub witnesses: Box<Account<'info, Witnesses>>,
+    #[account(mut, seeds = [PREFIX_STAKE.as_bytes(), proposal.witness.as_ref()], bump)]
+    pub stake: Option<Account<'info, WitnessStake>>,
+    /// CHECK: does not exist until staking is configured
+    #[account(seeds = [PREFIX_STAKE_CONFIG.as_bytes()], bump)]
+    pub stake_config: AccountInfo<'info>,
+    #[account(seeds = [PREFIX_STATE.as_bytes()], bump)]
+    pub global: Box<Account<'info, GlobalState>>,
+    #[account(mut, seeds = [PREFIX_TREASURY.as_bytes()], bump)]
+    pub treasury: Box<Account<'info, Treasury>>,
     #[account(mut)]
     pub payer: Signer<'info>,
     pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-#[instruction(chainid: u32)]
-pub struct SetCCFee<'info> {
-    #[account(init_if_needed, payer = payer, space = 8 + 8, seeds = [PREFIX_CCFEE.as_bytes(), chainid.to_le_bytes().as_ref()], bump)]
-    pub cc_fee: Account<'info, CCFee>,
+pub struct ClaimReward<'info> {
+    #[account(mut, seeds = [PREFIX_REWARD.as_bytes()], bump)]
+    pub reward_pool: Box<Account<'info, RewardPool>>,
+    #[account(mut, seeds = [PREFIX_REWARD.as_bytes(), payer.key().as_ref()], bump)]
+    pub reward: Account<'info, WitnessReward>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetTreasuryThreshold<'info> {
+    #[account(mut, seeds = [PREFIX_TREASURY.as_bytes()], bump)]
+    pub treasury: Box<Account<'info, Treasury>>,
+    #[account(seeds = [PREFIX_STATE.as_bytes()], bump)]
+    pub global: Box<Account<'info, GlobalState>>,
+    pub payer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ProposeTreasuryWithdrawal<'info> {
+    #[account(init, payer = payer, space = 8 + 512, seeds = [PREFIX_WITHDRAWAL.as_bytes(), treasury.curr_withdrawal_sn.to_le_bytes().as_ref()], bump)]
+    pub withdrawal: Box<Account<'info, TreasuryWithdrawal>>,
+    #[account(mut, seeds = [PREFIX_TREASURY.as_bytes()], bump)]
+    pub treasury: Box<Account<'info, Treasury>>,
+    #[account(seeds = [PREFIX_ROLES.as_bytes()], bump)]
+    pub roles: Box<Account<'info, Roles>>,
     #[account(mut)]
     pub payer: Signer<'info>,
     pub system_program: Program<'info, System>,
 }
-   
+
 #[derive(Accounts)]
-#[instruction(chainid: u32, collateral_sn: u64)]
-pub struct MultisigCollateral<'info> {
-    #[account(init_if_needed, payer = payer, space = 8 + 1024, seeds = [PREFIX_COLLATERAL.as_bytes(), chainid.to_le_bytes().as_ref(), collateral_sn.to_le_bytes().as_ref()], bump)]
-    pub multisig: Box<Account<'info, CollateralWithMultisig>>,
-    #[account(seeds = [PREFIX_WITNESSES.as_bytes()], bump)]
-    pub witnesses: Account<'info, Witnesses>,
+#[instruction(withdrawal_sn: u64)]
+pub struct ApproveTreasuryWithdrawal<'info> {
+    #[account(mut, seeds = [PREFIX_WITHDRAWAL.as_bytes(), withdrawal_sn.to_le_bytes().as_ref()], bump)]
+    pub withdrawal: Box<Account<'info, TreasuryWithdrawal>>,
+    #[account(seeds = [PREFIX_ROLES.as_bytes()], bump)]
+    pub roles: Box<Account<'info, Roles>>,
+    pub payer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(withdrawal_sn: u64)]
+pub struct ExecuteTreasuryWithdrawal<'info> {
+    #[account(mut, seeds = [PREFIX_WITHDRAWAL.as_bytes(), withdrawal_sn.to_le_bytes().as_ref()], bump)]
+    pub withdrawal: Box<Account<'info, TreasuryWithdrawal>>,
+    #[account(mut, seeds = [PREFIX_TREASURY.as_bytes()], bump)]
+    pub treasury: Box<Account<'info, Treasury>>,
+    #[account(seeds = [PREFIX_ROLES.as_bytes()], bump)]
+    pub roles: Box<Account<'info, Roles>>,
+    /// CHECK:
+    #[account(mut, constraint = receiver.key() == withdrawal.receiver)]
+    pub receiver: AccountInfo<'info>,
+    pub payer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(withdrawal_sn: u64)]
+pub struct ExecuteTokenTreasuryWithdrawal<'info> {
+    #[account(mut, seeds = [PREFIX_WITHDRAWAL.as_bytes(), withdrawal_sn.to_le_bytes().as_ref()], bump)]
+    pub withdrawal: Box<Account<'info, TreasuryWithdrawal>>,
+    #[account(seeds = [PREFIX_TREASURY.as_bytes()], bump)]
+    pub treasury: Box<Account<'info, Treasury>>,
+    #[account(mut, seeds = [PREFIX_BALANCE.as_bytes(), treasury.key().as_ref(), withdrawal.mint.as_ref()], bump)]
+    pub treasury_balance: Box<Account<'info, UserBalance>>,
+    #[account(mut, seeds = [PREFIX_VAULT.as_bytes(), withdrawal.mint.as_ref()], bump)]
+    pub vault: Box<Account<'info, TokenAccount>>,
+    #[account(seeds = [PREFIX_ROLES.as_bytes()], bump)]
+    pub roles: Box<Account<'info, Roles>>,
+    #[account(mut, token::mint = withdrawal.mint, constraint = receiver.key() == withdrawal.receiver)]
+    pub receiver: Box<Account<'info, TokenAccount>>,
+    pub payer: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct SetStakeConfig<'info> {
+    #[account(init_if_needed, payer = payer, space = 8 + 16, seeds = [PREFIX_STAKE_CONFIG.as_bytes()], bump)]
+    pub stake_config: Account<'info, StakeConfig>,
+    #[account(seeds = [PREFIX_ROLES.as_bytes()], bump)]
+    pub roles: Box<Account<'info, Roles>>,
     #[account(mut)]
     pub payer: Signer<'info>,
     pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-#[instruction(chainid: u32, collateral_sn: u64)]
-pub struct ClearCollateral<'info> {
-    #[account(mut, seeds = [PREFIX_COLLATERAL.as_bytes(), chainid.to_le_bytes().as_ref(), collateral_sn.to_le_bytes().as_ref()], bump, 
-        realloc = 8 + 5, realloc::payer = payer, realloc::zero = false)]
-    pub multisig: Box<Account<'info, CollateralWithMultisig>>,
+pub struct DepositStake<'info> {
+    #[account(init_if_needed, payer = payer, space = 8 + 48, seeds = [PREFIX_STAKE.as_bytes(), payer.key().as_ref()], bump)]
+    pub stake: Account<'info, WitnessStake>,
     #[account(mut)]
     pub payer: Signer<'info>,
     pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct UpdateWitnesses<'info> {
-    #[account(init_if_needed, payer = payer, space = 8 + 1024, seeds = [PREFIX_WITNESSES.as_bytes()], bump)]
+pub struct UnbondStake<'info> {
+    #[account(mut, seeds = [PREFIX_STAKE.as_bytes(), payer.key().as_ref()], bump)]
+    pub stake: Account<'info, WitnessStake>,
+    #[account(seeds = [PREFIX_STAKE_CONFIG.as_bytes()], bump)]
+    pub stake_config: Account<'info, StakeConfig>,
+    #[account(seeds = [PREFIX_WITNESSES.as_bytes()], bump)]
+    pub witnesses: Box<Account<'info, Witnesses>>,
+    pub payer: Signer<'info>,
+    pub clock: Sysvar<'info, Clock>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawStake<'info> {
+    #[account(mut, seeds = [PREFIX_STAKE.as_bytes(), payer.key().as_ref()], bump)]
+    pub stake: Account<'info, WitnessStake>,
+    #[account(seeds = [PREFIX_WITNESSES.as_bytes()], bump)]
+    pub witnesses: Box<Account<'info, Witnesses>>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub clock: Sysvar<'info, Clock>,
+}
+
+#[derive(Accounts)]
+#[instruction(chainid: u32, token: [u8; 20])]
+pub struct SubmitPrice<'info> {
+    #[account(init_if_needed, payer = payer, space = 8 + 1600, seeds = [PREFIX_PRICE.as_bytes(), chainid.to_le_bytes().as_ref(), token.as_ref()], bump)]
+    pub price_feed: Box<Account<'info, PriceFeed>>,
+    #[account(seeds = [PREFIX_WITNESSES.as_bytes()], bump)]
     pub witnesses: Box<Account<'info, Witnesses>>,
     #[account(mut)]
     pub payer: Signer<'info>,
     pub system_program: Program<'info, System>,
+    pub clock: Sysvar<'info, Clock>,
+}
+
+#[derive(Accounts)]
+#[instruction(chainid: u32, token: [u8; 20])]
+pub struct GetPrice<'info> {
+    #[account(seeds = [PREFIX_PRICE.as_bytes(), chainid.to_le_bytes().as_ref(), token.as_ref()], bump)]
+    pub price_feed: Box<Account<'info, PriceFeed>>,
+    #[account(seeds = [PREFIX_WITNESSES.as_bytes()], bump)]
+    pub witnesses: Box<Account<'info, Witnesses>>,
+    pub clock: Sysvar<'info, Clock>,
+}
+
+#[account]
+pub struct CollateralWithMultisig {
+    pub status: MultisigStatus,
+    pub start: u64,
+    pub reservation: Reservation,
+    pub threshold: u64,
+    pub witnesses: Vec<Pubkey>,
+    pub branches: Vec<CollateralWithSigners>, 
+}
+
+impl CollateralWithMultisig {
+    pub fn valid_signers(&self, signers: &[Pubkey], witnesses: &Witnesses) -> Vec<Pubkey> {
+        valid_signers(&self.witnesses, signers, witnesses)
+    }
+
+    pub fn evaluate(&mut self, witnesses: &Witnesses) {
+        self.status = evaluate_branches(&mut self.branches, |b| &b.signers, &self.witnesses, self.threshold, witnesses);
+    }
+
+    // The part of the reservation the agreed collateral can borrow: only a reservation on the
+    // same order for the same borrower counts.
+    pub fn own_reservation(&self) -> u64 {
+        let c = &self.branches[0].collateral;
+        let r = &self.reservation;
+        if r.order_sn == c.order_sn && r.borrower == c.borrower { r.amount } else { 0 }
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Default, PartialEq, Copy, Clone)]
+pub struct Reservation {
+    pub order_sn: u64,
+    pub borrower: Pubkey,
+    pub amount: u64,
+    pub expiry: u64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, PartialEq, Copy, Clone)]
+pub enum MultisigStatus {
+    Starting,
+    InProgress,
+    ReachConsensus,
+    Finished,
+    Expired,
+    Failed,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, PartialEq, Copy, Clone)]
+pub struct Collateral {
+    pub chainid: u32,
+    pub collateral_sn: u64,
+    pub source: [u8; 20],
+    pub token: [u8; 20],
+    pub frozen: u64,
+    pub borrower: Pubkey,
+    pub order_sn: u64,
+    pub amount: u64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct CollateralWithSigners {
+    pub collateral: Collateral,
+    pub signers: Vec<Pubkey>,
+}
+
+#[account]
+pub struct FrozenCollateral {
+    pub sn: u64,
+    pub owner: Pubkey,
+    pub mint: Pubkey,
+    pub frozen: u64,
+    pub chainid: u32,
+    pub order_sn: u64,
+    pub amount: u64,
+    pub receiver: [u8; 20],
+    pub status: FreezeStatus,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, PartialEq, Copy, Clone)]
+pub enum FreezeStatus {
+    Frozen,
+    Released,
+    Liquidated,
+}
+
+#[account]
+pub struct ReleaseWithMultisig {
+    pub status: MultisigStatus,
+    pub start: u64,
+    pub threshold: u64,
+    pub witnesses: Vec<Pubkey>,
+    pub branches: Vec<ReleaseWithSigners>,
+}
+
+impl ReleaseWithMultisig {
+    pub fn valid_signers(&self, signers: &[Pubkey], witnesses: &Witnesses) -> Vec<Pubkey> {
+        valid_signers(&self.witnesses, signers, witnesses)
+    }
+
+    pub fn evaluate(&mut self, witnesses: &Witnesses) {
+        self.status = evaluate_branches(&mut self.branches, |b| &b.signers, &self.witnesses, self.threshold, witnesses);
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, PartialEq, Copy, Clone)]
+pub enum ReleaseAction {
+    Unfreeze,
+    Liquidate,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, PartialEq, Copy, Clone)]
+pub struct Release {
+    pub freeze_sn: u64,
+    pub action: ReleaseAction,
+    pub lender: Pubkey,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct ReleaseWithSigners {
+    pub release: Release,
+    pub signers: Vec<Pubkey>,
+}
+
+#[account]
+pub struct Witnesses {
+    pub multisig_threshold: usize,
+    pub v: Vec<Pubkey>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, PartialEq, Copy, Clone)]
+pub enum Role {
+    ConfigManager,
+    FeeManager,
+    WitnessManager,
+    Pauser,
+    TreasuryWithdrawer,
+}
+
+#[account]
+pub struct Roles {
+    pub config_managers: Vec<Pubkey>,
+    pub fee_managers: Vec<Pubkey>,
+    pub witness_managers: Vec<Pubkey>,
+    pub pausers: Vec<Pubkey>,
+    pub treasury_withdrawers: Vec<Pubkey>,
+}
+
+impl Roles {
+    pub fn members(&self, role: Role) -> &Vec<Pubkey> {
+        match role {
+            Role::ConfigManager => &self.config_managers,
+            Role::FeeManager => &self.fee_managers,
+            Role::WitnessManager => &self.witness_managers,
+            Role::Pauser => &self.pausers,
+            Role::TreasuryWithdrawer => &self.treasury_withdrawers,
+        }
+    }
+
+    pub fn members_mut(&mut self, role: Role) -> &mut Vec<Pubkey> {
+        match role {
+            Role::ConfigManager => &mut self.config_managers,
+            Role::FeeManager => &mut self.fee_managers,
+            Role::WitnessManager => &mut self.witness_managers,
+            Role::Pauser => &mut self.pausers,
+            Role::TreasuryWithdrawer => &mut self.treasury_withdrawers,
+        }
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, PartialEq, Copy, Clone)]
+pub enum WitnessAction {
+    Add,
+    Remove,
+    Slash { amount: u64 },
+}
+
+#[account]
+pub struct WitnessProposal {
+    pub sn: u64,
+    pub action: WitnessAction,
+    pub witness: Pubkey,
+    pub executed: bool,
+    pub signers: Vec<Pubkey>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct PriceSubmission {
+    pub witness: Pubkey,
+    pub price: u64,
+    pub time: u64,
+}
+
+#[account]
+pub struct PriceFeed {
+    pub chainid: u32,
+    pub token: [u8; 20],
+    pub price: u64,
+    pub time: u64,
+    pub submissions: Vec<PriceSubmission>,
+}
+
+impl PriceFeed {
+    // Median of the submissions from current witnesses that are not older than max_age.
+    // More than half of the witnesses must have submitted within max_age.
+    pub fn median_price(&self, witnesses: &Witnesses, now: u64, max_age: u64) -> Result<u64> {
+        let mut prices: Vec<u64> = self.submissions.iter()
+            .filter(|s| witnesses.v.contains(&s.witness) && now.saturating_sub(s.time) <= max_age)
+            .map(|s| s.price)
+            .collect();
+        require!(prices.len() * 2 > witnesses.v.len(), MyError::StalePrice);
+        Ok(median(&mut prices))
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, PartialEq, Copy, Clone)]
+pub enum TreasuryCategory {
+    Commission,
+    CCFee,
+    Rent,
+    Slashing,
 }
 
 #[account]
-pub struct CollateralWithMultisig {
-    pub status: MultisigStatus,
-    pub branches: Vec<CollateralWithSigners>, 
+pub struct Treasury {
+    pub commission: u64,
+    pub ccfee: u64,
+    pub rent: u64,
+    pub slashed: u64,
+    pub threshold: u8,
+    pub curr_withdrawal_sn: u64,
 }
 
-#[derive(AnchorSerialize, AnchorDeserialize, PartialEq, Copy, Clone)]
-pub enum MultisigStatus {
-    Starting,
-    InProgress,
-    ReachConsensus,
-    Finished,
+impl Treasury {
+    pub fn balance_mut(&mut self, category: TreasuryCategory) -> &mut u64 {
+        match category {
+            TreasuryCategory::Commission => &mut self.commission,
+            TreasuryCategory::CCFee => &mut self.ccfee,
+            TreasuryCategory::Rent => &mut self.rent,
+            TreasuryCategory::Slashing => &mut self.slashed,
+        }
+    }
 }
 
-#[derive(AnchorSerialize, AnchorDeserialize, PartialEq, Copy, Clone)]
-pub struct Collateral {
-    pub chainid: u32,
-    pub collateral_sn: u64,
-    pub source: [u8; 20],
-    pub token: [u8; 20],
-    pub frozen: u64,
-    pub borrower: Pubkey,
-    pub order_sn: u64,
+#[account]
+pub struct TreasuryWithdrawal {
+    pub sn: u64,
+    pub category: TreasuryCategory,
+    pub mint: Pubkey,
     pub amount: u64,
+    pub receiver: Pubkey,
+    pub signers: Vec<Pubkey>,
+    pub executed: bool,
 }
 
-#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
-pub struct CollateralWithSigners {
-    pub collateral: Collateral,
-    pub signers: Vec<Pubkey>,
+// Holds the lamports of all unclaimed rewards; each witness's share is kept in its own `WitnessReward`.
+#[account]
+pub struct RewardPool {}
+
+#[account]
+pub struct WitnessReward {
+    pub witness: Pubkey,
+    pub amount: u64,
 }
 
 #[account]
-pub struct Witnesses {
-    pub multisig_threshold: usize,
-    pub v: Vec<Pubkey>,
+pub struct StakeConfig {
+    pub min_stake: u64,
+    pub unbonding_period: u64,
+}
+
+#[account]
+pub struct WitnessStake {
+    pub witness: Pubkey,
+    pub amount: u64,
+    pub unbonding_at: u64,
 }
 
 #[account]
@@ -570,6 +2977,13 @@ pub struct LoanReceipt {
     pub amount: u64,
     pub time: u64,
     pub rate: u64,
+    pub mint: Pubkey,
+    pub term: u64,
+    pub grace: u64,
+    pub installments: u8,
+    pub repaid: u64,
+    pub released: u64,
+    pub signers: Vec<Pubkey>,
 }
 
 #[account]
@@ -578,6 +2992,21 @@ pub struct Order {
     pub lender: Pubkey,
     pub balance: u64,
     pub rate: u64,
+    pub mint: Pubkey,
+    pub term: u64,
+    pub installments: u8,
+    pub reserved: u64,
+}
+
+impl Order {
+    pub fn available(&self) -> u64 {
+        self.balance - self.reserved
+    }
+}
+
+#[account]
+pub struct AutoReinvest {
+    pub order_sn: u64,
 }
 
 #[account]
@@ -589,6 +3018,11 @@ pub struct UserBalance {
 pub struct GlobalState {
     pub curr_order_sn: u64,
     pub curr_receipt_sn: u64,
+    pub curr_proposal_sn: u64,
+    pub curr_freeze_sn: u64,
+    pub admin: Pubkey,
+    pub pending_admin: Pubkey,
+    pub paused: u64,
 }
 
 #[account]
@@ -598,8 +3032,75 @@ pub struct Config {
     pub penalty_ir: u64,
     pub penalty_days: u64,
     pub commission_rate: u64,
-    pub cycle: u64,
-    pub deadline: u64,
+    pub min_term: u64,
+    pub max_term: u64,
+    pub grace: u64,
+    pub multisig_timeout: u64,
+    pub initialized: bool,
+}
+
+impl Config {
+    pub fn params(&self) -> ConfigParams {
+        ConfigParams {
+            min_ir: self.min_ir,
+            max_ir: self.max_ir,
+            penalty_ir: self.penalty_ir,
+            penalty_days: self.penalty_days,
+            commission_rate: self.commission_rate,
+            min_term: self.min_term,
+            max_term: self.max_term,
+            grace: self.grace,
+            multisig_timeout: self.multisig_timeout,
+        }
+    }
+
+    pub fn set_params(&mut self, params: &ConfigParams) {
+        self.min_ir = params.min_ir;
+        self.max_ir = params.max_ir;
+        self.penalty_ir = params.penalty_ir;
+        self.penalty_days = params.penalty_days;
+        self.commission_rate = params.commission_rate;
+        self.min_term = params.min_term;
+        self.max_term = params.max_term;
+        self.grace = params.grace;
+        self.multisig_timeout = params.multisig_timeout;
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Default, Copy, Clone)]
+pub struct ConfigParams {
+    pub min_ir: u64,
+    pub max_ir: u64,
+    pub penalty_ir: u64,
+    pub penalty_days: u64,
+    pub commission_rate: u64,
+    pub min_term: u64,
+    pub max_term: u64,
+    pub grace: u64,
+    pub multisig_timeout: u64,
+}
+
+impl ConfigParams {
+    pub fn validate(&self) -> Result<()> {
+        require!(self.min_ir <= self.max_ir, MyError::IllegalConfig);
+        require!(self.commission_rate <= 100, MyError::IllegalConfig);
+        require!(self.min_term > 0 && self.min_term <= self.max_term, MyError::IllegalConfig);
+        require!(self.multisig_timeout > 0, MyError::IllegalConfig);
+        require!(self.grace <= MAX_GRACE, MyError::IllegalConfig);
+        Ok(())
+    }
+}
+
+#[account]
+pub struct PendingConfig {
+    pub params: ConfigParams,
+    pub eta: u64,
+}
+
+#[account]
+pub struct PendingCCFee {
+    pub fee: u64,
+    pub eta: u64,
 }
 
 #[account]
@@ -607,12 +3108,70 @@ pub struct CCFee {
     pub fee: u64,
 }
 
+#[event]
+pub struct EventConfigQueued {
+    pub params: ConfigParams,
+    pub eta: u64,
+}
+
+#[event]
+pub struct EventConfigChanged {
+    pub old: ConfigParams,
+    pub new: ConfigParams,
+}
+
+#[event]
+pub struct EventCCFeeQueued {
+    pub chainid: u32,
+    pub fee: u64,
+    pub eta: u64,
+}
+
+#[event]
+pub struct EventCCFeeChanged {
+    pub chainid: u32,
+    pub old_fee: u64,
+    pub new_fee: u64,
+}
+
+#[event]
+pub struct EventSetPause {
+    pub paused: u64,
+}
+
+#[event]
+pub struct EventGrantRole {
+    pub role: Role,
+    pub key: Pubkey,
+}
+
+#[event]
+pub struct EventRevokeRole {
+    pub role: Role,
+    pub key: Pubkey,
+}
+
+#[event]
+pub struct EventProposeAdmin {
+    pub admin: Pubkey,
+    pub pending_admin: Pubkey,
+}
+
+#[event]
+pub struct EventAcceptAdmin {
+    pub old_admin: Pubkey,
+    pub new_admin: Pubkey,
+}
+
 #[event]
 pub struct EventPlaceOrder {
     pub order_sn: u64,
     pub lender: Pubkey,
     pub balance: u64,
     pub rate: u64,
+    pub mint: Pubkey,
+    pub term: u64,
+    pub installments: u8,
 }
 
 #[event]
@@ -620,6 +3179,7 @@ pub struct EventCancelOrder {
     pub order_sn: u64,
     pub lender: Pubkey,
     pub balance: u64,
+    pub reserved: u64,
 }
 
 #[event]
@@ -643,6 +3203,10 @@ pub struct EventBorrowSuccess {
     pub rate: u64,
     pub order_sn: u64,
     pub order_balance: u64,
+    pub mint: Pubkey,
+    pub term: u64,
+    pub installments: u8,
+    pub grace: u64,
 }
 
 #[event]
@@ -659,6 +3223,55 @@ pub struct EventRepaySuccess {
     pub frozen: u64,
 }
 
+#[event]
+pub struct EventPartialRepay {
+    pub receipt_sn: u64,
+    pub borrower: Pubkey,
+    pub lender: Pubkey,
+    pub amount: u64,
+    pub income: u64,
+    pub repaid: u64,
+    pub remaining: u64,
+    pub chainid: u32,
+    pub c_sn: u64,
+    pub source: [u8; 20],
+    pub token: [u8; 20],
+    pub released: u64,
+}
+
+#[event]
+pub struct EventExtendLoan {
+    pub receipt_sn: u64,
+    pub borrower: Pubkey,
+    pub lender: Pubkey,
+    pub amount: u64,
+    pub income: u64,
+    pub chainid: u32,
+    pub c_sn: u64,
+    pub source: [u8; 20],
+    pub token: [u8; 20],
+    pub frozen: u64,
+    pub rate: u64,
+    pub time: u64,
+    pub expiry: u64,
+}
+
+#[event]
+pub struct EventAutoReinvest {
+    pub lender: Pubkey,
+    pub mint: Pubkey,
+    pub order_sn: u64,
+}
+
+#[event]
+pub struct EventReinvest {
+    pub receipt_sn: u64,
+    pub order_sn: u64,
+    pub lender: Pubkey,
+    pub amount: u64,
+    pub order_balance: u64,
+}
+
 #[event]
 pub struct EventLiquidate {
     pub receipt_sn: u64,
@@ -671,6 +3284,202 @@ pub struct EventLiquidate {
     pub receiver: [u8; 20],
 }
 
+#[event]
+pub struct EventProposeWitnessChange {
+    pub proposal_sn: u64,
+    pub proposer: Pubkey,
+    pub action: WitnessAction,
+    pub witness: Pubkey,
+}
+
+#[event]
+pub struct EventApproveWitnessChange {
+    pub proposal_sn: u64,
+    pub witness: Pubkey,
+    pub approvals: u64,
+}
+
+#[event]
+pub struct EventWitnessChanged {
+    pub proposal_sn: u64,
+    pub action: WitnessAction,
+    pub witness: Pubkey,
+    pub multisig_threshold: u64,
+}
+
+#[event]
+pub struct EventProposeTreasuryWithdrawal {
+    pub withdrawal_sn: u64,
+    pub category: TreasuryCategory,
+    pub mint: Pubkey,
+    pub amount: u64,
+    pub receiver: Pubkey,
+    pub proposer: Pubkey,
+}
+
+#[event]
+pub struct EventApproveTreasuryWithdrawal {
+    pub withdrawal_sn: u64,
+    pub signer: Pubkey,
+    pub approvals: u64,
+}
+
+#[event]
+pub struct EventTreasuryWithdrawal {
+    pub withdrawal_sn: u64,
+    pub category: TreasuryCategory,
+    pub mint: Pubkey,
+    pub amount: u64,
+    pub receiver: Pubkey,
+}
+
+#[event]
+pub struct EventCreditRewards {
+    pub receipt_sn: u64,
+    pub fee: u64,
+    pub share: u64,
+    pub signers: Vec<Pubkey>,
+}
+
+#[event]
+pub struct EventClaimReward {
+    pub witness: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct EventDepositStake {
+    pub witness: Pubkey,
+    pub amount: u64,
+    pub total: u64,
+}
+
+#[event]
+pub struct EventUnbondStake {
+    pub witness: Pubkey,
+    pub amount: u64,
+    pub unlock_time: u64,
+}
+
+#[event]
+pub struct EventWithdrawStake {
+    pub witness: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct EventSlashStake {
+    pub proposal_sn: u64,
+    pub witness: Pubkey,
+    pub amount: u64,
+    pub remaining: u64,
+}
+
+#[event]
+pub struct EventPriceSubmitted {
+    pub chainid: u32,
+    pub token: [u8; 20],
+    pub witness: Pubkey,
+    pub price: u64,
+    pub median: u64,
+    pub time: u64,
+}
+
+#[event]
+pub struct EventFreezeCollateral {
+    pub freeze_sn: u64,
+    pub owner: Pubkey,
+    pub mint: Pubkey,
+    pub frozen: u64,
+    pub chainid: u32,
+    pub order_sn: u64,
+    pub amount: u64,
+    pub receiver: [u8; 20],
+}
+
+#[event]
+pub struct EventUnfreezeCollateral {
+    pub freeze_sn: u64,
+    pub owner: Pubkey,
+    pub mint: Pubkey,
+    pub frozen: u64,
+}
+
+#[event]
+pub struct EventLiquidateCollateral {
+    pub freeze_sn: u64,
+    pub owner: Pubkey,
+    pub lender: Pubkey,
+    pub mint: Pubkey,
+    pub frozen: u64,
+}
+
+#[event]
+pub struct EventRevokeReleaseSignature {
+    pub freeze_sn: u64,
+    pub witness: Pubkey,
+    pub release: Release,
+    pub signers: u64,
+}
+
+#[event]
+pub struct EventReleaseExpired {
+    pub freeze_sn: u64,
+    pub start: u64,
+    pub time: u64,
+}
+
+#[event]
+pub struct EventCollateralRejected {
+    pub chainid: u32,
+    pub collateral_sn: u64,
+    pub start: u64,
+    pub time: u64,
+}
+
+#[event]
+pub struct EventBorrowFailed {
+    pub chainid: u32,
+    pub collateral_sn: u64,
+    pub borrower: Pubkey,
+    pub order_sn: u64,
+    pub amount: u64,
+}
+
+#[event]
+pub struct EventRecountCollateral {
+    pub chainid: u32,
+    pub collateral_sn: u64,
+    pub consensus: bool,
+}
+
+#[event]
+pub struct EventRevokeCollateralSignature {
+    pub chainid: u32,
+    pub collateral_sn: u64,
+    pub witness: Pubkey,
+    pub collateral: Collateral,
+    pub signers: u64,
+}
+
+#[event]
+pub struct EventReserveOrder {
+    pub chainid: u32,
+    pub collateral_sn: u64,
+    pub order_sn: u64,
+    pub borrower: Pubkey,
+    pub amount: u64,
+    pub expiry: u64,
+}
+
+#[event]
+pub struct EventReleaseReservation {
+    pub chainid: u32,
+    pub collateral_sn: u64,
+    pub order_sn: u64,
+    pub amount: u64,
+}
+
 #[error_code]
 pub enum MyError {
     #[msg("insufficient user balance")]
@@ -697,4 +3506,84 @@ pub enum MyError {
     CannotClearUnfinishedMultisig,
     #[msg("witness already exist")]
     WitnessAlreadyExist,
+    #[msg("witness not exist")]
+    WitnessNotExist,
+    #[msg("witnesses already initialized")]
+    WitnessesAlreadyInitialized,
+    #[msg("cannot remove the last witness")]
+    CannotRemoveLastWitness,
+    #[msg("proposal already executed")]
+    ProposalAlreadyExecuted,
+    #[msg("illegal price")]
+    IllegalPrice,
+    #[msg("stale price")]
+    StalePrice,
+    #[msg("mint mismatch")]
+    MintMismatch,
+    #[msg("cannot settle without consensus")]
+    CannotSettleWithoutConsensus,
+    #[msg("collateral not frozen")]
+    CollateralNotFrozen,
+    #[msg("release action mismatch")]
+    ReleaseActionMismatch,
+    #[msg("illegal loan term")]
+    IllegalLoanTerm,
+    #[msg("illegal installments")]
+    IllegalInstallments,
+    #[msg("illegal repay amount")]
+    IllegalRepayAmount,
+    #[msg("cannot extend loan")]
+    CannotExtendLoan,
+    #[msg("lender approval required")]
+    LenderApprovalRequired,
+    #[msg("auto-reinvest order mismatch")]
+    ReinvestOrderMismatch,
+    #[msg("insufficient stake")]
+    InsufficientStake,
+    #[msg("witness still active")]
+    WitnessStillActive,
+    #[msg("stake still bonded")]
+    StakeStillBonded,
+    #[msg("no reward to claim")]
+    NoRewardToClaim,
+    #[msg("role already granted")]
+    RoleAlreadyGranted,
+    #[msg("role not granted")]
+    RoleNotGranted,
+    #[msg("instruction paused")]
+    InstructionPaused,
+    #[msg("illegal config")]
+    IllegalConfig,
+    #[msg("change still locked")]
+    ChangeStillLocked,
+    #[msg("illegal threshold")]
+    IllegalThreshold,
+    #[msg("illegal treasury category")]
+    IllegalTreasuryCategory,
+    #[msg("insufficient treasury balance")]
+    InsufficientTreasuryBalance,
+    #[msg("multisig not in progress")]
+    MultisigNotInProgress,
+    #[msg("multisig not expired")]
+    MultisigNotExpired,
+    #[msg("order can fill the borrow")]
+    OrderCanFillBorrow,
+    #[msg("already reserved")]
+    AlreadyReserved,
+    #[msg("no reservation")]
+    NoReservation,
+    #[msg("reservation still active")]
+    ReservationStillActive,
+    #[msg("not signed")]
+    NotSigned,
+    #[msg("witness not in round")]
+    WitnessNotInRound,
+    #[msg("consensus lost")]
+    ConsensusLost,
+    #[msg("reward account mismatch")]
+    RewardAccountMismatch,
+    #[msg("illegal cross-chain fee")]
+    IllegalCCFee,
+    #[msg("illegal reserve amount")]
+    IllegalReserveAmount,
 }
\ No newline at end of file
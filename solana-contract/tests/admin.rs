@@ -0,0 +1,269 @@
+use ::solana_lending::*;
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::system_program;
+use solana_lending_testkit::*;
+
+fn propose_admin(p: &mut Protocol, payer: &Pubkey, new_admin: &Pubkey) -> std::result::Result<(), ProgramError> {
+    p.env.process(
+        accounts::UpdateAdmin {
+            global: global(),
+            payer: *payer,
+        },
+        instruction::ProposeAdmin { new_admin: *new_admin },
+    )
+}
+
+fn accept_admin(p: &mut Protocol, payer: &Pubkey) -> std::result::Result<(), ProgramError> {
+    p.env.process(
+        accounts::UpdateAdmin {
+            global: global(),
+            payer: *payer,
+        },
+        instruction::AcceptAdmin {},
+    )
+}
+
+fn set_treasury_threshold(p: &mut Protocol, payer: &Pubkey, threshold: u8) -> std::result::Result<(), ProgramError> {
+    p.env.process(
+        accounts::SetTreasuryThreshold {
+            treasury: treasury(),
+            global: global(),
+            payer: *payer,
+        },
+        instruction::SetTreasuryThreshold { threshold },
+    )
+}
+
+fn propose_withdrawal(p: &mut Protocol, payer: &Pubkey, category: TreasuryCategory, mint: &Pubkey, amount: u64, receiver: &Pubkey) -> std::result::Result<u64, ProgramError> {
+    let sn = p.env.get::<Treasury>(&treasury()).curr_withdrawal_sn;
+    p.env.process(
+        accounts::ProposeTreasuryWithdrawal {
+            withdrawal: withdrawal(sn),
+            treasury: treasury(),
+            roles: roles(),
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::ProposeTreasuryWithdrawal {
+            category,
+            mint: *mint,
+            amount,
+            receiver: *receiver,
+        },
+    )?;
+    Ok(sn)
+}
+
+fn approve_withdrawal(p: &mut Protocol, payer: &Pubkey, withdrawal_sn: u64) -> std::result::Result<(), ProgramError> {
+    p.env.process(
+        accounts::ApproveTreasuryWithdrawal {
+            withdrawal: withdrawal(withdrawal_sn),
+            roles: roles(),
+            payer: *payer,
+        },
+        instruction::ApproveTreasuryWithdrawal { withdrawal_sn },
+    )
+}
+
+fn execute_withdrawal(p: &mut Protocol, payer: &Pubkey, withdrawal_sn: u64) -> std::result::Result<(), ProgramError> {
+    let receiver = p.env.get::<TreasuryWithdrawal>(&withdrawal(withdrawal_sn)).receiver;
+    p.env.process(
+        accounts::ExecuteTreasuryWithdrawal {
+            withdrawal: withdrawal(withdrawal_sn),
+            treasury: treasury(),
+            roles: roles(),
+            receiver,
+            payer: *payer,
+        },
+        instruction::ExecuteTreasuryWithdrawal { withdrawal_sn },
+    )
+}
+
+#[test]
+fn initialize_requires_upgrade_authority() {
+    let mut env = Env::new();
+    let authority = env.wallet();
+    let other = env.wallet();
+    let mut p = Protocol {
+        env,
+        admin: other,
+        witnesses: vec![],
+    };
+    assert_eq!(p.initialize(&other), Err(anchor_error(anchor_lang::error::ErrorCode::AccountNotInitialized)));
+
+    p.env.set_upgrade_authority(&authority);
+    assert_eq!(p.initialize(&other), Err(error(MyError::NoOperationPermission)));
+    p.initialize(&authority).unwrap();
+    assert_eq!(p.global_state().admin, authority);
+}
+
+#[test]
+fn initialize_once() {
+    let mut p = Protocol::bare();
+    let g = p.global_state();
+    assert_eq!((g.curr_order_sn, g.curr_receipt_sn, g.curr_proposal_sn, g.curr_freeze_sn), (1, 1, 1, 1));
+    assert_eq!((g.admin, g.pending_admin, g.paused), (p.admin, Pubkey::default(), 0));
+    let t: Treasury = p.env.get(&treasury());
+    assert_eq!((t.threshold, t.curr_withdrawal_sn), (1, 1));
+
+    let other = p.env.wallet();
+    assert!(p.initialize(&other).is_err());
+}
+
+#[test]
+fn two_step_admin_rotation() {
+    let mut p = Protocol::bare();
+    let admin = p.admin;
+    let next = p.env.wallet();
+    let stranger = p.env.wallet();
+
+    assert_eq!(propose_admin(&mut p, &stranger, &stranger), Err(error(MyError::NoOperationPermission)));
+    assert_eq!(accept_admin(&mut p, &next), Err(error(MyError::NoOperationPermission)));
+    propose_admin(&mut p, &admin, &next).unwrap();
+    assert_eq!(p.global_state().pending_admin, next);
+    assert_eq!(accept_admin(&mut p, &stranger), Err(error(MyError::NoOperationPermission)));
+
+    accept_admin(&mut p, &next).unwrap();
+    let g = p.global_state();
+    assert_eq!((g.admin, g.pending_admin), (next, Pubkey::default()));
+    assert_eq!(propose_admin(&mut p, &admin, &admin), Err(error(MyError::NoOperationPermission)));
+    assert_eq!(p.grant_role(&admin, Role::Pauser, &stranger), Err(error(MyError::NoOperationPermission)));
+    p.grant_role(&next, Role::Pauser, &stranger).unwrap();
+}
+
+#[test]
+fn grant_and_revoke_roles() {
+    let mut p = Protocol::bare();
+    let admin = p.admin;
+    let pauser = p.env.wallet();
+
+    assert_eq!(p.grant_role(&admin, Role::Pauser, &admin), Err(error(MyError::RoleAlreadyGranted)));
+    assert_eq!(p.revoke_role(&admin, Role::Pauser, &pauser), Err(error(MyError::RoleNotGranted)));
+    assert_eq!(p.set_pause(&pauser, PAUSE_BORROW), Err(error(MyError::NoOperationPermission)));
+
+    p.grant_role(&admin, Role::Pauser, &pauser).unwrap();
+    assert_eq!(p.env.get::<Roles>(&roles()).members(Role::Pauser), &vec![admin, pauser]);
+    p.set_pause(&pauser, PAUSE_BORROW).unwrap();
+    assert_eq!(p.global_state().paused, PAUSE_BORROW);
+
+    // A pauser holds no other role.
+    assert_eq!(p.queue_config(&pauser, p.default_config()), Err(error(MyError::NoOperationPermission)));
+    assert_eq!(p.queue_ccfee(&pauser, CHAIN, FEE), Err(error(MyError::NoOperationPermission)));
+    assert_eq!(p.init_witnesses(&pauser, vec![pauser]), Err(error(MyError::NoOperationPermission)));
+
+    p.revoke_role(&admin, Role::Pauser, &pauser).unwrap();
+    assert_eq!(p.set_pause(&pauser, 0), Err(error(MyError::NoOperationPermission)));
+}
+
+#[test]
+fn config_timelock() {
+    let mut p = Protocol::bare();
+    let admin = p.admin;
+
+    let mut bad = p.default_config();
+    bad.min_ir = bad.max_ir + 1;
+    assert_eq!(p.queue_config(&admin, bad), Err(error(MyError::IllegalConfig)));
+    let mut bad = p.default_config();
+    bad.commission_rate = 101;
+    assert_eq!(p.queue_config(&admin, bad), Err(error(MyError::IllegalConfig)));
+    let mut bad = p.default_config();
+    bad.min_term = 0;
+    assert_eq!(p.queue_config(&admin, bad), Err(error(MyError::IllegalConfig)));
+    let mut bad = p.default_config();
+    bad.multisig_timeout = 0;
+    assert_eq!(p.queue_config(&admin, bad), Err(error(MyError::IllegalConfig)));
+    let mut bad = p.default_config();
+    bad.grace = MAX_GRACE + 1;
+    assert_eq!(p.queue_config(&admin, bad), Err(error(MyError::IllegalConfig)));
+
+    // The first config applies immediately.
+    p.queue_config(&admin, p.default_config()).unwrap();
+    p.apply_config(&admin).unwrap();
+    assert!(!p.env.exists(&pending_config()));
+    assert_eq!(p.env.get::<Config>(&config()).commission_rate, COMMISSION_RATE);
+    assert!(p.env.get::<Config>(&config()).initialized);
+
+    let mut next = p.default_config();
+    next.commission_rate = 20;
+    p.queue_config(&admin, next).unwrap();
+    assert_eq!(p.env.get::<PendingConfig>(&pending_config()).eta, p.env.now() + CONFIG_DELAY);
+    p.env.warp(CONFIG_DELAY - 1);
+    assert_eq!(p.apply_config(&admin), Err(error(MyError::ChangeStillLocked)));
+    p.env.warp(1);
+    p.apply_config(&admin).unwrap();
+    assert_eq!(p.env.get::<Config>(&config()).commission_rate, 20);
+}
+
+#[test]
+fn ccfee_timelock() {
+    let mut p = Protocol::new();
+    let admin = p.admin;
+    assert_eq!(p.env.get::<CCFee>(&ccfee(CHAIN)).fee, FEE);
+    assert_eq!(p.queue_ccfee(&admin, CHAIN, MAX_CCFEE + 1), Err(error(MyError::IllegalCCFee)));
+
+    p.queue_ccfee(&admin, CHAIN, 2 * FEE).unwrap();
+    assert_eq!(p.apply_ccfee(&admin, CHAIN), Err(error(MyError::ChangeStillLocked)));
+    p.env.warp(CONFIG_DELAY);
+    p.apply_ccfee(&admin, CHAIN).unwrap();
+    assert_eq!(p.env.get::<CCFee>(&ccfee(CHAIN)).fee, 2 * FEE);
+    assert!(!p.env.exists(&pending_ccfee(CHAIN)));
+}
+
+#[test]
+fn treasury_withdrawals_need_threshold_approvals() {
+    let mut p = Protocol::new();
+    let admin = p.admin;
+    let withdrawer = p.env.wallet();
+    let receiver = p.env.wallet();
+    let loan = p.loan(LAMPORTS, 500, 30 * DAY, 1);
+    p.repay(&loan.borrower, loan.receipt_sn).unwrap();
+    let commission = p.env.get::<Treasury>(&treasury()).commission;
+    assert!(commission > 0);
+
+    assert_eq!(set_treasury_threshold(&mut p, &withdrawer, 2), Err(error(MyError::NoOperationPermission)));
+    assert_eq!(set_treasury_threshold(&mut p, &admin, 0), Err(error(MyError::IllegalThreshold)));
+    set_treasury_threshold(&mut p, &admin, 2).unwrap();
+    assert_eq!(
+        propose_withdrawal(&mut p, &withdrawer, TreasuryCategory::Commission, &Pubkey::default(), commission, &receiver),
+        Err(error(MyError::NoOperationPermission))
+    );
+    p.grant_role(&admin, Role::TreasuryWithdrawer, &withdrawer).unwrap();
+
+    let sn = propose_withdrawal(&mut p, &admin, TreasuryCategory::Commission, &Pubkey::default(), commission, &receiver).unwrap();
+    assert_eq!(approve_withdrawal(&mut p, &admin, sn), Err(error(MyError::AlreadySigned)));
+    assert_eq!(execute_withdrawal(&mut p, &admin, sn), Err(error(MyError::CannotSettleWithoutConsensus)));
+    approve_withdrawal(&mut p, &withdrawer, sn).unwrap();
+
+    // Approvals of revoked withdrawers no longer count.
+    p.revoke_role(&admin, Role::TreasuryWithdrawer, &withdrawer).unwrap();
+    assert_eq!(execute_withdrawal(&mut p, &admin, sn), Err(error(MyError::CannotSettleWithoutConsensus)));
+    p.grant_role(&admin, Role::TreasuryWithdrawer, &withdrawer).unwrap();
+
+    let before = p.env.lamports(&receiver);
+    execute_withdrawal(&mut p, &admin, sn).unwrap();
+    assert_eq!(p.env.lamports(&receiver), before + commission);
+    assert_eq!(p.env.get::<Treasury>(&treasury()).commission, 0);
+    assert_eq!(execute_withdrawal(&mut p, &admin, sn), Err(error(MyError::ProposalAlreadyExecuted)));
+    assert_eq!(approve_withdrawal(&mut p, &admin, sn), Err(error(MyError::ProposalAlreadyExecuted)));
+}
+
+#[test]
+fn treasury_withdrawal_categories() {
+    let mut p = Protocol::new();
+    let admin = p.admin;
+    let loan = p.loan(LAMPORTS, 500, 30 * DAY, 1);
+    p.env.warp(30 * DAY + GRACE + 1);
+    p.liquidate(&loan.lender, loan.receipt_sn).unwrap();
+    let rent = p.env.get::<Treasury>(&treasury()).rent;
+
+    let sn = propose_withdrawal(&mut p, &admin, TreasuryCategory::CCFee, &Pubkey::default(), 1, &admin).unwrap();
+    assert_eq!(execute_withdrawal(&mut p, &admin, sn), Err(error(MyError::InsufficientTreasuryBalance)));
+    let sn = propose_withdrawal(&mut p, &admin, TreasuryCategory::Commission, &Pubkey::new_unique(), 1, &admin).unwrap();
+    assert_eq!(execute_withdrawal(&mut p, &admin, sn), Err(error(MyError::MintMismatch)));
+
+    let sn = propose_withdrawal(&mut p, &admin, TreasuryCategory::Rent, &Pubkey::default(), rent, &admin).unwrap();
+    let before = p.env.lamports(&admin);
+    execute_withdrawal(&mut p, &admin, sn).unwrap();
+    assert_eq!(p.env.lamports(&admin), before + rent);
+    assert_eq!(p.env.get::<Treasury>(&treasury()).rent, 0);
+}
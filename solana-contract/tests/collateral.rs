@@ -0,0 +1,340 @@
+use ::solana_lending::*;
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::{system_program, sysvar};
+use solana_lending_testkit::*;
+
+const NATIVE: Pubkey = Pubkey::new_from_array([0; 32]);
+
+fn deposit_collateral(p: &mut Protocol, payer: &Pubkey, amount: u64) -> std::result::Result<(), ProgramError> {
+    p.env.process(
+        accounts::DepositCollateral {
+            user_collateral: mortgage(payer, &NATIVE),
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::DepositCollateral { amount },
+    )
+}
+
+fn withdraw_collateral(p: &mut Protocol, payer: &Pubkey, amount: u64) -> std::result::Result<(), ProgramError> {
+    p.env.process(
+        accounts::WithdrawCollateral {
+            user_collateral: mortgage(payer, &NATIVE),
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::WithdrawCollateral { amount },
+    )
+}
+
+fn freeze_collateral(p: &mut Protocol, payer: &Pubkey, mint: &Pubkey, frozen: u64) -> std::result::Result<u64, ProgramError> {
+    let sn = p.global_state().curr_freeze_sn;
+    p.env.process(
+        accounts::FreezeCollateral {
+            user_collateral: mortgage(payer, mint),
+            freeze: freeze(sn),
+            global: global(),
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::FreezeCollateral {
+            mint: *mint,
+            chainid: CHAIN,
+            order_sn: 7,
+            frozen,
+            amount: frozen / 2,
+            receiver: SOURCE,
+        },
+    )?;
+    Ok(sn)
+}
+
+fn multisig_release(p: &mut Protocol, witness: &Pubkey, freeze_sn: u64, action: ReleaseAction, lender: &Pubkey) -> std::result::Result<(), ProgramError> {
+    p.env.process(
+        accounts::MultisigRelease {
+            multisig: release(freeze_sn),
+            witnesses: witnesses(),
+            global: global(),
+            payer: *witness,
+            system_program: system_program::ID,
+            clock: sysvar::clock::ID,
+        },
+        instruction::MultisigRelease {
+            freeze_sn,
+            action,
+            lender: *lender,
+        },
+    )
+}
+
+fn revoke_release_signature(p: &mut Protocol, witness: &Pubkey, freeze_sn: u64) -> std::result::Result<(), ProgramError> {
+    p.env.process(
+        accounts::RevokeReleaseSignature {
+            multisig: release(freeze_sn),
+            witnesses: witnesses(),
+            payer: *witness,
+        },
+        instruction::RevokeReleaseSignature { freeze_sn },
+    )
+}
+
+fn expire_release(p: &mut Protocol, payer: &Pubkey, freeze_sn: u64) -> std::result::Result<(), ProgramError> {
+    p.env.process(
+        accounts::ExpireRelease {
+            multisig: release(freeze_sn),
+            config: config(),
+            witnesses: witnesses(),
+            payer: *payer,
+            clock: sysvar::clock::ID,
+        },
+        instruction::ExpireRelease { freeze_sn },
+    )
+}
+
+fn release_with_consensus(p: &mut Protocol, freeze_sn: u64, action: ReleaseAction, lender: &Pubkey) {
+    for witness in p.witnesses.clone().iter().take(2) {
+        multisig_release(p, witness, freeze_sn, action, lender).unwrap();
+    }
+}
+
+fn unfreeze_collateral(p: &mut Protocol, payer: &Pubkey, freeze_sn: u64) -> std::result::Result<(), ProgramError> {
+    let f: FrozenCollateral = p.env.get(&freeze(freeze_sn));
+    p.env.process(
+        accounts::UnfreezeCollateral {
+            multisig: release(freeze_sn),
+            witnesses: witnesses(),
+            freeze: freeze(freeze_sn),
+            owner_collateral: mortgage(&f.owner, &f.mint),
+            payer: *payer,
+        },
+        instruction::UnfreezeCollateral { freeze_sn },
+    )
+}
+
+fn liquidate_collateral(p: &mut Protocol, payer: &Pubkey, freeze_sn: u64) -> std::result::Result<(), ProgramError> {
+    let f: FrozenCollateral = p.env.get(&freeze(freeze_sn));
+    let lender = p.env.get::<ReleaseWithMultisig>(&release(freeze_sn)).branches[0].release.lender;
+    p.env.process(
+        accounts::LiquidateCollateral {
+            multisig: release(freeze_sn),
+            witnesses: witnesses(),
+            freeze: freeze(freeze_sn),
+            owner_collateral: mortgage(&f.owner, &f.mint),
+            lender_collateral: mortgage(&lender, &f.mint),
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::LiquidateCollateral { freeze_sn },
+    )
+}
+
+#[test]
+fn deposit_and_withdraw_collateral() {
+    let mut p = Protocol::new();
+    let user = p.env.wallet();
+    deposit_collateral(&mut p, &user, 3 * LAMPORTS).unwrap();
+    withdraw_collateral(&mut p, &user, LAMPORTS).unwrap();
+    assert_eq!(p.env.get::<UserBalance>(&mortgage(&user, &NATIVE)).amount, 2 * LAMPORTS);
+    assert_eq!(withdraw_collateral(&mut p, &user, 3 * LAMPORTS), Err(error(MyError::InsufficientUserBalance)));
+}
+
+#[test]
+fn deposit_and_withdraw_token_collateral() {
+    let mut p = Protocol::new();
+    let mint = p.env.create_mint(6);
+    let user = p.env.wallet();
+    let user_token = p.env.create_token_account(&mint, &user, 1000);
+    p.env
+        .process(
+            accounts::DepositTokenCollateral {
+                user_collateral: mortgage(&user, &mint),
+                vault: vault(&mint),
+                payer_token: user_token,
+                mint,
+                payer: user,
+                token_program: spl_token::ID,
+                system_program: system_program::ID,
+                rent: sysvar::rent::ID,
+            },
+            instruction::DepositTokenCollateral { amount: 700 },
+        )
+        .unwrap();
+    assert_eq!(p.env.token_balance(&vault(&mint)), 700);
+
+    let withdraw = |p: &mut Protocol, amount: u64| {
+        p.env.process(
+            accounts::WithdrawTokenCollateral {
+                user_collateral: mortgage(&user, &mint),
+                vault: vault(&mint),
+                recipient: user_token,
+                mint,
+                payer: user,
+                token_program: spl_token::ID,
+            },
+            instruction::WithdrawTokenCollateral { amount },
+        )
+    };
+    withdraw(&mut p, 300).unwrap();
+    assert_eq!(p.env.token_balance(&user_token), 600);
+    assert_eq!(withdraw(&mut p, 401), Err(error(MyError::InsufficientUserBalance)));
+
+    // Frozen token collateral stays in the vault and only leaves the free amount.
+    let sn = freeze_collateral(&mut p, &user, &mint, 400).unwrap();
+    assert_eq!(p.env.get::<FrozenCollateral>(&freeze(sn)).mint, mint);
+    assert_eq!(withdraw(&mut p, 1), Err(error(MyError::InsufficientUserBalance)));
+}
+
+#[test]
+fn freeze_and_unfreeze() {
+    let mut p = Protocol::new();
+    let owner = p.env.wallet();
+    let lender = p.env.wallet();
+    deposit_collateral(&mut p, &owner, 3 * LAMPORTS).unwrap();
+
+    assert_eq!(freeze_collateral(&mut p, &owner, &NATIVE, 4 * LAMPORTS), Err(error(MyError::InsufficientUserBalance)));
+    let sn = freeze_collateral(&mut p, &owner, &NATIVE, 2 * LAMPORTS).unwrap();
+    let f: FrozenCollateral = p.env.get(&freeze(sn));
+    assert_eq!((f.owner, f.frozen, f.chainid, f.order_sn, f.amount, f.receiver), (owner, 2 * LAMPORTS, CHAIN, 7, LAMPORTS, SOURCE));
+    assert!(f.status == FreezeStatus::Frozen);
+    assert_eq!(p.env.get::<UserBalance>(&mortgage(&owner, &NATIVE)).amount, LAMPORTS);
+    assert_eq!(p.global_state().curr_freeze_sn, sn + 1);
+
+    let [w0, w1, w2] = [p.witnesses[0], p.witnesses[1], p.witnesses[2]];
+    assert_eq!(multisig_release(&mut p, &owner, sn, ReleaseAction::Unfreeze, &lender), Err(error(MyError::NoOperationPermission)));
+    multisig_release(&mut p, &w0, sn, ReleaseAction::Unfreeze, &lender).unwrap();
+    assert_eq!(multisig_release(&mut p, &w0, sn, ReleaseAction::Unfreeze, &lender), Err(error(MyError::AlreadySigned)));
+    assert_eq!(unfreeze_collateral(&mut p, &owner, sn), Err(error(MyError::CannotSettleWithoutConsensus)));
+    multisig_release(&mut p, &w1, sn, ReleaseAction::Liquidate, &lender).unwrap();
+    multisig_release(&mut p, &w2, sn, ReleaseAction::Unfreeze, &lender).unwrap();
+
+    assert_eq!(liquidate_collateral(&mut p, &lender, sn), Err(error(MyError::ReleaseActionMismatch)));
+    unfreeze_collateral(&mut p, &owner, sn).unwrap();
+    assert!(p.env.get::<FrozenCollateral>(&freeze(sn)).status == FreezeStatus::Released);
+    assert_eq!(p.env.get::<UserBalance>(&mortgage(&owner, &NATIVE)).amount, 3 * LAMPORTS);
+    assert_eq!(unfreeze_collateral(&mut p, &owner, sn), Err(error(MyError::CannotSettleWithoutConsensus)));
+    withdraw_collateral(&mut p, &owner, 3 * LAMPORTS).unwrap();
+}
+
+#[test]
+fn liquidate_frozen_collateral() {
+    let mut p = Protocol::new();
+    let owner = p.env.wallet();
+    let lender = p.env.wallet();
+    deposit_collateral(&mut p, &owner, 2 * LAMPORTS).unwrap();
+    let sn = freeze_collateral(&mut p, &owner, &NATIVE, 2 * LAMPORTS).unwrap();
+
+    release_with_consensus(&mut p, sn, ReleaseAction::Liquidate, &lender);
+    assert_eq!(unfreeze_collateral(&mut p, &owner, sn), Err(error(MyError::ReleaseActionMismatch)));
+
+    let before = p.env.lamports(&mortgage(&owner, &NATIVE));
+    p.env.take_events();
+    liquidate_collateral(&mut p, &lender, sn).unwrap();
+    let event = EventLiquidateCollateral::try_from_slice(&p.env.take_events()[0][8..]).unwrap();
+    assert_eq!((event.freeze_sn, event.owner, event.lender, event.frozen), (sn, owner, lender, 2 * LAMPORTS));
+    assert!(p.env.get::<FrozenCollateral>(&freeze(sn)).status == FreezeStatus::Liquidated);
+    assert_eq!(p.env.lamports(&mortgage(&owner, &NATIVE)), before - 2 * LAMPORTS);
+    assert_eq!(p.env.get::<UserBalance>(&mortgage(&lender, &NATIVE)).amount, 2 * LAMPORTS);
+    withdraw_collateral(&mut p, &lender, 2 * LAMPORTS).unwrap();
+}
+
+#[test]
+fn release_rounds_revoke_and_expire() {
+    let mut p = Protocol::new();
+    let owner = p.env.wallet();
+    let lender = p.env.wallet();
+    deposit_collateral(&mut p, &owner, 2 * LAMPORTS).unwrap();
+    let sn = freeze_collateral(&mut p, &owner, &NATIVE, LAMPORTS).unwrap();
+    let [w0, w1, w2] = [p.witnesses[0], p.witnesses[1], p.witnesses[2]];
+
+    multisig_release(&mut p, &w0, sn, ReleaseAction::Unfreeze, &lender).unwrap();
+    multisig_release(&mut p, &w1, sn, ReleaseAction::Liquidate, &lender).unwrap();
+    let multisig: ReleaseWithMultisig = p.env.get(&release(sn));
+    assert_eq!((multisig.start, multisig.threshold, multisig.witnesses), (p.env.now(), 2, vec![w0, w1, w2]));
+    assert_eq!(revoke_release_signature(&mut p, &w2, sn), Err(error(MyError::NotSigned)));
+    p.env.take_events();
+    revoke_release_signature(&mut p, &w1, sn).unwrap();
+    let event = EventRevokeReleaseSignature::try_from_slice(&p.env.take_events()[0][8..]).unwrap();
+    assert_eq!((event.freeze_sn, event.witness, event.signers), (sn, w1, 0));
+    assert!(event.release.action == ReleaseAction::Liquidate);
+    assert_eq!(p.env.get::<ReleaseWithMultisig>(&release(sn)).branches.len(), 1);
+
+    // Nobody else signs in time, so the round expires and starts over with the next signature.
+    assert_eq!(expire_release(&mut p, &owner, sn), Err(error(MyError::MultisigNotExpired)));
+    p.env.warp(MULTISIG_TIMEOUT);
+    expire_release(&mut p, &owner, sn).unwrap();
+    let event = EventReleaseExpired::try_from_slice(&p.env.take_events()[0][8..]).unwrap();
+    assert_eq!((event.freeze_sn, event.time), (sn, p.env.now()));
+    assert_eq!(unfreeze_collateral(&mut p, &owner, sn), Err(error(MyError::CannotSettleWithoutConsensus)));
+    multisig_release(&mut p, &w1, sn, ReleaseAction::Unfreeze, &lender).unwrap();
+    let multisig: ReleaseWithMultisig = p.env.get(&release(sn));
+    assert!(multisig.status == MultisigStatus::InProgress);
+    assert_eq!((multisig.start, multisig.branches[0].signers.clone()), (p.env.now(), vec![w1]));
+    assert_eq!(revoke_release_signature(&mut p, &w0, sn), Err(error(MyError::NotSigned)));
+    multisig_release(&mut p, &w2, sn, ReleaseAction::Unfreeze, &lender).unwrap();
+    assert_eq!(revoke_release_signature(&mut p, &w2, sn), Err(error(MyError::MultisigNotInProgress)));
+    unfreeze_collateral(&mut p, &owner, sn).unwrap();
+}
+
+#[test]
+fn release_rounds_that_lose_consensus_expire() {
+    let mut p = Protocol::new();
+    let owner = p.env.wallet();
+    let lender = p.env.wallet();
+    deposit_collateral(&mut p, &owner, LAMPORTS).unwrap();
+    let sn = freeze_collateral(&mut p, &owner, &NATIVE, LAMPORTS).unwrap();
+    release_with_consensus(&mut p, sn, ReleaseAction::Unfreeze, &lender);
+
+    // Drop the first signer from the witness set by hand.
+    let w0 = p.witnesses[0];
+    let mut account = p.env.accounts[&witnesses()].clone();
+    let mut set: Witnesses = p.env.get(&witnesses());
+    set.v.retain(|w| *w != w0);
+    let mut data = Vec::new();
+    set.try_serialize(&mut data).unwrap();
+    account.data[..data.len()].copy_from_slice(&data);
+    p.env.set_account(witnesses(), account);
+
+    assert_eq!(unfreeze_collateral(&mut p, &owner, sn), Err(error(MyError::ConsensusLost)));
+    assert_eq!(expire_release(&mut p, &owner, sn), Err(error(MyError::MultisigNotExpired)));
+    p.env.warp(MULTISIG_TIMEOUT);
+    expire_release(&mut p, &owner, sn).unwrap();
+    assert!(p.env.get::<ReleaseWithMultisig>(&release(sn)).status == MultisigStatus::Expired);
+}
+
+#[test]
+fn settled_freeze_cannot_be_settled_again() {
+    let mut p = Protocol::new();
+    let owner = p.env.wallet();
+    let lender = p.env.wallet();
+    deposit_collateral(&mut p, &owner, LAMPORTS).unwrap();
+    let sn = freeze_collateral(&mut p, &owner, &NATIVE, LAMPORTS).unwrap();
+    release_with_consensus(&mut p, sn, ReleaseAction::Unfreeze, &lender);
+    unfreeze_collateral(&mut p, &owner, sn).unwrap();
+
+    // Reopen the release round by hand to reach the freeze status check.
+    let mut account = p.env.accounts[&release(sn)].clone();
+    let mut multisig: ReleaseWithMultisig = p.env.get(&release(sn));
+    multisig.status = MultisigStatus::ReachConsensus;
+    let mut data = Vec::new();
+    multisig.try_serialize(&mut data).unwrap();
+    account.data[..data.len()].copy_from_slice(&data);
+    p.env.set_account(release(sn), account);
+    assert_eq!(unfreeze_collateral(&mut p, &owner, sn), Err(error(MyError::CollateralNotFrozen)));
+}
+
+#[test]
+fn paused_freeze_and_release() {
+    let mut p = Protocol::new();
+    let admin = p.admin;
+    let owner = p.env.wallet();
+    deposit_collateral(&mut p, &owner, LAMPORTS).unwrap();
+    let sn = freeze_collateral(&mut p, &owner, &NATIVE, LAMPORTS / 2).unwrap();
+
+    p.set_pause(&admin, PAUSE_FREEZE_COLLATERAL | PAUSE_MULTISIG_RELEASE).unwrap();
+    assert_eq!(freeze_collateral(&mut p, &owner, &NATIVE, LAMPORTS / 2), Err(error(MyError::InstructionPaused)));
+    let w0 = p.witnesses[0];
+    assert_eq!(multisig_release(&mut p, &w0, sn, ReleaseAction::Unfreeze, &owner), Err(error(MyError::InstructionPaused)));
+
+    p.set_pause(&admin, 0).unwrap();
+    freeze_collateral(&mut p, &owner, &NATIVE, LAMPORTS / 2).unwrap();
+}
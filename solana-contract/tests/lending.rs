@@ -0,0 +1,632 @@
+use ::solana_lending::*;
+use anchor_lang::prelude::*;
+use anchor_lang::{InstructionData, ToAccountMetas};
+use anchor_lang::solana_program::{system_program, sysvar};
+use solana_lending_testkit::*;
+
+const RATE: u64 = 500;
+const TERM: u64 = 30 * DAY;
+
+#[test]
+fn deposit_and_withdraw() {
+    let mut p = Protocol::new();
+    let user = p.env.wallet();
+    p.deposit(&user, 5 * LAMPORTS).unwrap();
+    assert_eq!(p.env.get::<UserBalance>(&balance(&user)).amount, 5 * LAMPORTS);
+
+    p.withdraw(&user, 2 * LAMPORTS).unwrap();
+    assert_eq!(p.env.get::<UserBalance>(&balance(&user)).amount, 3 * LAMPORTS);
+    assert_eq!(p.withdraw(&user, 4 * LAMPORTS), Err(error(MyError::InsufficientUserBalance)));
+}
+
+#[test]
+fn place_and_cancel_order() {
+    let mut p = Protocol::new();
+    let lender = p.env.wallet();
+    p.deposit(&lender, 5 * LAMPORTS).unwrap();
+
+    assert_eq!(p.place_order(&lender, 6 * LAMPORTS, RATE, TERM, 1), Err(error(MyError::InsufficientUserBalance)));
+    assert_eq!(p.place_order(&lender, LAMPORTS, MIN_IR - 1, TERM, 1), Err(error(MyError::IllegalInterestRate)));
+    assert_eq!(p.place_order(&lender, LAMPORTS, MAX_IR + 1, TERM, 1), Err(error(MyError::IllegalInterestRate)));
+    assert_eq!(p.place_order(&lender, LAMPORTS, RATE, MIN_TERM - 1, 1), Err(error(MyError::IllegalLoanTerm)));
+    assert_eq!(p.place_order(&lender, LAMPORTS, RATE, MAX_TERM + 1, 1), Err(error(MyError::IllegalLoanTerm)));
+    assert_eq!(p.place_order(&lender, LAMPORTS, RATE, TERM, 0), Err(error(MyError::IllegalInstallments)));
+    assert_eq!(p.place_order(&lender, LAMPORTS, RATE, TERM, MAX_INSTALLMENTS + 1), Err(error(MyError::IllegalInstallments)));
+
+    let sn = p.place_order(&lender, 3 * LAMPORTS, RATE, TERM, 1).unwrap();
+    let o: Order = p.env.get(&order(sn));
+    assert_eq!((o.lender, o.balance, o.rate, o.term, o.installments), (lender, 3 * LAMPORTS, RATE, TERM, 1));
+    assert_eq!(p.env.get::<UserBalance>(&balance(&lender)).amount, 2 * LAMPORTS);
+    assert_eq!(p.global_state().curr_order_sn, sn + 1);
+
+    let other = p.env.wallet();
+    p.deposit(&other, LAMPORTS).unwrap();
+    assert_eq!(p.cancel_order(&other, sn), Err(error(MyError::NoOrderFound)));
+
+    p.cancel_order(&lender, sn).unwrap();
+    assert!(!p.env.exists(&order(sn)));
+    assert_eq!(p.env.get::<UserBalance>(&balance(&lender)).amount, 5 * LAMPORTS);
+}
+
+#[test]
+fn close_order() {
+    let mut p = Protocol::new();
+    let (lender, sn) = p.lender_with_order(LAMPORTS, RATE, TERM, 1);
+    let metas = accounts::CloseOrder {
+        order: order(sn),
+        lender,
+        payer: lender,
+        system_program: system_program::ID,
+    }
+    .to_account_metas(None);
+    let data = instruction::CloseOrder { order_sn: sn }.data();
+    assert_eq!(p.env.process_metas(metas.clone(), data.clone()), Err(error(MyError::CloseOrderWithNonZeroBalance)));
+
+    let borrower = p.env.wallet();
+    p.reach_consensus(1, sn, &borrower, LAMPORTS);
+    p.borrow(&borrower, 1, &borrower).unwrap();
+    let rent = p.env.lamports(&order(sn));
+    let before = p.env.lamports(&lender);
+    p.env.process_metas(metas, data).unwrap();
+    assert!(!p.env.exists(&order(sn)));
+    assert_eq!(p.env.lamports(&lender), before + rent);
+}
+
+#[test]
+fn multisig_collateral_reaches_consensus() {
+    let mut p = Protocol::new();
+    let (_, sn) = p.lender_with_order(5 * LAMPORTS, RATE, TERM, 1);
+    let borrower = p.env.wallet();
+    let [w0, w1, w2] = [p.witnesses[0], p.witnesses[1], p.witnesses[2]];
+    let stranger = p.env.wallet();
+
+    assert_eq!(p.multisig_collateral(&stranger, 1, sn, &borrower, LAMPORTS), Err(error(MyError::NoOperationPermission)));
+    p.multisig_collateral(&w0, 1, sn, &borrower, LAMPORTS).unwrap();
+    assert_eq!(p.multisig_collateral(&w0, 1, sn, &borrower, LAMPORTS), Err(error(MyError::AlreadySigned)));
+    assert_eq!(p.borrow(&borrower, 1, &borrower), Err(error(MyError::CannotBorrowWithoutConsensus)));
+
+    // A witness reporting a different collateral opens its own branch.
+    p.multisig_collateral(&w1, 1, sn, &borrower, 2 * LAMPORTS).unwrap();
+    let multisig: CollateralWithMultisig = p.env.get(&collateral(CHAIN, 1));
+    assert!(multisig.status == MultisigStatus::InProgress);
+    assert_eq!(multisig.branches.len(), 2);
+
+    p.multisig_collateral(&w2, 1, sn, &borrower, LAMPORTS).unwrap();
+    let multisig: CollateralWithMultisig = p.env.get(&collateral(CHAIN, 1));
+    assert!(multisig.status == MultisigStatus::ReachConsensus);
+    assert_eq!(multisig.branches.len(), 1);
+    assert_eq!(multisig.branches[0].signers, vec![w0, w2]);
+    assert_eq!(multisig.branches[0].collateral.amount, LAMPORTS);
+}
+
+#[test]
+fn witness_revokes_and_signs_again() {
+    let mut p = Protocol::new();
+    let (_, sn) = p.lender_with_order(5 * LAMPORTS, RATE, TERM, 1);
+    let borrower = p.env.wallet();
+    let [w0, w1, w2] = [p.witnesses[0], p.witnesses[1], p.witnesses[2]];
+    assert_eq!(p.revoke_collateral_signature(&w0, 1), Err(anchor_error(anchor_lang::error::ErrorCode::AccountNotInitialized)));
+
+    p.multisig_collateral(&w0, 1, sn, &borrower, LAMPORTS).unwrap();
+    p.multisig_collateral(&w1, 1, sn, &borrower, 2 * LAMPORTS).unwrap();
+    assert_eq!(p.revoke_collateral_signature(&w2, 1), Err(error(MyError::NotSigned)));
+    let stranger = p.env.wallet();
+    assert_eq!(p.revoke_collateral_signature(&stranger, 1), Err(error(MyError::NoOperationPermission)));
+
+    // w1 saw a reorged freeze; its branch disappears with its signature.
+    p.env.take_events();
+    p.revoke_collateral_signature(&w1, 1).unwrap();
+    let events = p.env.take_events();
+    let event = EventRevokeCollateralSignature::try_from_slice(&events[0][8..]).unwrap();
+    assert_eq!((event.chainid, event.collateral_sn, event.witness, event.collateral.amount, event.signers), (CHAIN, 1, w1, 2 * LAMPORTS, 0));
+    let multisig: CollateralWithMultisig = p.env.get(&collateral(CHAIN, 1));
+    assert_eq!(multisig.branches.len(), 1);
+    assert_eq!(multisig.branches[0].signers, vec![w0]);
+
+    p.multisig_collateral(&w1, 1, sn, &borrower, LAMPORTS).unwrap();
+    assert!(p.env.get::<CollateralWithMultisig>(&collateral(CHAIN, 1)).status == MultisigStatus::ReachConsensus);
+    assert_eq!(p.revoke_collateral_signature(&w0, 1), Err(error(MyError::MultisigNotInProgress)));
+    p.borrow(&borrower, 1, &borrower).unwrap();
+}
+
+#[test]
+fn borrow_and_clear_collateral() {
+    let mut p = Protocol::new();
+    let (lender, sn) = p.lender_with_order(5 * LAMPORTS, RATE, TERM, 1);
+    let borrower = p.env.wallet();
+    p.reach_consensus(1, sn, &borrower, 2 * LAMPORTS);
+
+    let clear = |p: &mut Protocol| {
+        p.env.process(
+            accounts::ClearCollateral {
+                multisig: collateral(CHAIN, 1),
+                payer: borrower,
+                system_program: system_program::ID,
+            },
+            instruction::ClearCollateral {
+                _chainid: CHAIN,
+                _collateral_sn: 1,
+            },
+        )
+    };
+    assert_eq!(clear(&mut p), Err(error(MyError::CannotClearUnfinishedMultisig)));
+
+    let other = p.env.wallet();
+    assert_eq!(p.borrow(&borrower, 1, &other), Err(anchor_error(anchor_lang::error::ErrorCode::ConstraintRaw)));
+
+    let before = p.env.lamports(&borrower);
+    let receipt_sn = p.borrow(&borrower, 1, &borrower).unwrap();
+    let rent = p.env.lamports(&receipt(receipt_sn));
+    assert_eq!(p.env.lamports(&borrower), before + 2 * LAMPORTS - rent);
+    assert_eq!(p.env.get::<Order>(&order(sn)).balance, 3 * LAMPORTS);
+
+    let r: LoanReceipt = p.env.get(&receipt(receipt_sn));
+    assert_eq!((r.borrower, r.lender, r.amount, r.rate, r.term), (borrower, lender, 2 * LAMPORTS, RATE, TERM));
+    assert_eq!((r.chainid, r.c_sn, r.source, r.token, r.frozen), (CHAIN, 1, SOURCE, TOKEN, 20 * LAMPORTS));
+    assert_eq!((r.time, r.grace), (p.env.now(), GRACE));
+    assert_eq!(r.signers, p.witnesses[..2].to_vec());
+    assert_eq!(p.global_state().curr_receipt_sn, receipt_sn + 1);
+    assert!(p.env.get::<CollateralWithMultisig>(&collateral(CHAIN, 1)).status == MultisigStatus::Finished);
+
+    // A finished collateral cannot be borrowed against twice.
+    assert_eq!(p.borrow(&borrower, 1, &borrower), Err(error(MyError::CannotBorrowWithoutConsensus)));
+    clear(&mut p).unwrap();
+    assert!(p.env.get::<CollateralWithMultisig>(&collateral(CHAIN, 1)).branches.is_empty());
+}
+
+#[test]
+fn split_round_expires() {
+    let mut p = Protocol::new();
+    let (_, sn) = p.lender_with_order(5 * LAMPORTS, RATE, TERM, 1);
+    let borrower = p.env.wallet();
+    let [w0, w1, w2] = [p.witnesses[0], p.witnesses[1], p.witnesses[2]];
+    let start = p.env.now();
+    p.multisig_collateral(&w0, 1, sn, &borrower, LAMPORTS).unwrap();
+    p.multisig_collateral(&w1, 1, sn, &borrower, 2 * LAMPORTS).unwrap();
+    assert_eq!(p.env.get::<CollateralWithMultisig>(&collateral(CHAIN, 1)).start, start);
+
+    let anyone = p.env.wallet();
+    assert_eq!(p.expire_collateral(&anyone, 1), Err(error(MyError::MultisigNotExpired)));
+    assert_eq!(p.clear_collateral(&anyone, 1), Err(error(MyError::CannotClearUnfinishedMultisig)));
+    p.env.warp(MULTISIG_TIMEOUT);
+    p.env.take_events();
+    p.expire_collateral(&anyone, 1).unwrap();
+    let events = p.env.take_events();
+    let event = EventCollateralRejected::try_from_slice(&events[0][8..]).unwrap();
+    assert_eq!((event.chainid, event.collateral_sn, event.start, event.time), (CHAIN, 1, start, p.env.now()));
+
+    // An expired round takes no more signatures and cannot be borrowed against.
+    p.multisig_collateral(&w2, 1, sn, &borrower, LAMPORTS).unwrap();
+    assert!(p.env.get::<CollateralWithMultisig>(&collateral(CHAIN, 1)).status == MultisigStatus::Expired);
+    assert_eq!(p.borrow(&borrower, 1, &borrower), Err(error(MyError::CannotBorrowWithoutConsensus)));
+    assert_eq!(p.expire_collateral(&anyone, 1), Err(error(MyError::MultisigNotInProgress)));
+
+    let rent = p.env.lamports(&collateral(CHAIN, 1));
+    let before = p.env.lamports(&anyone);
+    p.clear_collateral(&anyone, 1).unwrap();
+    assert!(p.env.get::<CollateralWithMultisig>(&collateral(CHAIN, 1)).branches.is_empty());
+    assert_eq!(p.env.lamports(&anyone), before + rent - p.env.lamports(&collateral(CHAIN, 1)));
+}
+
+#[test]
+fn consensus_rounds_do_not_expire() {
+    let mut p = Protocol::new();
+    let (_, sn) = p.lender_with_order(5 * LAMPORTS, RATE, TERM, 1);
+    let borrower = p.env.wallet();
+    p.reach_consensus(1, sn, &borrower, LAMPORTS);
+    p.env.warp(MULTISIG_TIMEOUT);
+    assert_eq!(p.expire_collateral(&borrower, 1), Err(error(MyError::MultisigNotInProgress)));
+    p.borrow(&borrower, 1, &borrower).unwrap();
+}
+
+#[test]
+fn fail_borrow_against_drained_order() {
+    let mut p = Protocol::new();
+    let (_, sn) = p.lender_with_order(LAMPORTS, RATE, TERM, 1);
+    let borrower = p.env.wallet();
+    let anyone = p.env.wallet();
+    // Round 1 reserves the whole order, so round 2 starts without a reservation.
+    p.reach_consensus(1, sn, &borrower, LAMPORTS);
+    p.multisig_collateral(&p.witnesses[0].clone(), 2, sn, &borrower, LAMPORTS).unwrap();
+    assert_eq!(p.fail_borrow(&anyone, 2), Err(error(MyError::CannotBorrowWithoutConsensus)));
+    p.multisig_collateral(&p.witnesses[1].clone(), 2, sn, &borrower, LAMPORTS).unwrap();
+    assert_eq!(p.fail_borrow(&anyone, 1), Err(error(MyError::OrderCanFillBorrow)));
+    assert_eq!(p.borrow(&borrower, 2, &borrower), Err(error(MyError::InsufficientOrderBalance)));
+
+    p.borrow(&borrower, 1, &borrower).unwrap();
+    p.env.take_events();
+    p.fail_borrow(&anyone, 2).unwrap();
+    let events = p.env.take_events();
+    let event = EventBorrowFailed::try_from_slice(&events[0][8..]).unwrap();
+    assert_eq!((event.chainid, event.collateral_sn, event.borrower, event.order_sn, event.amount), (CHAIN, 2, borrower, sn, LAMPORTS));
+    assert!(p.env.get::<CollateralWithMultisig>(&collateral(CHAIN, 2)).status == MultisigStatus::Failed);
+    assert_eq!(p.borrow(&borrower, 2, &borrower), Err(error(MyError::CannotBorrowWithoutConsensus)));
+    assert_eq!(p.fail_borrow(&anyone, 2), Err(error(MyError::CannotBorrowWithoutConsensus)));
+    p.clear_collateral(&anyone, 2).unwrap();
+}
+
+#[test]
+fn fail_borrow_against_cancelled_order() {
+    let mut p = Protocol::new();
+    let (lender, sn) = p.lender_with_order(LAMPORTS, RATE, TERM, 1);
+    let borrower = p.env.wallet();
+    p.cancel_order(&lender, sn).unwrap();
+    assert!(!p.env.exists(&order(sn)));
+    p.reach_consensus(1, sn, &borrower, LAMPORTS);
+
+    p.fail_borrow(&borrower, 1).unwrap();
+    assert!(p.env.get::<CollateralWithMultisig>(&collateral(CHAIN, 1)).status == MultisigStatus::Failed);
+}
+
+#[test]
+fn first_signature_reserves_the_order() {
+    let mut p = Protocol::new();
+    let (lender, sn) = p.lender_with_order(3 * LAMPORTS, RATE, TERM, 1);
+    let borrower = p.env.wallet();
+    p.multisig_collateral(&p.witnesses[0].clone(), 1, sn, &borrower, 2 * LAMPORTS).unwrap();
+    let r = p.env.get::<CollateralWithMultisig>(&collateral(CHAIN, 1)).reservation;
+    assert_eq!((r.order_sn, r.borrower, r.amount, r.expiry), (sn, borrower, 2 * LAMPORTS, p.env.now() + MULTISIG_TIMEOUT));
+    assert_eq!(p.env.get::<Order>(&order(sn)).reserved, 2 * LAMPORTS);
+
+    // The lender can only take back what is not reserved, and the order stays open for the round.
+    let before = p.env.get::<UserBalance>(&balance(&lender)).amount;
+    p.cancel_order(&lender, sn).unwrap();
+    assert_eq!(p.env.get::<UserBalance>(&balance(&lender)).amount, before + LAMPORTS);
+    assert_eq!(p.env.get::<Order>(&order(sn)).balance, 2 * LAMPORTS);
+    assert_eq!(p.release_reservation(&lender, 1), Err(error(MyError::ReservationStillActive)));
+
+    p.multisig_collateral(&p.witnesses[1].clone(), 1, sn, &borrower, 2 * LAMPORTS).unwrap();
+    p.borrow(&borrower, 1, &borrower).unwrap();
+    let o: Order = p.env.get(&order(sn));
+    assert_eq!((o.balance, o.reserved), (0, 0));
+    assert_eq!(p.env.get::<CollateralWithMultisig>(&collateral(CHAIN, 1)).reservation.amount, 0);
+    assert_eq!(p.release_reservation(&lender, 1), Err(error(MyError::NoReservation)));
+}
+
+#[test]
+fn borrower_reserves_a_signed_round() {
+    let mut p = Protocol::new();
+    let (_, sn) = p.lender_with_order(2 * LAMPORTS, RATE, TERM, 1);
+    let (_, elsewhere) = p.lender_with_order(2 * LAMPORTS, RATE, TERM, 1);
+    let borrower = p.env.wallet();
+    let other = p.env.wallet();
+    let witness = p.witnesses[0];
+
+    // Nobody can reserve for a round the witnesses have not signed.
+    assert_eq!(p.reserve_order(&borrower, 1, sn, LAMPORTS), Err(anchor_error(anchor_lang::error::ErrorCode::AccountNotInitialized)));
+
+    // Another round reserves half the order, so the borrower's first signature cannot.
+    p.multisig_collateral(&witness, 2, sn, &other, LAMPORTS).unwrap();
+    p.multisig_collateral(&witness, 1, sn, &borrower, 2 * LAMPORTS).unwrap();
+    assert_eq!(p.env.get::<CollateralWithMultisig>(&collateral(CHAIN, 1)).reservation.amount, 0);
+
+    // Only the attested borrower can reserve, on the attested order, up to the attested amount.
+    assert_eq!(p.reserve_order(&other, 1, sn, LAMPORTS), Err(error(MyError::NoOperationPermission)));
+    assert_eq!(p.reserve_order(&borrower, 1, elsewhere, LAMPORTS), Err(error(MyError::NoOperationPermission)));
+    assert_eq!(p.reserve_order(&borrower, 1, sn, 3 * LAMPORTS), Err(error(MyError::IllegalReserveAmount)));
+    assert_eq!(p.reserve_order(&borrower, 1, sn, 2 * LAMPORTS), Err(error(MyError::InsufficientOrderBalance)));
+
+    // A reservation left unused can be released by anyone once it expires.
+    p.env.warp(MULTISIG_TIMEOUT - 1);
+    assert_eq!(p.release_reservation(&borrower, 2), Err(error(MyError::ReservationStillActive)));
+    p.env.warp(1);
+    p.release_reservation(&borrower, 2).unwrap();
+    p.reserve_order(&borrower, 1, sn, 2 * LAMPORTS).unwrap();
+    assert_eq!(p.reserve_order(&borrower, 1, sn, 2 * LAMPORTS), Err(error(MyError::AlreadyReserved)));
+    assert_eq!(p.env.get::<Order>(&order(sn)).reserved, 2 * LAMPORTS);
+
+    p.multisig_collateral(&p.witnesses[1].clone(), 1, sn, &borrower, 2 * LAMPORTS).unwrap();
+    p.borrow(&borrower, 1, &borrower).unwrap();
+    let o: Order = p.env.get(&order(sn));
+    assert_eq!((o.balance, o.reserved), (0, 0));
+}
+
+#[test]
+fn borrow_requires_the_chain_ccfee() {
+    let mut p = Protocol::new();
+    let admin = p.admin;
+    let (_, sn) = p.lender_with_order(2 * LAMPORTS, RATE, TERM, 1);
+    let borrower = p.env.wallet();
+    let fresh = CHAIN + 1;
+    for witness in p.witnesses.clone().iter().take(2) {
+        p.env
+            .process(
+                accounts::MultisigCollateral {
+                    multisig: collateral(fresh, 1),
+                    order: order(sn),
+                    config: config(),
+                    witnesses: witnesses(),
+                    global: global(),
+                    reward: reward(witness),
+                    payer: *witness,
+                    system_program: system_program::ID,
+                    clock: sysvar::clock::ID,
+                },
+                instruction::MultisigCollateral {
+                    chainid: fresh,
+                    collateral_sn: 1,
+                    source: SOURCE,
+                    token: TOKEN,
+                    frozen: 10 * LAMPORTS,
+                    order_sn: sn,
+                    borrower,
+                    amount: LAMPORTS,
+                },
+            )
+            .unwrap();
+    }
+    let borrow = |p: &mut Protocol| {
+        p.env.process(
+            accounts::Borrow {
+                multisig: collateral(fresh, 1),
+                witnesses: witnesses(),
+                receipt: receipt(p.global_state().curr_receipt_sn),
+                order: order(sn),
+                global: global(),
+                config: config(),
+                cc_fee: ccfee(fresh),
+                recipient: borrower,
+                payer: borrower,
+                system_program: system_program::ID,
+                clock: sysvar::clock::ID,
+            },
+            instruction::Borrow { _chainid: fresh, _collateral_sn: 1 },
+        )
+    };
+
+    // Nothing could be repaid to a chain without a cross-chain fee, so nothing is lent on it.
+    assert_eq!(borrow(&mut p), Err(anchor_error(anchor_lang::error::ErrorCode::AccountNotInitialized)));
+    p.queue_ccfee(&admin, fresh, FEE).unwrap();
+    p.env.warp(CONFIG_DELAY);
+    p.apply_ccfee(&admin, fresh).unwrap();
+    borrow(&mut p).unwrap();
+}
+
+#[test]
+fn borrow_more_than_order_balance() {
+    let mut p = Protocol::new();
+    let (_, sn) = p.lender_with_order(LAMPORTS, RATE, TERM, 1);
+    let borrower = p.env.wallet();
+    p.reach_consensus(1, sn, &borrower, 2 * LAMPORTS);
+    assert_eq!(p.borrow(&borrower, 1, &borrower), Err(error(MyError::InsufficientOrderBalance)));
+}
+
+#[test]
+fn repay_in_full() {
+    let mut p = Protocol::new();
+    let loan = p.loan(LAMPORTS, RATE, TERM, 1);
+    p.env.warp(10 * DAY);
+
+    let stranger = p.env.wallet();
+    assert_eq!(p.repay(&stranger, loan.receipt_sn), Err(error(MyError::NoReceiptFound)));
+    assert_eq!(p.repay_partial(&loan.borrower, loan.receipt_sn, 0), Err(error(MyError::IllegalRepayAmount)));
+    assert_eq!(p.repay_partial(&loan.borrower, loan.receipt_sn, LAMPORTS * 2), Err(error(MyError::IllegalRepayAmount)));
+
+    let interest = LAMPORTS * RATE / 10000;
+    let commission = interest * COMMISSION_RATE / 100;
+    let rent = p.env.lamports(&receipt(loan.receipt_sn));
+    let borrower_before = p.env.lamports(&loan.borrower);
+    let treasury_before = p.env.lamports(&treasury());
+    let pool_before = p.env.lamports(&reward_pool());
+    p.repay(&loan.borrower, loan.receipt_sn).unwrap();
+
+    assert!(!p.env.exists(&receipt(loan.receipt_sn)));
+    assert_eq!(p.env.lamports(&loan.borrower), borrower_before - LAMPORTS - interest - FEE);
+    assert_eq!(p.env.get::<UserBalance>(&balance(&loan.lender)).amount, LAMPORTS + interest - commission);
+    assert_eq!(p.env.lamports(&treasury()), treasury_before + commission + rent);
+    assert_eq!(p.env.lamports(&reward_pool()), pool_before + FEE);
+
+    let t: Treasury = p.env.get(&treasury());
+    assert_eq!((t.commission, t.ccfee, t.rent), (commission, 0, rent));
+    let rewards: Vec<u64> = p.witnesses[..2].iter().map(|w| p.env.get::<WitnessReward>(&reward(w)).amount).collect();
+    assert_eq!(rewards, vec![FEE / 2, FEE / 2]);
+    assert!(!p.env.exists(&reward(&p.witnesses[2])));
+}
+
+#[test]
+fn repay_credits_only_the_lender() {
+    let mut p = Protocol::new();
+    let loan = p.loan(LAMPORTS, RATE, TERM, 1);
+    let other = p.env.wallet();
+    p.deposit(&other, LAMPORTS).unwrap();
+
+    let accounts = accounts::Repay {
+        lender_balance: balance(&other),
+        ..p.repay_accounts(&loan.borrower, loan.receipt_sn)
+    };
+    assert_eq!(
+        p.env.process(accounts, instruction::Repay { receipt_sn: loan.receipt_sn }),
+        Err(anchor_error(anchor_lang::error::ErrorCode::ConstraintSeeds))
+    );
+    assert_eq!(p.env.get::<UserBalance>(&balance(&other)).amount, LAMPORTS);
+    assert!(p.env.exists(&receipt(loan.receipt_sn)));
+}
+
+#[test]
+fn repay_requires_signer_reward_accounts() {
+    let mut p = Protocol::new();
+    let loan = p.loan(LAMPORTS, RATE, TERM, 1);
+    let mut metas = p.with_rewards(p.repay_accounts(&loan.borrower, loan.receipt_sn), loan.receipt_sn);
+    let data = instruction::Repay { receipt_sn: loan.receipt_sn }.data();
+    let n = metas.len();
+
+    assert_eq!(p.env.process_metas(metas[..n - 1].to_vec(), data.clone()), Err(error(MyError::RewardAccountMismatch)));
+    metas.swap(n - 1, n - 2);
+    assert_eq!(p.env.process_metas(metas.clone(), data.clone()), Err(error(MyError::RewardAccountMismatch)));
+    metas[n - 1] = AccountMeta::new(balance(&loan.lender), false);
+    assert!(p.env.process_metas(metas, data).is_err());
+    assert!(p.env.exists(&receipt(loan.receipt_sn)));
+}
+
+#[test]
+fn repay_installments_with_penalty() {
+    let mut p = Protocol::new();
+    let loan = p.loan(LAMPORTS, RATE, TERM, 2);
+    let total = LAMPORTS + LAMPORTS * RATE / 10000;
+
+    // The first installment is due after half the term; paying it a day late costs two days
+    // of penalty interest on its principal.
+    p.env.warp(TERM / 2 + DAY);
+    let penalty = (LAMPORTS / 2) * PENALTY_IR / 1000 * 2;
+    let commission = (total / 2 - LAMPORTS / 2 + penalty) * COMMISSION_RATE / 100;
+    let before = p.env.lamports(&loan.borrower);
+    p.repay_partial(&loan.borrower, loan.receipt_sn, total / 2).unwrap();
+    assert_eq!(p.env.lamports(&loan.borrower), before - total / 2 - penalty - FEE);
+    assert_eq!(p.env.get::<UserBalance>(&balance(&loan.lender)).amount, total / 2 + penalty - commission);
+
+    let r: LoanReceipt = p.env.get(&receipt(loan.receipt_sn));
+    assert_eq!((r.repaid, r.released), (total / 2, r.frozen / 2));
+
+    p.repay(&loan.borrower, loan.receipt_sn).unwrap();
+    assert!(!p.env.exists(&receipt(loan.receipt_sn)));
+}
+
+#[test]
+fn extend_loan() {
+    let mut p = Protocol::new();
+    let loan = p.loan(LAMPORTS, RATE, TERM, 1);
+    let time = p.env.now();
+    p.env.warp(10 * DAY);
+
+    let stranger = p.env.wallet();
+    assert_eq!(p.extend_loan(&stranger, loan.receipt_sn, RATE, None), Err(error(MyError::NoReceiptFound)));
+    assert_eq!(p.extend_loan(&loan.borrower, loan.receipt_sn, RATE + 100, None), Err(error(MyError::LenderApprovalRequired)));
+    assert_eq!(p.extend_loan(&loan.borrower, loan.receipt_sn, MAX_IR + 1, Some(loan.lender)), Err(error(MyError::IllegalInterestRate)));
+
+    let interest = LAMPORTS * RATE / 10000;
+    let commission = interest * COMMISSION_RATE / 100;
+    p.extend_loan(&loan.borrower, loan.receipt_sn, RATE, None).unwrap();
+    let r: LoanReceipt = p.env.get(&receipt(loan.receipt_sn));
+    assert_eq!((r.time, r.rate, r.repaid), (time + TERM, RATE, 0));
+    assert_eq!(p.env.get::<UserBalance>(&balance(&loan.lender)).amount, interest - commission);
+
+    p.extend_loan(&loan.borrower, loan.receipt_sn, RATE + 100, Some(loan.lender)).unwrap();
+    let r: LoanReceipt = p.env.get(&receipt(loan.receipt_sn));
+    assert_eq!((r.time, r.rate), (time + 2 * TERM, RATE + 100));
+
+    p.env.warp(3 * TERM);
+    assert_eq!(p.extend_loan(&loan.borrower, loan.receipt_sn, RATE + 100, None), Err(error(MyError::CannotExtendLoan)));
+}
+
+#[test]
+fn extend_installment_loan() {
+    let mut p = Protocol::new();
+    let loan = p.loan(LAMPORTS, RATE, TERM, 3);
+    assert_eq!(p.extend_loan(&loan.borrower, loan.receipt_sn, RATE, None), Err(error(MyError::CannotExtendLoan)));
+}
+
+#[test]
+fn liquidate_after_grace() {
+    let mut p = Protocol::new();
+    let loan = p.loan(LAMPORTS, RATE, TERM, 1);
+
+    assert_eq!(p.liquidate(&loan.borrower, loan.receipt_sn), Err(error(MyError::NoReceiptFound)));
+    p.env.warp(TERM + GRACE);
+    assert_eq!(p.liquidate(&loan.lender, loan.receipt_sn), Err(error(MyError::DeadlineNotMeet)));
+
+    p.env.warp(1);
+    let rent = p.env.lamports(&receipt(loan.receipt_sn));
+    let before = p.env.lamports(&loan.lender);
+    p.liquidate(&loan.lender, loan.receipt_sn).unwrap();
+    assert!(!p.env.exists(&receipt(loan.receipt_sn)));
+    assert_eq!(p.env.lamports(&loan.lender), before - FEE);
+    assert_eq!(p.env.get::<Treasury>(&treasury()).rent, rent);
+    assert_eq!(p.env.get::<WitnessReward>(&reward(&p.witnesses[1])).amount, FEE / 2);
+}
+
+#[test]
+fn auto_reinvest_repayments() {
+    let mut p = Protocol::new();
+    let loan = p.loan(LAMPORTS, RATE, TERM, 1);
+    let enable = |p: &mut Protocol, payer: Pubkey, order_sn: u64| {
+        p.env.process(
+            accounts::EnableAutoReinvest {
+                order: order(order_sn),
+                reinvest: reinvest(&payer, &Pubkey::default()),
+                payer,
+                system_program: system_program::ID,
+            },
+            instruction::EnableAutoReinvest { order_sn },
+        )
+    };
+    assert_eq!(enable(&mut p, loan.borrower, loan.order_sn), Err(error(MyError::NoOrderFound)));
+    enable(&mut p, loan.lender, loan.order_sn).unwrap();
+    assert_eq!(p.env.get::<AutoReinvest>(&reinvest(&loan.lender, &Pubkey::default())).order_sn, loan.order_sn);
+
+    // The order must be the one named by the setting.
+    let accounts = accounts::Repay {
+        reinvest_order: order(loan.order_sn + 1),
+        ..p.repay_accounts(&loan.borrower, loan.receipt_sn)
+    };
+    assert_eq!(p.env.process(accounts, instruction::Repay { receipt_sn: loan.receipt_sn }), Err(error(MyError::ReinvestOrderMismatch)));
+
+    let interest = LAMPORTS * RATE / 10000;
+    let commission = interest * COMMISSION_RATE / 100;
+    let before = p.env.get::<Order>(&order(loan.order_sn)).balance;
+    p.repay(&loan.borrower, loan.receipt_sn).unwrap();
+    assert_eq!(p.env.get::<Order>(&order(loan.order_sn)).balance, before + LAMPORTS + interest - commission);
+    assert_eq!(p.env.get::<UserBalance>(&balance(&loan.lender)).amount, 0);
+
+    p.env
+        .process(
+            accounts::DisableAutoReinvest {
+                reinvest: reinvest(&loan.lender, &Pubkey::default()),
+                payer: loan.lender,
+                system_program: system_program::ID,
+            },
+            instruction::DisableAutoReinvest { mint: Pubkey::default() },
+        )
+        .unwrap();
+    assert!(!p.env.exists(&reinvest(&loan.lender, &Pubkey::default())));
+}
+
+#[test]
+fn reinvest_falls_back_to_balance_after_cancel() {
+    let mut p = Protocol::new();
+    let loan = p.loan(LAMPORTS, RATE, TERM, 1);
+    p.env
+        .process(
+            accounts::EnableAutoReinvest {
+                order: order(loan.order_sn),
+                reinvest: reinvest(&loan.lender, &Pubkey::default()),
+                payer: loan.lender,
+                system_program: system_program::ID,
+            },
+            instruction::EnableAutoReinvest { order_sn: loan.order_sn },
+        )
+        .unwrap();
+    p.cancel_order(&loan.lender, loan.order_sn).unwrap();
+    let before = p.env.get::<UserBalance>(&balance(&loan.lender)).amount;
+
+    p.repay(&loan.borrower, loan.receipt_sn).unwrap();
+    let interest = LAMPORTS * RATE / 10000;
+    let commission = interest * COMMISSION_RATE / 100;
+    assert_eq!(p.env.get::<UserBalance>(&balance(&loan.lender)).amount, before + LAMPORTS + interest - commission);
+}
+
+#[test]
+fn paused_instructions() {
+    let mut p = Protocol::new();
+    let admin = p.admin;
+    let (lender, sn) = p.lender_with_order(5 * LAMPORTS, RATE, TERM, 1);
+    let borrower = p.env.wallet();
+
+    let stranger = p.env.wallet();
+    assert_eq!(p.set_pause(&stranger, PAUSE_BORROW), Err(error(MyError::NoOperationPermission)));
+
+    p.set_pause(&admin, PAUSE_PLACE_ORDER | PAUSE_MULTISIG_COLLATERAL).unwrap();
+    assert_eq!(p.place_order(&lender, 0, RATE, TERM, 1), Err(error(MyError::InstructionPaused)));
+    let w0 = p.witnesses[0];
+    assert_eq!(p.multisig_collateral(&w0, 1, sn, &borrower, LAMPORTS), Err(error(MyError::InstructionPaused)));
+
+    p.set_pause(&admin, PAUSE_BORROW | PAUSE_EXTEND_LOAN).unwrap();
+    p.reach_consensus(1, sn, &borrower, LAMPORTS);
+    assert_eq!(p.borrow(&borrower, 1, &borrower), Err(error(MyError::InstructionPaused)));
+
+    p.set_pause(&admin, PAUSE_EXTEND_LOAN).unwrap();
+    let receipt_sn = p.borrow(&borrower, 1, &borrower).unwrap();
+    assert_eq!(p.extend_loan(&borrower, receipt_sn, RATE, None), Err(error(MyError::InstructionPaused)));
+
+    // Repayments are never paused.
+    p.repay(&borrower, receipt_sn).unwrap();
+}
@@ -0,0 +1,353 @@
+use ::solana_lending::*;
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::{system_program, sysvar};
+use anchor_lang::InstructionData;
+use solana_lending_testkit::*;
+
+const RATE: u64 = 500;
+const TERM: u64 = 30 * DAY;
+const AMOUNT: u64 = 1_000_000_000;
+
+struct TokenLoan {
+    mint: Pubkey,
+    lender: Pubkey,
+    borrower: Pubkey,
+    borrower_token: Pubkey,
+    order_sn: u64,
+    receipt_sn: u64,
+}
+
+fn deposit_token(p: &mut Protocol, payer: &Pubkey, payer_token: &Pubkey, mint: &Pubkey, amount: u64) -> std::result::Result<(), ProgramError> {
+    p.env.process(
+        accounts::DepositToken {
+            user_balance: token_balance(payer, mint),
+            vault: vault(mint),
+            payer_token: *payer_token,
+            mint: *mint,
+            payer: *payer,
+            token_program: spl_token::ID,
+            system_program: system_program::ID,
+            rent: sysvar::rent::ID,
+        },
+        instruction::DepositToken { amount },
+    )
+}
+
+fn withdraw_token(p: &mut Protocol, payer: &Pubkey, recipient: &Pubkey, mint: &Pubkey, amount: u64) -> std::result::Result<(), ProgramError> {
+    p.env.process(
+        accounts::WithdrawToken {
+            user_balance: token_balance(payer, mint),
+            vault: vault(mint),
+            recipient: *recipient,
+            mint: *mint,
+            payer: *payer,
+            token_program: spl_token::ID,
+        },
+        instruction::WithdrawToken { amount },
+    )
+}
+
+fn place_token_order(p: &mut Protocol, payer: &Pubkey, mint: &Pubkey, amount: u64, installments: u8) -> std::result::Result<u64, ProgramError> {
+    let sn = p.global_state().curr_order_sn;
+    p.env.process(
+        accounts::PlaceTokenOrder {
+            order: order(sn),
+            user_balance: token_balance(payer, mint),
+            mint: *mint,
+            global: global(),
+            config: config(),
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::PlaceTokenOrder {
+            amount,
+            rate: RATE,
+            term: TERM,
+            installments,
+        },
+    )?;
+    Ok(sn)
+}
+
+fn cancel_token_order(p: &mut Protocol, payer: &Pubkey, mint: &Pubkey, order_sn: u64) -> std::result::Result<(), ProgramError> {
+    p.env.process(
+        accounts::CancelTokenOrder {
+            order: order(order_sn),
+            user_balance: token_balance(payer, mint),
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::CancelTokenOrder { order_sn },
+    )
+}
+
+fn borrow_token(p: &mut Protocol, payer: &Pubkey, collateral_sn: u64, recipient: &Pubkey) -> std::result::Result<u64, ProgramError> {
+    let sn = p.global_state().curr_receipt_sn;
+    let multisig: CollateralWithMultisig = p.env.get(&collateral(CHAIN, collateral_sn));
+    let order_sn = multisig.branches[0].collateral.order_sn;
+    let mint = p.env.get::<Order>(&order(order_sn)).mint;
+    p.env.process(
+        accounts::BorrowToken {
+            multisig: collateral(CHAIN, collateral_sn),
+            witnesses: witnesses(),
+            receipt: receipt(sn),
+            order: order(order_sn),
+            vault: vault(&mint),
+            global: global(),
+            config: config(),
+            cc_fee: ccfee(CHAIN),
+            recipient: *recipient,
+            payer: *payer,
+            token_program: spl_token::ID,
+            system_program: system_program::ID,
+            clock: sysvar::clock::ID,
+        },
+        instruction::BorrowToken {
+            _chainid: CHAIN,
+            _collateral_sn: collateral_sn,
+        },
+    )?;
+    Ok(sn)
+}
+
+fn repay_token(p: &mut Protocol, loan: &TokenLoan, amount: Option<u64>) -> std::result::Result<(), ProgramError> {
+    let accounts = accounts::RepayToken {
+        receipt: receipt(loan.receipt_sn),
+        lender_balance: token_balance(&loan.lender, &loan.mint),
+        vault: vault(&loan.mint),
+        reinvest: reinvest(&loan.lender, &loan.mint),
+        reinvest_order: order(loan.order_sn),
+        payer_token: loan.borrower_token,
+        config: config(),
+        treasury: treasury(),
+        treasury_balance: token_balance(&treasury(), &loan.mint),
+        cc_fee: ccfee(CHAIN),
+        reward_pool: reward_pool(),
+        payer: loan.borrower,
+        token_program: spl_token::ID,
+        system_program: system_program::ID,
+        clock: sysvar::clock::ID,
+    };
+    let metas = p.with_rewards(accounts, loan.receipt_sn);
+    match amount {
+        Some(amount) => p.env.process_metas(metas, instruction::RepayTokenPartial { receipt_sn: loan.receipt_sn, amount }.data()),
+        None => p.env.process_metas(metas, instruction::RepayToken { receipt_sn: loan.receipt_sn }.data()),
+    }
+}
+
+fn extend_token_loan(p: &mut Protocol, loan: &TokenLoan, rate: u64, lender: Option<Pubkey>) -> std::result::Result<(), ProgramError> {
+    let metas = p.with_rewards(
+        accounts::ExtendTokenLoan {
+            receipt: receipt(loan.receipt_sn),
+            lender_balance: token_balance(&loan.lender, &loan.mint),
+            vault: vault(&loan.mint),
+            payer_token: loan.borrower_token,
+            global: global(),
+            config: config(),
+            treasury: treasury(),
+            treasury_balance: token_balance(&treasury(), &loan.mint),
+            cc_fee: ccfee(CHAIN),
+            reward_pool: reward_pool(),
+            lender,
+            payer: loan.borrower,
+            token_program: spl_token::ID,
+            system_program: system_program::ID,
+            clock: sysvar::clock::ID,
+        },
+        loan.receipt_sn,
+    );
+    p.env.process_metas(metas, instruction::ExtendTokenLoan { receipt_sn: loan.receipt_sn, rate }.data())
+}
+
+fn token_loan(p: &mut Protocol, installments: u8) -> TokenLoan {
+    let mint = p.env.create_mint(6);
+    let lender = p.env.wallet();
+    let lender_token = p.env.create_token_account(&mint, &lender, 10 * AMOUNT);
+    deposit_token(p, &lender, &lender_token, &mint, 10 * AMOUNT).unwrap();
+    let order_sn = place_token_order(p, &lender, &mint, 10 * AMOUNT, installments).unwrap();
+
+    let borrower = p.env.wallet();
+    let borrower_token = p.env.create_token_account(&mint, &borrower, AMOUNT);
+    let collateral_sn = p.next_collateral_sn();
+    p.reach_consensus(collateral_sn, order_sn, &borrower, AMOUNT);
+    let receipt_sn = borrow_token(p, &borrower, collateral_sn, &borrower_token).unwrap();
+    TokenLoan {
+        mint,
+        lender,
+        borrower,
+        borrower_token,
+        order_sn,
+        receipt_sn,
+    }
+}
+
+#[test]
+fn deposit_and_withdraw_tokens() {
+    let mut p = Protocol::new();
+    let mint = p.env.create_mint(6);
+    let user = p.env.wallet();
+    let user_token = p.env.create_token_account(&mint, &user, AMOUNT);
+
+    deposit_token(&mut p, &user, &user_token, &mint, 600).unwrap();
+    assert_eq!(p.env.token_balance(&vault(&mint)), 600);
+    assert_eq!(p.env.token_balance(&user_token), AMOUNT - 600);
+    assert_eq!(p.env.get::<UserBalance>(&token_balance(&user, &mint)).amount, 600);
+
+    let recipient = p.env.create_token_account(&mint, &Pubkey::new_unique(), 0);
+    withdraw_token(&mut p, &user, &recipient, &mint, 200).unwrap();
+    assert_eq!(p.env.token_balance(&recipient), 200);
+    assert_eq!(p.env.token_balance(&vault(&mint)), 400);
+    assert_eq!(withdraw_token(&mut p, &user, &recipient, &mint, 401), Err(error(MyError::InsufficientUserBalance)));
+}
+
+#[test]
+fn place_and_cancel_token_order() {
+    let mut p = Protocol::new();
+    let mint = p.env.create_mint(6);
+    let lender = p.env.wallet();
+    let lender_token = p.env.create_token_account(&mint, &lender, AMOUNT);
+    deposit_token(&mut p, &lender, &lender_token, &mint, AMOUNT).unwrap();
+
+    assert_eq!(place_token_order(&mut p, &lender, &mint, AMOUNT + 1, 1), Err(error(MyError::InsufficientUserBalance)));
+    assert_eq!(place_token_order(&mut p, &lender, &mint, AMOUNT, 0), Err(error(MyError::IllegalInstallments)));
+    let sn = place_token_order(&mut p, &lender, &mint, AMOUNT, 1).unwrap();
+    assert_eq!(p.env.get::<Order>(&order(sn)).mint, mint);
+    assert_eq!(p.env.get::<UserBalance>(&token_balance(&lender, &mint)).amount, 0);
+
+    // Native order instructions refuse token orders.
+    assert_eq!(p.cancel_order(&lender, sn), Err(anchor_error(anchor_lang::error::ErrorCode::AccountNotInitialized)));
+    p.deposit(&lender, 1).unwrap();
+    assert_eq!(p.cancel_order(&lender, sn), Err(error(MyError::MintMismatch)));
+    let borrower = p.env.wallet();
+    p.reach_consensus(1, sn, &borrower, AMOUNT);
+    assert_eq!(p.borrow(&borrower, 1, &borrower), Err(error(MyError::MintMismatch)));
+
+    let other = p.env.wallet();
+    let other_token = p.env.create_token_account(&mint, &other, 1);
+    deposit_token(&mut p, &other, &other_token, &mint, 1).unwrap();
+    assert_eq!(cancel_token_order(&mut p, &other, &mint, sn), Err(error(MyError::NoOrderFound)));
+
+    // The round reserved the order until its reservation expires.
+    p.env.warp(MULTISIG_TIMEOUT);
+    p.release_reservation(&lender, 1).unwrap();
+    cancel_token_order(&mut p, &lender, &mint, sn).unwrap();
+    assert!(!p.env.exists(&order(sn)));
+    assert_eq!(p.env.get::<UserBalance>(&token_balance(&lender, &mint)).amount, AMOUNT);
+}
+
+#[test]
+fn token_loan_lifecycle() {
+    let mut p = Protocol::new();
+    let loan = token_loan(&mut p, 1);
+    assert_eq!(p.env.token_balance(&loan.borrower_token), 2 * AMOUNT);
+    assert_eq!(p.env.token_balance(&vault(&loan.mint)), 9 * AMOUNT);
+    assert_eq!(p.env.get::<Order>(&order(loan.order_sn)).balance, 9 * AMOUNT);
+    let r: LoanReceipt = p.env.get(&receipt(loan.receipt_sn));
+    assert_eq!((r.mint, r.amount, r.borrower, r.lender), (loan.mint, AMOUNT, loan.borrower, loan.lender));
+
+    // Native repayment refuses token receipts.
+    p.deposit(&loan.lender, 1).unwrap();
+    assert_eq!(p.repay(&loan.borrower, loan.receipt_sn), Err(error(MyError::MintMismatch)));
+
+    p.env.warp(10 * DAY);
+    let interest = AMOUNT * RATE / 10000;
+    let commission = interest * COMMISSION_RATE / 100;
+    let sol_before = p.env.lamports(&loan.borrower);
+    repay_token(&mut p, &loan, None).unwrap();
+    assert!(!p.env.exists(&receipt(loan.receipt_sn)));
+    assert_eq!(p.env.token_balance(&loan.borrower_token), AMOUNT - interest);
+    assert_eq!(p.env.token_balance(&vault(&loan.mint)), 10 * AMOUNT + interest);
+    assert_eq!(p.env.get::<UserBalance>(&token_balance(&loan.lender, &loan.mint)).amount, AMOUNT + interest - commission);
+    assert_eq!(p.env.get::<UserBalance>(&token_balance(&treasury(), &loan.mint)).amount, commission);
+    let treasury_rent = p.env.lamports(&token_balance(&treasury(), &loan.mint));
+    assert_eq!(p.env.lamports(&loan.borrower), sol_before - FEE - treasury_rent);
+}
+
+#[test]
+fn partial_token_repayment() {
+    let mut p = Protocol::new();
+    let loan = token_loan(&mut p, 2);
+    let total = AMOUNT + AMOUNT * RATE / 10000;
+
+    assert_eq!(repay_token(&mut p, &loan, Some(total + 1)), Err(error(MyError::IllegalRepayAmount)));
+    repay_token(&mut p, &loan, Some(total / 2)).unwrap();
+    let r: LoanReceipt = p.env.get(&receipt(loan.receipt_sn));
+    assert_eq!((r.repaid, r.released), (total / 2, r.frozen / 2));
+    repay_token(&mut p, &loan, Some(total / 2)).unwrap();
+    assert!(!p.env.exists(&receipt(loan.receipt_sn)));
+}
+
+#[test]
+fn extend_token_loan_pays_interest() {
+    let mut p = Protocol::new();
+    let loan = token_loan(&mut p, 1);
+    let time = p.env.get::<LoanReceipt>(&receipt(loan.receipt_sn)).time;
+
+    assert_eq!(extend_token_loan(&mut p, &loan, RATE * 2, None), Err(error(MyError::LenderApprovalRequired)));
+    let interest = AMOUNT * RATE / 10000;
+    let commission = interest * COMMISSION_RATE / 100;
+    extend_token_loan(&mut p, &loan, RATE, None).unwrap();
+    assert_eq!(p.env.get::<LoanReceipt>(&receipt(loan.receipt_sn)).time, time + TERM);
+    assert_eq!(p.env.token_balance(&loan.borrower_token), 2 * AMOUNT - interest);
+    assert_eq!(p.env.get::<UserBalance>(&token_balance(&loan.lender, &loan.mint)).amount, interest - commission);
+    assert_eq!(p.env.get::<UserBalance>(&token_balance(&treasury(), &loan.mint)).amount, commission);
+
+    extend_token_loan(&mut p, &loan, RATE * 2, Some(loan.lender)).unwrap();
+    assert_eq!(p.env.get::<LoanReceipt>(&receipt(loan.receipt_sn)).rate, RATE * 2);
+}
+
+#[test]
+fn withdraw_token_commission_from_treasury() {
+    let mut p = Protocol::new();
+    let admin = p.admin;
+    let loan = token_loan(&mut p, 1);
+    repay_token(&mut p, &loan, None).unwrap();
+    let commission = AMOUNT * RATE / 10000 * COMMISSION_RATE / 100;
+
+    let receiver = p.env.create_token_account(&loan.mint, &admin, 0);
+    let propose = |p: &mut Protocol, category: TreasuryCategory, amount: u64| {
+        let sn = p.env.get::<Treasury>(&treasury()).curr_withdrawal_sn;
+        p.env
+            .process(
+                accounts::ProposeTreasuryWithdrawal {
+                    withdrawal: withdrawal(sn),
+                    treasury: treasury(),
+                    roles: roles(),
+                    payer: admin,
+                    system_program: system_program::ID,
+                },
+                instruction::ProposeTreasuryWithdrawal {
+                    category,
+                    mint: loan.mint,
+                    amount,
+                    receiver,
+                },
+            )
+            .map(|_| sn)
+    };
+    let execute = |p: &mut Protocol, sn: u64| {
+        p.env.process(
+            accounts::ExecuteTokenTreasuryWithdrawal {
+                withdrawal: withdrawal(sn),
+                treasury: treasury(),
+                treasury_balance: token_balance(&treasury(), &loan.mint),
+                vault: vault(&loan.mint),
+                roles: roles(),
+                receiver,
+                payer: admin,
+                token_program: spl_token::ID,
+            },
+            instruction::ExecuteTokenTreasuryWithdrawal { withdrawal_sn: sn },
+        )
+    };
+
+    assert_eq!(propose(&mut p, TreasuryCategory::Rent, commission), Err(error(MyError::IllegalTreasuryCategory)));
+    let sn = propose(&mut p, TreasuryCategory::Commission, commission + 1).unwrap();
+    assert_eq!(execute(&mut p, sn), Err(error(MyError::InsufficientTreasuryBalance)));
+
+    let sn = propose(&mut p, TreasuryCategory::Commission, commission).unwrap();
+    execute(&mut p, sn).unwrap();
+    assert_eq!(p.env.token_balance(&receiver), commission);
+    assert_eq!(p.env.get::<UserBalance>(&token_balance(&treasury(), &loan.mint)).amount, 0);
+    assert_eq!(execute(&mut p, sn), Err(error(MyError::ProposalAlreadyExecuted)));
+}
@@ -0,0 +1,366 @@
+use ::solana_lending::*;
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::{system_program, sysvar};
+use solana_lending_testkit::*;
+
+fn propose(p: &mut Protocol, payer: &Pubkey, action: WitnessAction, witness: &Pubkey) -> std::result::Result<u64, ProgramError> {
+    let sn = p.global_state().curr_proposal_sn;
+    let stake = stake_account(p, witness);
+    p.env.process(
+        accounts::ProposeWitnessChange {
+            proposal: proposal(sn),
+            witnesses: witnesses(),
+            global: global(),
+            stake,
+            stake_config: stake_config(),
+            treasury: treasury(),
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::ProposeWitnessChange { action, witness: *witness },
+    )?;
+    Ok(sn)
+}
+
+fn approve(p: &mut Protocol, payer: &Pubkey, proposal_sn: u64) -> std::result::Result<(), ProgramError> {
+    let witness = p.env.get::<WitnessProposal>(&proposal(proposal_sn)).witness;
+    let stake = stake_account(p, &witness);
+    p.env.process(
+        accounts::ApproveWitnessChange {
+            proposal: proposal(proposal_sn),
+            witnesses: witnesses(),
+            stake,
+            stake_config: stake_config(),
+            global: global(),
+            treasury: treasury(),
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::ApproveWitnessChange { proposal_sn },
+    )
+}
+
+fn stake_account(p: &Protocol, witness: &Pubkey) -> Option<Pubkey> {
+    Some(stake(witness)).filter(|k| p.env.exists(k))
+}
+
+fn current_witnesses(p: &Protocol) -> Witnesses {
+    p.env.get(&witnesses())
+}
+
+fn set_stake_config(p: &mut Protocol, payer: &Pubkey, min_stake: u64, unbonding_period: u64) -> std::result::Result<(), ProgramError> {
+    p.env.process(
+        accounts::SetStakeConfig {
+            stake_config: stake_config(),
+            roles: roles(),
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::SetStakeConfig { min_stake, unbonding_period },
+    )
+}
+
+fn deposit_stake(p: &mut Protocol, payer: &Pubkey, amount: u64) -> std::result::Result<(), ProgramError> {
+    p.env.process(
+        accounts::DepositStake {
+            stake: stake(payer),
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::DepositStake { amount },
+    )
+}
+
+fn unbond_stake(p: &mut Protocol, payer: &Pubkey) -> std::result::Result<(), ProgramError> {
+    p.env.process(
+        accounts::UnbondStake {
+            stake: stake(payer),
+            stake_config: stake_config(),
+            witnesses: witnesses(),
+            payer: *payer,
+            clock: sysvar::clock::ID,
+        },
+        instruction::UnbondStake {},
+    )
+}
+
+fn withdraw_stake(p: &mut Protocol, payer: &Pubkey, amount: u64) -> std::result::Result<(), ProgramError> {
+    p.env.process(
+        accounts::WithdrawStake {
+            stake: stake(payer),
+            witnesses: witnesses(),
+            payer: *payer,
+            clock: sysvar::clock::ID,
+        },
+        instruction::WithdrawStake { amount },
+    )
+}
+
+fn submit_price(p: &mut Protocol, payer: &Pubkey, price: u64) -> std::result::Result<(), ProgramError> {
+    p.env.process(
+        accounts::SubmitPrice {
+            price_feed: price_feed(CHAIN, &TOKEN),
+            witnesses: witnesses(),
+            payer: *payer,
+            system_program: system_program::ID,
+            clock: sysvar::clock::ID,
+        },
+        instruction::SubmitPrice { chainid: CHAIN, token: TOKEN, price },
+    )
+}
+
+fn get_price(p: &mut Protocol, max_age: u64) -> std::result::Result<u64, ProgramError> {
+    p.env.process(
+        accounts::GetPrice {
+            price_feed: price_feed(CHAIN, &TOKEN),
+            witnesses: witnesses(),
+            clock: sysvar::clock::ID,
+        },
+        instruction::GetPrice {
+            _chainid: CHAIN,
+            _token: TOKEN,
+            max_age,
+        },
+    )?;
+    Ok(u64::from_le_bytes(p.env.return_data().unwrap().try_into().unwrap()))
+}
+
+fn claim_reward(p: &mut Protocol, payer: &Pubkey) -> std::result::Result<(), ProgramError> {
+    p.env.process(
+        accounts::ClaimReward {
+            reward_pool: reward_pool(),
+            reward: reward(payer),
+            payer: *payer,
+        },
+        instruction::ClaimReward {},
+    )
+}
+
+#[test]
+fn init_witnesses() {
+    let mut p = Protocol::bare();
+    let admin = p.admin;
+    let w = Pubkey::new_unique();
+    let stranger = p.env.wallet();
+
+    assert_eq!(p.init_witnesses(&stranger, vec![w]), Err(error(MyError::NoOperationPermission)));
+    assert_eq!(p.init_witnesses(&admin, vec![w, w]), Err(error(MyError::WitnessAlreadyExist)));
+    let v: Vec<Pubkey> = (0..4).map(|_| Pubkey::new_unique()).collect();
+    p.init_witnesses(&admin, v.clone()).unwrap();
+    let witnesses = current_witnesses(&p);
+    assert_eq!((witnesses.v, witnesses.multisig_threshold), (v, 3));
+    assert_eq!(p.init_witnesses(&admin, vec![w]), Err(error(MyError::WitnessesAlreadyInitialized)));
+}
+
+#[test]
+fn add_and_remove_witness() {
+    let mut p = Protocol::new();
+    let [w0, w1, w2] = [p.witnesses[0], p.witnesses[1], p.witnesses[2]];
+    let candidate = p.env.wallet();
+
+    assert_eq!(propose(&mut p, &candidate, WitnessAction::Add, &candidate), Err(error(MyError::NoOperationPermission)));
+    assert_eq!(propose(&mut p, &w0, WitnessAction::Add, &w1), Err(error(MyError::WitnessAlreadyExist)));
+    assert_eq!(propose(&mut p, &w0, WitnessAction::Remove, &candidate), Err(error(MyError::WitnessNotExist)));
+
+    let sn = propose(&mut p, &w0, WitnessAction::Add, &candidate).unwrap();
+    assert_eq!(p.global_state().curr_proposal_sn, sn + 1);
+    assert_eq!(approve(&mut p, &w0, sn), Err(error(MyError::AlreadySigned)));
+    assert_eq!(current_witnesses(&p).v.len(), 3);
+    approve(&mut p, &w1, sn).unwrap();
+    let witnesses = current_witnesses(&p);
+    assert_eq!((witnesses.v, witnesses.multisig_threshold), (vec![w0, w1, w2, candidate], 3));
+    assert!(p.env.get::<WitnessProposal>(&proposal(sn)).executed);
+    assert_eq!(approve(&mut p, &w2, sn), Err(error(MyError::ProposalAlreadyExecuted)));
+
+    let sn = propose(&mut p, &candidate, WitnessAction::Remove, &w0).unwrap();
+    approve(&mut p, &w1, sn).unwrap();
+    approve(&mut p, &w2, sn).unwrap();
+    let witnesses = current_witnesses(&p);
+    assert_eq!((witnesses.v, witnesses.multisig_threshold), (vec![w1, w2, candidate], 2));
+}
+
+#[test]
+fn removed_witness_approvals_do_not_count() {
+    let mut p = Protocol::new();
+    let [w0, w1, w2] = [p.witnesses[0], p.witnesses[1], p.witnesses[2]];
+    let candidate = Pubkey::new_unique();
+    let add = propose(&mut p, &w0, WitnessAction::Add, &candidate).unwrap();
+
+    let remove = propose(&mut p, &w1, WitnessAction::Remove, &w0).unwrap();
+    approve(&mut p, &w2, remove).unwrap();
+    assert_eq!(current_witnesses(&p).v, vec![w1, w2]);
+
+    approve(&mut p, &w1, add).unwrap();
+    assert!(!p.env.get::<WitnessProposal>(&proposal(add)).executed);
+    approve(&mut p, &w2, add).unwrap();
+    assert_eq!(current_witnesses(&p).v, vec![w1, w2, candidate]);
+}
+
+#[test]
+fn rounds_count_only_current_witnesses() {
+    let mut p = Protocol::new();
+    let [w0, w1, w2] = [p.witnesses[0], p.witnesses[1], p.witnesses[2]];
+    let (_, sn) = p.lender_with_order(5 * LAMPORTS, 500, 30 * DAY, 1);
+    let borrower = p.env.wallet();
+    p.multisig_collateral(&w0, 1, sn, &borrower, LAMPORTS).unwrap();
+    p.multisig_collateral(&w1, 1, sn, &borrower, LAMPORTS).unwrap();
+
+    // w0 is removed after signing, so the round is back to one valid signer of the two it needs.
+    let remove = propose(&mut p, &w1, WitnessAction::Remove, &w0).unwrap();
+    approve(&mut p, &w2, remove).unwrap();
+    assert_eq!(p.borrow(&borrower, 1, &borrower), Err(error(MyError::ConsensusLost)));
+    p.env.take_events();
+    p.recount_collateral(&borrower, 1).unwrap();
+    let event = EventRecountCollateral::try_from_slice(&p.env.take_events()[0][8..]).unwrap();
+    assert_eq!((event.chainid, event.collateral_sn, event.consensus), (CHAIN, 1, false));
+    let multisig: CollateralWithMultisig = p.env.get(&collateral(CHAIN, 1));
+    assert!(multisig.status == MultisigStatus::InProgress);
+    assert_eq!((multisig.threshold, multisig.witnesses), (2, vec![w0, w1, w2]));
+
+    // Witnesses added mid-round sign the next round, not this one.
+    let candidate = p.env.wallet();
+    let add = propose(&mut p, &w1, WitnessAction::Add, &candidate).unwrap();
+    approve(&mut p, &w2, add).unwrap();
+    assert_eq!(p.multisig_collateral(&candidate, 1, sn, &borrower, LAMPORTS), Err(error(MyError::WitnessNotInRound)));
+
+    p.multisig_collateral(&w2, 1, sn, &borrower, LAMPORTS).unwrap();
+    assert!(p.env.get::<CollateralWithMultisig>(&collateral(CHAIN, 1)).status == MultisigStatus::ReachConsensus);
+    let receipt_sn = p.borrow(&borrower, 1, &borrower).unwrap();
+    assert_eq!(p.env.get::<LoanReceipt>(&receipt(receipt_sn)).signers, vec![w1, w2]);
+}
+
+#[test]
+fn rounds_that_lose_consensus_expire() {
+    let mut p = Protocol::new();
+    let [w0, w1, w2] = [p.witnesses[0], p.witnesses[1], p.witnesses[2]];
+    let (_, sn) = p.lender_with_order(5 * LAMPORTS, 500, 30 * DAY, 1);
+    let borrower = p.env.wallet();
+    p.multisig_collateral(&w0, 1, sn, &borrower, LAMPORTS).unwrap();
+    p.multisig_collateral(&w1, 1, sn, &borrower, LAMPORTS).unwrap();
+    let remove = propose(&mut p, &w1, WitnessAction::Remove, &w0).unwrap();
+    approve(&mut p, &w2, remove).unwrap();
+
+    // Nobody recounts, yet expiring the round sees the consensus is gone.
+    assert!(p.env.get::<CollateralWithMultisig>(&collateral(CHAIN, 1)).status == MultisigStatus::ReachConsensus);
+    assert_eq!(p.expire_collateral(&borrower, 1), Err(error(MyError::MultisigNotExpired)));
+    p.env.warp(MULTISIG_TIMEOUT);
+    p.expire_collateral(&borrower, 1).unwrap();
+    assert!(p.env.get::<CollateralWithMultisig>(&collateral(CHAIN, 1)).status == MultisigStatus::Expired);
+}
+
+#[test]
+fn single_witness_reaches_consensus_alone() {
+    let mut p = Protocol::bare();
+    let admin = p.admin;
+    p.queue_config(&admin, p.default_config()).unwrap();
+    p.apply_config(&admin).unwrap();
+    let w = p.env.wallet();
+    p.init_witnesses(&admin, vec![w]).unwrap();
+    p.multisig_collateral(&w, 1, 0, &Pubkey::new_unique(), LAMPORTS).unwrap();
+    assert!(p.env.get::<CollateralWithMultisig>(&collateral(CHAIN, 1)).status == MultisigStatus::ReachConsensus);
+}
+
+#[test]
+fn cannot_remove_last_witness() {
+    let mut p = Protocol::bare();
+    let admin = p.admin;
+    let w = p.env.wallet();
+    p.init_witnesses(&admin, vec![w]).unwrap();
+    assert_eq!(propose(&mut p, &w, WitnessAction::Remove, &w), Err(error(MyError::CannotRemoveLastWitness)));
+}
+
+#[test]
+fn staking_and_slashing() {
+    let mut p = Protocol::new();
+    let admin = p.admin;
+    let [w0, w1, w2] = [p.witnesses[0], p.witnesses[1], p.witnesses[2]];
+    let candidate = p.env.wallet();
+    let stranger = p.env.wallet();
+    let unstaked = p.env.wallet();
+    let pending = propose(&mut p, &w0, WitnessAction::Add, &unstaked).unwrap();
+    assert_eq!(set_stake_config(&mut p, &stranger, LAMPORTS, DAY), Err(error(MyError::NoOperationPermission)));
+    set_stake_config(&mut p, &admin, 5 * LAMPORTS, 7 * DAY).unwrap();
+
+    // Once staking is configured a candidate without any stake can be neither proposed nor approved.
+    assert_eq!(propose(&mut p, &w0, WitnessAction::Add, &unstaked), Err(error(MyError::InsufficientStake)));
+    assert_eq!(approve(&mut p, &w1, pending), Err(error(MyError::InsufficientStake)));
+
+    deposit_stake(&mut p, &candidate, 2 * LAMPORTS).unwrap();
+    assert_eq!(propose(&mut p, &w0, WitnessAction::Add, &candidate), Err(error(MyError::InsufficientStake)));
+    deposit_stake(&mut p, &candidate, 3 * LAMPORTS).unwrap();
+    let s: WitnessStake = p.env.get(&stake(&candidate));
+    assert_eq!((s.witness, s.amount), (candidate, 5 * LAMPORTS));
+
+    let sn = propose(&mut p, &w0, WitnessAction::Add, &candidate).unwrap();
+    approve(&mut p, &w1, sn).unwrap();
+    assert!(current_witnesses(&p).v.contains(&candidate));
+    assert_eq!(unbond_stake(&mut p, &candidate), Err(error(MyError::WitnessStillActive)));
+    assert_eq!(withdraw_stake(&mut p, &candidate, LAMPORTS), Err(error(MyError::WitnessStillActive)));
+
+    // Slashing removes the witness and pays the slashed stake to the treasury.
+    assert_eq!(propose(&mut p, &w0, WitnessAction::Slash { amount: 6 * LAMPORTS }, &candidate), Err(error(MyError::InsufficientStake)));
+    let before = p.env.lamports(&treasury());
+    let admin_before = p.env.lamports(&admin);
+    let sn = propose(&mut p, &w0, WitnessAction::Slash { amount: 2 * LAMPORTS }, &candidate).unwrap();
+    approve(&mut p, &w1, sn).unwrap();
+    assert!(current_witnesses(&p).v.contains(&candidate));
+    approve(&mut p, &w2, sn).unwrap();
+    assert!(!current_witnesses(&p).v.contains(&candidate));
+    assert_eq!(p.env.lamports(&treasury()), before + 2 * LAMPORTS);
+    assert_eq!(p.env.lamports(&admin), admin_before);
+    assert_eq!(p.env.get::<Treasury>(&treasury()).slashed, 2 * LAMPORTS);
+    assert_eq!(p.env.get::<WitnessStake>(&stake(&candidate)).amount, 3 * LAMPORTS);
+
+    assert_eq!(withdraw_stake(&mut p, &candidate, LAMPORTS), Err(error(MyError::StakeStillBonded)));
+    unbond_stake(&mut p, &candidate).unwrap();
+    p.env.warp(7 * DAY - 1);
+    assert_eq!(withdraw_stake(&mut p, &candidate, LAMPORTS), Err(error(MyError::StakeStillBonded)));
+    p.env.warp(1);
+    assert_eq!(withdraw_stake(&mut p, &candidate, 4 * LAMPORTS), Err(error(MyError::InsufficientStake)));
+    let before = p.env.lamports(&candidate);
+    withdraw_stake(&mut p, &candidate, 3 * LAMPORTS).unwrap();
+    assert_eq!(p.env.lamports(&candidate), before + 3 * LAMPORTS);
+}
+
+#[test]
+fn price_feed_median() {
+    let mut p = Protocol::new();
+    let [w0, w1, w2] = [p.witnesses[0], p.witnesses[1], p.witnesses[2]];
+    let stranger = p.env.wallet();
+
+    assert_eq!(submit_price(&mut p, &stranger, 100), Err(error(MyError::NoOperationPermission)));
+    assert_eq!(submit_price(&mut p, &w0, 0), Err(error(MyError::IllegalPrice)));
+    submit_price(&mut p, &w0, 100).unwrap();
+    assert_eq!(get_price(&mut p, 60), Err(error(MyError::StalePrice)));
+
+    submit_price(&mut p, &w1, 300).unwrap();
+    assert_eq!(get_price(&mut p, 60), Ok(200));
+    submit_price(&mut p, &w2, 110).unwrap();
+    assert_eq!(get_price(&mut p, 60), Ok(110));
+    assert_eq!(p.env.get::<PriceFeed>(&price_feed(CHAIN, &TOKEN)).price, 110);
+
+    // Resubmitting replaces the witness's price; old submissions age out.
+    p.env.warp(61);
+    submit_price(&mut p, &w0, 120).unwrap();
+    assert_eq!(get_price(&mut p, 60), Err(error(MyError::StalePrice)));
+    submit_price(&mut p, &w1, 130).unwrap();
+    assert_eq!(get_price(&mut p, 60), Ok(125));
+    assert_eq!(p.env.get::<PriceFeed>(&price_feed(CHAIN, &TOKEN)).submissions.len(), 3);
+}
+
+#[test]
+fn claim_rewards() {
+    let mut p = Protocol::new();
+    let [w0, w1, w2] = [p.witnesses[0], p.witnesses[1], p.witnesses[2]];
+    assert_eq!(claim_reward(&mut p, &w0), Err(anchor_error(anchor_lang::error::ErrorCode::AccountNotInitialized)));
+
+    let loan = p.loan(LAMPORTS, 500, 30 * DAY, 1);
+    p.repay(&loan.borrower, loan.receipt_sn).unwrap();
+    let before = p.env.lamports(&w1);
+    claim_reward(&mut p, &w1).unwrap();
+    assert_eq!(p.env.lamports(&w1), before + FEE / 2);
+    assert_eq!(claim_reward(&mut p, &w1), Err(error(MyError::NoRewardToClaim)));
+    assert_eq!(claim_reward(&mut p, &w2), Err(anchor_error(anchor_lang::error::ErrorCode::AccountNotInitialized)));
+    claim_reward(&mut p, &w0).unwrap();
+    assert_eq!(p.env.get::<WitnessReward>(&reward(&w0)).amount, 0);
+}
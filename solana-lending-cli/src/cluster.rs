@@ -0,0 +1,88 @@
+//! The cluster the CLI talks to: account reads, the program's accounts of one type, the
+//! cluster clock and sending instructions signed by the user's keypair.
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::solana_program::sysvar::{self, clock::Clock};
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::rpc_client::RpcClient;
+use solana_client::rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig};
+use solana_client::rpc_filter::{Memcmp, RpcFilterType};
+use solana_lending_client::ID;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::signature::{Keypair, Signer};
+use solana_sdk::transaction::Transaction;
+
+use crate::{CliError, Result};
+
+pub trait Cluster {
+    /// The keypair that signs and pays for every transaction.
+    fn payer(&self) -> Pubkey;
+
+    fn account(&self, key: &Pubkey) -> Result<Option<Vec<u8>>>;
+
+    /// The lending program's accounts whose data starts with `discriminator`.
+    fn program_accounts(&self, discriminator: [u8; 8]) -> Result<Vec<(Pubkey, Vec<u8>)>>;
+
+    /// The cluster's unix time, as the program sees it.
+    fn now(&self) -> Result<u64>;
+
+    /// Sends the instructions in one transaction and returns its signature.
+    fn send(&mut self, instructions: &[Instruction]) -> Result<String>;
+}
+
+pub struct RpcCluster {
+    client: RpcClient,
+    keypair: Keypair,
+}
+
+impl RpcCluster {
+    pub fn new(url: &str, keypair: Keypair) -> Self {
+        RpcCluster {
+            client: RpcClient::new_with_commitment(url.to_string(), CommitmentConfig::confirmed()),
+            keypair,
+        }
+    }
+}
+
+fn rpc_error<E: std::fmt::Display>(e: E) -> CliError {
+    CliError::Solana(e.to_string())
+}
+
+impl Cluster for RpcCluster {
+    fn payer(&self) -> Pubkey {
+        self.keypair.pubkey()
+    }
+
+    fn account(&self, key: &Pubkey) -> Result<Option<Vec<u8>>> {
+        let account = self.client.get_account_with_commitment(key, self.client.commitment()).map_err(rpc_error)?.value;
+        Ok(account.map(|a| a.data))
+    }
+
+    fn program_accounts(&self, discriminator: [u8; 8]) -> Result<Vec<(Pubkey, Vec<u8>)>> {
+        let config = RpcProgramAccountsConfig {
+            filters: Some(vec![RpcFilterType::Memcmp(Memcmp::new_base58_encoded(0, &discriminator))]),
+            account_config: RpcAccountInfoConfig {
+                encoding: Some(UiAccountEncoding::Base64),
+                commitment: Some(self.client.commitment()),
+                ..RpcAccountInfoConfig::default()
+            },
+            with_context: None,
+        };
+        let accounts = self.client.get_program_accounts_with_config(&ID, config).map_err(rpc_error)?;
+        Ok(accounts.into_iter().map(|(key, a)| (key, a.data)).collect())
+    }
+
+    fn now(&self) -> Result<u64> {
+        let data = self.account(&sysvar::clock::ID)?.ok_or_else(|| CliError::Solana("clock sysvar not found".into()))?;
+        let clock: Clock = bincode::deserialize(&data).map_err(rpc_error)?;
+        Ok(clock.unix_timestamp as u64)
+    }
+
+    fn send(&mut self, instructions: &[Instruction]) -> Result<String> {
+        let blockhash = self.client.get_latest_blockhash().map_err(rpc_error)?;
+        let tx = Transaction::new_signed_with_payer(instructions, Some(&self.keypair.pubkey()), &[&self.keypair], blockhash);
+        let signature = self.client.send_and_confirm_transaction(&tx).map_err(|e| CliError::Transaction(e.to_string()))?;
+        Ok(signature.to_string())
+    }
+}
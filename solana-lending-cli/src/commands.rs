@@ -0,0 +1,265 @@
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::{AccountDeserialize, Discriminator};
+use serde_json::{json, Map, Value};
+use solana_lending_client::instructions as ix;
+use solana_lending_client::*;
+
+use crate::{CliError, Cluster, Command, Result, WitnessCommand};
+
+fn fetch<T: AccountDeserialize>(cluster: &dyn Cluster, key: &Pubkey, what: &str) -> Result<T> {
+    let data = cluster.account(key)?.ok_or_else(|| CliError::NotFound(what.into()))?;
+    decode_account(&data).map_err(|e| CliError::Decode(format!("{what}: {e}")))
+}
+
+fn fetch_optional<T: AccountDeserialize>(cluster: &dyn Cluster, key: &Pubkey, what: &str) -> Result<Option<T>> {
+    match cluster.account(key)? {
+        Some(data) => decode_account(&data).map(Some).map_err(|e| CliError::Decode(format!("{what}: {e}"))),
+        None => Ok(None),
+    }
+}
+
+fn send(cluster: &mut dyn Cluster, instruction: Instruction) -> Result<String> {
+    cluster.send(&[instruction])
+}
+
+fn hex(bytes: &[u8; 20]) -> String {
+    format!("0x{}", bytes.iter().map(|b| format!("{b:02x}")).collect::<String>())
+}
+
+fn order_json(o: &Order) -> Value {
+    json!({
+        "order_sn": o.sn,
+        "lender": o.lender.to_string(),
+        "mint": o.mint.to_string(),
+        "balance": o.balance,
+        "reserved": o.reserved,
+        "rate": o.rate,
+        "term": o.term,
+        "installments": o.installments,
+    })
+}
+
+fn config_json(c: &ConfigParams) -> Value {
+    json!({
+        "min_ir": c.min_ir,
+        "max_ir": c.max_ir,
+        "penalty_ir": c.penalty_ir,
+        "penalty_days": c.penalty_days,
+        "commission_rate": c.commission_rate,
+        "min_term": c.min_term,
+        "max_term": c.max_term,
+        "grace": c.grace,
+        "multisig_timeout": c.multisig_timeout,
+    })
+}
+
+// the order repayments are paid into when the lender enabled auto-reinvest
+fn reinvest_order(cluster: &dyn Cluster, r: &LoanReceipt) -> Result<Option<u64>> {
+    let reinvest: Option<AutoReinvest> = fetch_optional(cluster, &pda::reinvest(&r.lender, &r.mint), "auto-reinvest")?;
+    Ok(reinvest.map(|r| r.order_sn))
+}
+
+// the witness's stake account is passed only when the witness has one
+fn staked(cluster: &dyn Cluster, witness: &Pubkey) -> Result<bool> {
+    Ok(cluster.account(&pda::stake(witness))?.is_some())
+}
+
+fn receipt(cluster: &dyn Cluster, receipt_sn: u64) -> Result<Value> {
+    let r: LoanReceipt = fetch(cluster, &pda::receipt(receipt_sn), &format!("receipt {receipt_sn}"))?;
+    let config: Config = fetch(cluster, &pda::config(), "config")?;
+    let ccfee: CCFee = fetch(cluster, &pda::ccfee(r.chainid), &format!("ccfee for chain {}", r.chainid))?;
+    let now = cluster.now()?;
+    let (remaining, penalty) = amount_due(&r, &config, now).map_err(|e| CliError::Invalid(e.to_string()))?;
+    let due = next_due(&r);
+    Ok(json!({
+        "receipt_sn": r.sn,
+        "borrower": r.borrower.to_string(),
+        "lender": r.lender.to_string(),
+        "mint": r.mint.to_string(),
+        "chainid": r.chainid,
+        "collateral_sn": r.c_sn,
+        "source": hex(&r.source),
+        "token": hex(&r.token),
+        "frozen": r.frozen,
+        "amount": r.amount,
+        "rate": r.rate,
+        "time": r.time,
+        "term": r.term,
+        "installments": r.installments,
+        "repaid": r.repaid,
+        "due": due,
+        "deadline": due + r.grace,
+        "overdue": now > due,
+        "remaining": remaining,
+        "penalty": penalty,
+        "ccfee": ccfee.fee,
+        "amount_due": remaining + penalty + ccfee.fee,
+    }))
+}
+
+/// Runs one command and returns its result.
+pub fn run(command: &Command, cluster: &mut dyn Cluster) -> Result<Value> {
+    let payer = cluster.payer();
+    let value = match command {
+        Command::Deposit { amount } => {
+            let signature = send(cluster, ix::deposit(&payer, *amount))?;
+            let balance: UserBalance = fetch(cluster, &pda::user_balance(&payer), "balance")?;
+            json!({ "signature": signature, "balance": balance.amount })
+        }
+        Command::Withdraw { amount } => {
+            let signature = send(cluster, ix::withdraw(&payer, *amount))?;
+            let balance: UserBalance = fetch(cluster, &pda::user_balance(&payer), "balance")?;
+            json!({ "signature": signature, "balance": balance.amount })
+        }
+        Command::Balance { owner } => {
+            let owner = owner.unwrap_or(payer);
+            let balance: Option<UserBalance> = fetch_optional(cluster, &pda::user_balance(&owner), "balance")?;
+            json!({ "owner": owner.to_string(), "balance": balance.map_or(0, |b| b.amount) })
+        }
+        Command::PlaceOrder { amount, rate, term, installments } => {
+            let global: GlobalState = fetch(cluster, &pda::global_state(), "global state")?;
+            let order_sn = global.curr_order_sn;
+            let signature = send(cluster, ix::place_order(&payer, order_sn, *amount, *rate, *term, *installments))?;
+            json!({ "signature": signature, "order_sn": order_sn })
+        }
+        Command::CancelOrder { order_sn } => {
+            let order: Order = fetch(cluster, &pda::order(*order_sn), &format!("order {order_sn}"))?;
+            let instruction = if order.mint == Pubkey::default() {
+                ix::cancel_order(&payer, *order_sn)
+            } else {
+                ix::cancel_token_order(&payer, *order_sn, &order.mint)
+            };
+            json!({ "signature": send(cluster, instruction)?, "order_sn": order_sn, "returned": order.available(), "reserved": order.reserved })
+        }
+        Command::Orders { mint, lender } => {
+            let mut orders: Vec<Order> = cluster
+                .program_accounts(Order::discriminator())?
+                .iter()
+                .filter_map(|(_, data)| decode_account::<Order>(data).ok())
+                .filter(|o| o.available() > 0 && mint.is_none_or(|m| o.mint == m) && lender.is_none_or(|l| o.lender == l))
+                .collect();
+            orders.sort_by_key(|o| (o.rate, o.sn));
+            Value::Array(orders.iter().map(order_json).collect())
+        }
+        Command::Reserve { chainid, collateral_sn, order_sn, amount } => {
+            let signature = send(cluster, ix::reserve_order(&payer, *chainid, *collateral_sn, *order_sn, *amount))?;
+            let multisig: CollateralWithMultisig = fetch(cluster, &pda::collateral(*chainid, *collateral_sn), "collateral round")?;
+            json!({ "signature": signature, "order_sn": order_sn, "amount": amount, "expiry": multisig.reservation.expiry })
+        }
+        Command::Receipt { receipt_sn } => receipt(cluster, *receipt_sn)?,
+        Command::Repay { receipt_sn, amount, token_account } => {
+            let r: LoanReceipt = fetch(cluster, &pda::receipt(*receipt_sn), &format!("receipt {receipt_sn}"))?;
+            let reinvest = reinvest_order(cluster, &r)?;
+            let instruction = match (r.mint == Pubkey::default(), token_account, amount) {
+                (true, _, None) => ix::repay(&payer, &r, reinvest),
+                (true, _, Some(amount)) => ix::repay_partial(&payer, &r, reinvest, *amount),
+                (false, Some(token), None) => ix::repay_token(&payer, &r, reinvest, token),
+                (false, Some(token), Some(amount)) => ix::repay_token_partial(&payer, &r, reinvest, token, *amount),
+                (false, None, _) => return Err(CliError::Invalid(format!("receipt {receipt_sn} is a token loan; pass --token-account"))),
+            };
+            let signature = send(cluster, instruction)?;
+            let left: Option<LoanReceipt> = fetch_optional(cluster, &pda::receipt(*receipt_sn), "receipt")?;
+            json!({ "signature": signature, "receipt_sn": receipt_sn, "settled": left.is_none() })
+        }
+        Command::Liquidate { receipt_sn, receiver } => {
+            let r: LoanReceipt = fetch(cluster, &pda::receipt(*receipt_sn), &format!("receipt {receipt_sn}"))?;
+            json!({ "signature": send(cluster, ix::liquidate(&payer, &r, *receiver))?, "receipt_sn": receipt_sn })
+        }
+        Command::SetConfig {
+            min_ir,
+            max_ir,
+            penalty_ir,
+            penalty_days,
+            commission_rate,
+            min_term,
+            max_term,
+            grace,
+            multisig_timeout,
+        } => {
+            let current: Option<Config> = fetch_optional(cluster, &pda::config(), "config")?;
+            let pick = |value: &Option<u64>, name: &str, old: Option<u64>| {
+                value.or(old).ok_or_else(|| CliError::Invalid(format!("no config yet; pass --{}", name.replace('_', "-"))))
+            };
+            let c = current.as_ref();
+            let params = ConfigParams {
+                min_ir: pick(min_ir, "min_ir", c.map(|c| c.min_ir))?,
+                max_ir: pick(max_ir, "max_ir", c.map(|c| c.max_ir))?,
+                penalty_ir: pick(penalty_ir, "penalty_ir", c.map(|c| c.penalty_ir))?,
+                penalty_days: pick(penalty_days, "penalty_days", c.map(|c| c.penalty_days))?,
+                commission_rate: pick(commission_rate, "commission_rate", c.map(|c| c.commission_rate))?,
+                min_term: pick(min_term, "min_term", c.map(|c| c.min_term))?,
+                max_term: pick(max_term, "max_term", c.map(|c| c.max_term))?,
+                grace: pick(grace, "grace", c.map(|c| c.grace))?,
+                multisig_timeout: pick(multisig_timeout, "multisig_timeout", c.map(|c| c.multisig_timeout))?,
+            };
+            let signature = send(cluster, ix::queue_config(&payer, params))?;
+            let pending: PendingConfig = fetch(cluster, &pda::pending_config(), "pending config")?;
+            json!({ "signature": signature, "params": config_json(&pending.params), "eta": pending.eta })
+        }
+        Command::ApplyConfig => json!({ "signature": send(cluster, ix::apply_config(&payer))? }),
+        Command::SetCcfee { chainid, fee } => {
+            let signature = send(cluster, ix::queue_ccfee(&payer, *chainid, *fee))?;
+            let pending: PendingCCFee = fetch(cluster, &pda::pending_ccfee(*chainid), "pending ccfee")?;
+            json!({ "signature": signature, "chainid": chainid, "fee": pending.fee, "eta": pending.eta })
+        }
+        Command::ApplyCcfee { chainid } => json!({ "signature": send(cluster, ix::apply_ccfee(&payer, *chainid))?, "chainid": chainid }),
+        Command::Witness(command) => {
+            let global: GlobalState = fetch(cluster, &pda::global_state(), "global state")?;
+            let (proposal_sn, instruction) = match command {
+                WitnessCommand::Add { witness } | WitnessCommand::Remove { witness } => {
+                    let action = if matches!(command, WitnessCommand::Add { .. }) { WitnessAction::Add } else { WitnessAction::Remove };
+                    let staked = staked(cluster, witness)?;
+                    let sn = global.curr_proposal_sn;
+                    (sn, ix::propose_witness_change(&payer, sn, action, *witness, staked))
+                }
+                WitnessCommand::Approve { proposal_sn } => {
+                    let proposal: WitnessProposal = fetch(cluster, &pda::proposal(*proposal_sn), &format!("proposal {proposal_sn}"))?;
+                    let staked = staked(cluster, &proposal.witness)?;
+                    (*proposal_sn, ix::approve_witness_change(&payer, *proposal_sn, &proposal.witness, staked))
+                }
+            };
+            let signature = send(cluster, instruction)?;
+            let proposal: WitnessProposal = fetch(cluster, &pda::proposal(proposal_sn), "proposal")?;
+            json!({
+                "signature": signature,
+                "proposal_sn": proposal_sn,
+                "witness": proposal.witness.to_string(),
+                "approvals": proposal.signers.len(),
+                "executed": proposal.executed,
+            })
+        }
+    };
+    Ok(value)
+}
+
+fn scalar(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+fn fields(map: &Map<String, Value>, sep: &str, assign: &str) -> String {
+    map.iter().map(|(k, v)| format!("{k}{assign}{}", scalar(v))).collect::<Vec<_>>().join(sep)
+}
+
+/// Formats a result as JSON, or for reading: an object as one `key: value` line per field and
+/// a list as one line per item.
+pub fn render(value: &Value, json: bool) -> String {
+    if json {
+        return serde_json::to_string_pretty(value).unwrap();
+    }
+    match value {
+        Value::Object(map) => fields(map, "\n", ": "),
+        Value::Array(items) => items
+            .iter()
+            .map(|item| match item {
+                Value::Object(map) => fields(map, "  ", "="),
+                other => scalar(other),
+            })
+            .collect::<Vec<_>>()
+            .join("\n"),
+        other => scalar(other),
+    }
+}
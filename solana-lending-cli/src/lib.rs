@@ -0,0 +1,160 @@
+//! `cclending`, a command-line tool for the lending program. Every command returns a JSON value,
+//! printed as-is with `--json` or as `key: value` lines otherwise.
+
+pub mod cluster;
+pub mod commands;
+
+use std::path::PathBuf;
+
+use anchor_lang::prelude::Pubkey;
+use clap::{Parser, Subcommand};
+use thiserror::Error;
+
+pub use cluster::{Cluster, RpcCluster};
+pub use commands::{render, run};
+
+#[derive(Debug, Parser)]
+#[command(name = "cclending", about = "Lend, borrow and administer the cross-chain lending program")]
+pub struct Cli {
+    /// RPC endpoint of the cluster.
+    #[arg(long, short = 'u', global = true, default_value = "http://127.0.0.1:8899")]
+    pub url: String,
+    /// Keypair file that signs and pays; defaults to the Solana CLI keypair.
+    #[arg(long, short = 'k', global = true)]
+    pub keypair: Option<PathBuf>,
+    /// Print the result as JSON.
+    #[arg(long, global = true)]
+    pub json: bool,
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Deposit lamports into your lending balance.
+    Deposit { amount: u64 },
+    /// Withdraw lamports from your lending balance.
+    Withdraw { amount: u64 },
+    /// Show a user's lending balance.
+    Balance { owner: Option<Pubkey> },
+    /// Place a loan order from your lending balance.
+    PlaceOrder {
+        #[arg(long)]
+        amount: u64,
+        /// Interest over the whole term, in basis points.
+        #[arg(long)]
+        rate: u64,
+        /// Loan term in seconds.
+        #[arg(long)]
+        term: u64,
+        #[arg(long, default_value_t = 1)]
+        installments: u8,
+    },
+    /// Cancel one of your orders, returning its balance.
+    CancelOrder { order_sn: u64 },
+    /// List orders with unreserved balance, cheapest first.
+    Orders {
+        #[arg(long)]
+        mint: Option<Pubkey>,
+        #[arg(long)]
+        lender: Option<Pubkey>,
+    },
+    /// Reserve part of an order for the collateral round `collateral_sn` once a witness has signed
+    /// it, when its first signature could not.
+    Reserve {
+        #[arg(long)]
+        chainid: u32,
+        #[arg(long)]
+        collateral_sn: u64,
+        #[arg(long)]
+        order_sn: u64,
+        #[arg(long)]
+        amount: u64,
+    },
+    /// Show a receipt and what repaying it in full costs now.
+    Receipt { receipt_sn: u64 },
+    /// Repay a receipt in full, or `--amount` of its scheduled total.
+    Repay {
+        receipt_sn: u64,
+        #[arg(long)]
+        amount: Option<u64>,
+        /// Token account paying a token loan.
+        #[arg(long)]
+        token_account: Option<Pubkey>,
+    },
+    /// Liquidate an overdue receipt; the collateral goes to `receiver` on the source chain.
+    Liquidate {
+        receipt_sn: u64,
+        #[arg(long, value_parser = parse_address)]
+        receiver: [u8; 20],
+    },
+    /// Queue a config change; parameters left out keep their current value.
+    SetConfig {
+        #[arg(long)]
+        min_ir: Option<u64>,
+        #[arg(long)]
+        max_ir: Option<u64>,
+        #[arg(long)]
+        penalty_ir: Option<u64>,
+        #[arg(long)]
+        penalty_days: Option<u64>,
+        #[arg(long)]
+        commission_rate: Option<u64>,
+        #[arg(long)]
+        min_term: Option<u64>,
+        #[arg(long)]
+        max_term: Option<u64>,
+        #[arg(long)]
+        grace: Option<u64>,
+        /// Seconds after which a collateral round without consensus can be expired.
+        #[arg(long)]
+        multisig_timeout: Option<u64>,
+    },
+    /// Apply the queued config change once its timelock has passed.
+    ApplyConfig,
+    /// Queue a cross-chain fee change for a source chain.
+    SetCcfee { chainid: u32, fee: u64 },
+    /// Apply the queued cross-chain fee change once its timelock has passed.
+    ApplyCcfee { chainid: u32 },
+    /// Propose or approve witness set changes, as a witness.
+    #[command(subcommand)]
+    Witness(WitnessCommand),
+}
+
+#[derive(Debug, Subcommand)]
+pub enum WitnessCommand {
+    Add { witness: Pubkey },
+    Remove { witness: Pubkey },
+    Approve { proposal_sn: u64 },
+}
+
+/// Parses a 0x-prefixed 20-byte source-chain address.
+pub fn parse_address(s: &str) -> std::result::Result<[u8; 20], String> {
+    let hex = s.strip_prefix("0x").unwrap_or(s);
+    if hex.len() != 40 || !hex.is_ascii() {
+        return Err("expected 20 hex-encoded bytes".into());
+    }
+    let mut bytes = [0; 20];
+    for (i, b) in bytes.iter_mut().enumerate() {
+        *b = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).map_err(|e| e.to_string())?;
+    }
+    Ok(bytes)
+}
+
+#[derive(Debug, Error)]
+pub enum CliError {
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("solana rpc error: {0}")]
+    Solana(String),
+    #[error("transaction failed: {0}")]
+    Transaction(String),
+    #[error("{0} not found")]
+    NotFound(String),
+    #[error("cannot decode {0}")]
+    Decode(String),
+    #[error("{0}")]
+    Invalid(String),
+}
+
+pub type Result<T> = std::result::Result<T, CliError>;
@@ -0,0 +1,34 @@
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use clap::Parser;
+use solana_lending_cli::{render, run, Cli, RpcCluster};
+use solana_sdk::signature::read_keypair_file;
+
+fn default_keypair() -> PathBuf {
+    let home = std::env::var_os("HOME").unwrap_or_default();
+    PathBuf::from(home).join(".config/solana/id.json")
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let path = cli.keypair.clone().unwrap_or_else(default_keypair);
+    let keypair = match read_keypair_file(&path) {
+        Ok(keypair) => keypair,
+        Err(e) => {
+            eprintln!("cannot read keypair {}: {e}", path.display());
+            return ExitCode::FAILURE;
+        }
+    };
+    let mut cluster = RpcCluster::new(&cli.url, keypair);
+    match run(&cli.command, &mut cluster) {
+        Ok(value) => {
+            println!("{}", render(&value, cli.json));
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
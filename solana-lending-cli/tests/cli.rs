@@ -0,0 +1,210 @@
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::Instruction;
+use clap::Parser;
+use solana_lending_testkit::{Protocol, CHAIN, DAY, FEE, GRACE, LAMPORTS, MULTISIG_TIMEOUT, SOURCE};
+use serde_json::Value;
+use ::solana_lending::CONFIG_DELAY;
+use solana_lending_cli::*;
+use solana_lending_client::{pda, Config, Witnesses, ID};
+
+const TERM: u64 = 30 * DAY;
+
+// A `Cluster` over the native harness, signing as whichever key `payer` is set to.
+struct HarnessCluster {
+    p: Protocol,
+    payer: Pubkey,
+}
+
+impl Cluster for HarnessCluster {
+    fn payer(&self) -> Pubkey {
+        self.payer
+    }
+
+    fn account(&self, key: &Pubkey) -> Result<Option<Vec<u8>>> {
+        Ok(self.p.env.accounts.get(key).map(|a| a.data.clone()))
+    }
+
+    fn program_accounts(&self, discriminator: [u8; 8]) -> Result<Vec<(Pubkey, Vec<u8>)>> {
+        Ok(self
+            .p
+            .env
+            .accounts
+            .iter()
+            .filter(|(_, a)| a.owner == ID && a.data.starts_with(&discriminator))
+            .map(|(k, a)| (*k, a.data.clone()))
+            .collect())
+    }
+
+    fn now(&self) -> Result<u64> {
+        Ok(self.p.env.now())
+    }
+
+    fn send(&mut self, instructions: &[Instruction]) -> Result<String> {
+        for ix in instructions {
+            self.p.env.process_metas(ix.accounts.clone(), ix.data.clone()).map_err(|e| CliError::Transaction(e.to_string()))?;
+        }
+        Ok("signature".into())
+    }
+}
+
+impl HarnessCluster {
+    fn new() -> Self {
+        let p = Protocol::new();
+        let payer = p.admin;
+        HarnessCluster { p, payer }
+    }
+
+    // Parses the arguments the way the binary does and runs the command as `payer`.
+    fn cli(&mut self, payer: Pubkey, args: &str) -> Result<Value> {
+        let cli = Cli::try_parse_from(std::iter::once("cclending").chain(args.split_whitespace())).unwrap();
+        self.payer = payer;
+        run(&cli.command, self)
+    }
+}
+
+#[test]
+fn lender_commands() {
+    let mut c = HarnessCluster::new();
+    let lender = c.p.env.wallet();
+
+    assert_eq!(c.cli(lender, &format!("deposit {}", 5 * LAMPORTS)).unwrap()["balance"], 5 * LAMPORTS);
+    let dear = c.cli(lender, &format!("place-order --amount {} --rate 500 --term {TERM}", 2 * LAMPORTS)).unwrap();
+    let cheap = c.cli(lender, &format!("place-order --amount {} --rate 300 --term {TERM} --installments 3", LAMPORTS)).unwrap();
+    assert_eq!(cheap["order_sn"], dear["order_sn"].as_u64().unwrap() + 1);
+
+    let orders = c.cli(lender, "orders").unwrap();
+    let rates: Vec<u64> = orders.as_array().unwrap().iter().map(|o| o["rate"].as_u64().unwrap()).collect();
+    assert_eq!(rates, vec![300, 500]);
+    assert_eq!(orders[0]["installments"], 3);
+    assert_eq!(orders[0]["lender"], lender.to_string());
+    assert_eq!(c.cli(lender, &format!("orders --lender {}", Pubkey::new_unique())).unwrap(), Value::Array(vec![]));
+
+    let cancelled = c.cli(lender, &format!("cancel-order {}", cheap["order_sn"])).unwrap();
+    assert_eq!(cancelled["returned"], LAMPORTS);
+    assert_eq!(c.cli(lender, "orders").unwrap().as_array().unwrap().len(), 1);
+    assert_eq!(c.cli(lender, &format!("withdraw {}", LAMPORTS)).unwrap()["balance"], 2 * LAMPORTS);
+    assert_eq!(c.cli(c.p.admin, &format!("balance {lender}")).unwrap()["balance"], 2 * LAMPORTS);
+}
+
+#[test]
+fn borrower_reserves_an_order() {
+    let mut c = HarnessCluster::new();
+    let (lender, order_sn) = c.p.lender_with_order(3 * LAMPORTS, 500, TERM, 1);
+    let borrower = c.p.env.wallet();
+    let collateral_sn = c.p.next_collateral_sn();
+    // the order cannot cover the attested amount, so the first signature reserves nothing
+    let witness = c.p.witnesses[0];
+    c.p.multisig_collateral(&witness, collateral_sn, order_sn, &borrower, 4 * LAMPORTS).unwrap();
+    let reserved = c.cli(borrower, &format!("reserve --chainid {CHAIN} --collateral-sn {collateral_sn} --order-sn {order_sn} --amount {LAMPORTS}")).unwrap();
+    assert_eq!(reserved["expiry"], c.p.env.now() + MULTISIG_TIMEOUT);
+
+    let orders = c.cli(borrower, "orders").unwrap();
+    assert_eq!((orders[0]["balance"].as_u64(), orders[0]["reserved"].as_u64()), (Some(3 * LAMPORTS), Some(LAMPORTS)));
+    let cancelled = c.cli(lender, &format!("cancel-order {order_sn}")).unwrap();
+    assert_eq!((cancelled["returned"].as_u64(), cancelled["reserved"].as_u64()), (Some(2 * LAMPORTS), Some(LAMPORTS)));
+    assert_eq!(c.cli(borrower, "orders").unwrap(), Value::Array(vec![]));
+}
+
+#[test]
+fn receipt_quotes_what_repay_charges() {
+    let mut c = HarnessCluster::new();
+    let loan = c.p.loan(LAMPORTS, 500, TERM, 1);
+    let quote = c.cli(loan.borrower, &format!("receipt {}", loan.receipt_sn)).unwrap();
+    assert_eq!(quote["remaining"], LAMPORTS + LAMPORTS * 500 / 10000);
+    assert_eq!(quote["penalty"], 0);
+    assert_eq!(quote["overdue"], false);
+
+    c.p.env.warp(TERM + 2 * DAY);
+    let quote = c.cli(loan.borrower, &format!("receipt {}", loan.receipt_sn)).unwrap();
+    assert_eq!(quote["overdue"], true);
+    assert!(quote["penalty"].as_u64().unwrap() > 0);
+    assert_eq!(quote["ccfee"], FEE);
+
+    let before = c.p.env.lamports(&loan.borrower);
+    let repaid = c.cli(loan.borrower, &format!("repay {}", loan.receipt_sn)).unwrap();
+    assert_eq!(repaid["settled"], true);
+    assert_eq!(before - c.p.env.lamports(&loan.borrower), quote["amount_due"].as_u64().unwrap());
+}
+
+#[test]
+fn partial_repay_and_liquidate() {
+    let mut c = HarnessCluster::new();
+    let loan = c.p.loan(LAMPORTS, 500, TERM, 2);
+    let repaid = c.cli(loan.borrower, &format!("repay {} --amount {}", loan.receipt_sn, LAMPORTS / 2)).unwrap();
+    assert_eq!(repaid["settled"], false);
+    assert_eq!(c.cli(loan.borrower, &format!("receipt {}", loan.receipt_sn)).unwrap()["repaid"], LAMPORTS / 2);
+
+    let receiver = format!("0x{}", "01".repeat(20));
+    assert!(matches!(c.cli(loan.lender, &format!("liquidate {} --receiver {receiver}", loan.receipt_sn)), Err(CliError::Transaction(_))));
+    c.p.env.warp(TERM + GRACE + 1);
+    c.cli(loan.lender, &format!("liquidate {} --receiver {receiver}", loan.receipt_sn)).unwrap();
+    assert!(!c.p.env.exists(&pda::receipt(loan.receipt_sn)));
+    assert!(matches!(c.cli(loan.lender, &format!("receipt {}", loan.receipt_sn)), Err(CliError::NotFound(_))));
+}
+
+#[test]
+fn receipt_follows_the_installment_schedule() {
+    let mut c = HarnessCluster::new();
+    let loan = c.p.loan(LAMPORTS, 500, TERM, 2);
+    let time = c.p.env.now();
+    let quote = c.cli(loan.borrower, &format!("receipt {}", loan.receipt_sn)).unwrap();
+    assert_eq!((quote["due"].as_u64(), quote["deadline"].as_u64()), (Some(time + TERM / 2), Some(time + TERM / 2 + GRACE)));
+    assert_eq!(quote["overdue"], false);
+
+    // the first installment is overdue half way through the term and liquidatable after its grace
+    c.p.env.warp(TERM / 2 + GRACE + 1);
+    let quote = c.cli(loan.borrower, &format!("receipt {}", loan.receipt_sn)).unwrap();
+    assert_eq!(quote["overdue"], true);
+    assert!(c.p.env.now() > quote["deadline"].as_u64().unwrap());
+    let receiver = format!("0x{}", "01".repeat(20));
+    c.cli(loan.lender, &format!("liquidate {} --receiver {receiver}", loan.receipt_sn)).unwrap();
+}
+
+#[test]
+fn admin_commands() {
+    let mut c = HarnessCluster::new();
+    let admin = c.p.admin;
+
+    let queued = c.cli(admin, &format!("set-config --grace {}", 5 * DAY)).unwrap();
+    assert_eq!(queued["params"]["grace"], 5 * DAY);
+    assert_eq!(queued["params"]["max_term"], 365 * DAY);
+    assert_eq!(queued["params"]["multisig_timeout"], DAY);
+    assert_eq!(queued["eta"], c.p.env.now() + CONFIG_DELAY);
+    assert!(c.cli(admin, "apply-config").is_err());
+    c.p.env.warp(CONFIG_DELAY);
+    c.cli(admin, "apply-config").unwrap();
+    assert_eq!(c.p.env.get::<Config>(&pda::config()).grace, 5 * DAY);
+
+    let queued = c.cli(admin, &format!("set-ccfee 7 {FEE}")).unwrap();
+    assert_eq!(queued["fee"], FEE);
+    c.p.env.warp(CONFIG_DELAY);
+    c.cli(admin, "apply-ccfee 7").unwrap();
+    assert!(c.p.env.exists(&pda::ccfee(7)));
+
+    let witnesses = c.p.witnesses.clone();
+    let newcomer = Pubkey::new_unique();
+    let proposed = c.cli(witnesses[0], &format!("witness add {newcomer}")).unwrap();
+    assert_eq!((proposed["approvals"].as_u64(), proposed["executed"].as_bool()), (Some(1), Some(false)));
+    let approved = c.cli(witnesses[1], &format!("witness approve {}", proposed["proposal_sn"])).unwrap();
+    assert_eq!(approved["executed"], true);
+    assert!(c.p.env.get::<Witnesses>(&pda::witnesses()).v.contains(&newcomer));
+
+    let proposed = c.cli(witnesses[0], &format!("witness remove {newcomer}")).unwrap();
+    c.cli(witnesses[2], &format!("witness approve {}", proposed["proposal_sn"])).unwrap();
+    c.cli(witnesses[1], &format!("witness approve {}", proposed["proposal_sn"])).unwrap();
+    assert!(!c.p.env.get::<Witnesses>(&pda::witnesses()).v.contains(&newcomer));
+}
+
+#[test]
+fn parses_and_renders() {
+    let cli = Cli::try_parse_from(["cclending", "--json", "-k", "id.json", "liquidate", "3", "--receiver", "0x0101010101010101010101010101010101010101"]).unwrap();
+    assert!(cli.json);
+    assert_eq!(cli.keypair.unwrap().to_str(), Some("id.json"));
+    assert!(matches!(cli.command, Command::Liquidate { receipt_sn: 3, receiver: SOURCE }));
+    assert!(Cli::try_parse_from(["cclending", "liquidate", "3", "--receiver", "0x01"]).is_err());
+
+    let value = serde_json::json!({ "balance": 5, "owner": "abc" });
+    assert_eq!(render(&value, false), "balance: 5\nowner: abc");
+    assert_eq!(render(&serde_json::json!([{ "rate": 1, "sn": 2 }, { "rate": 3, "sn": 4 }]), false), "rate=1  sn=2\nrate=3  sn=4");
+    assert_eq!(serde_json::from_str::<Value>(&render(&value, true)).unwrap(), value);
+}
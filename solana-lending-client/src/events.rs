@@ -0,0 +1,125 @@
+//! Decoders for the `Event*` structs the program emits with `emit!`, which show up in the
+//! transaction logs as `Program data: <base64>` lines.
+
+use anchor_lang::{AnchorDeserialize, Discriminator};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use ::solana_lending::*;
+
+macro_rules! lending_events {
+    ($($variant:ident => $event:ident,)*) => {
+        pub enum LendingEvent {
+            $($variant($event),)*
+        }
+
+        impl LendingEvent {
+            pub fn name(&self) -> &'static str {
+                match self {
+                    $(LendingEvent::$variant(_) => stringify!($event),)*
+                }
+            }
+        }
+
+        /// Decodes the discriminator-prefixed payload of a single event. Returns `None` for
+        /// data that is not one of the program's events.
+        pub fn decode(data: &[u8]) -> Option<LendingEvent> {
+            if data.len() < 8 {
+                return None;
+            }
+            let (discriminator, mut payload) = data.split_at(8);
+            $(
+                if discriminator == $event::discriminator() {
+                    return $event::deserialize(&mut payload).ok().map(LendingEvent::$variant);
+                }
+            )*
+            None
+        }
+    };
+}
+
+lending_events! {
+    ConfigQueued => EventConfigQueued,
+    ConfigChanged => EventConfigChanged,
+    CCFeeQueued => EventCCFeeQueued,
+    CCFeeChanged => EventCCFeeChanged,
+    SetPause => EventSetPause,
+    GrantRole => EventGrantRole,
+    RevokeRole => EventRevokeRole,
+    ProposeAdmin => EventProposeAdmin,
+    AcceptAdmin => EventAcceptAdmin,
+    PlaceOrder => EventPlaceOrder,
+    CancelOrder => EventCancelOrder,
+    CloseOrder => EventCloseOrder,
+    BorrowSuccess => EventBorrowSuccess,
+    RepaySuccess => EventRepaySuccess,
+    PartialRepay => EventPartialRepay,
+    ExtendLoan => EventExtendLoan,
+    AutoReinvest => EventAutoReinvest,
+    Reinvest => EventReinvest,
+    Liquidate => EventLiquidate,
+    ProposeWitnessChange => EventProposeWitnessChange,
+    ApproveWitnessChange => EventApproveWitnessChange,
+    WitnessChanged => EventWitnessChanged,
+    ProposeTreasuryWithdrawal => EventProposeTreasuryWithdrawal,
+    ApproveTreasuryWithdrawal => EventApproveTreasuryWithdrawal,
+    TreasuryWithdrawal => EventTreasuryWithdrawal,
+    CreditRewards => EventCreditRewards,
+    ClaimReward => EventClaimReward,
+    DepositStake => EventDepositStake,
+    UnbondStake => EventUnbondStake,
+    WithdrawStake => EventWithdrawStake,
+    SlashStake => EventSlashStake,
+    PriceSubmitted => EventPriceSubmitted,
+    FreezeCollateral => EventFreezeCollateral,
+    UnfreezeCollateral => EventUnfreezeCollateral,
+    LiquidateCollateral => EventLiquidateCollateral,
+    RevokeReleaseSignature => EventRevokeReleaseSignature,
+    ReleaseExpired => EventReleaseExpired,
+    CollateralRejected => EventCollateralRejected,
+    BorrowFailed => EventBorrowFailed,
+    RecountCollateral => EventRecountCollateral,
+    RevokeCollateralSignature => EventRevokeCollateralSignature,
+    ReserveOrder => EventReserveOrder,
+    ReleaseReservation => EventReleaseReservation,
+}
+
+/// Decodes every event in a transaction's log messages. Only data logged while the lending
+/// program itself is executing is considered, so events of other programs invoked in the
+/// same transaction are skipped.
+pub fn parse_logs<S: AsRef<str>>(logs: &[S]) -> Vec<LendingEvent> {
+    let program = ID.to_string();
+    let mut stack: Vec<&str> = Vec::new();
+    let mut events = Vec::new();
+    for line in logs {
+        let line = line.as_ref();
+        if let Some(data) = line.strip_prefix("Program data: ") {
+            if stack.last() != Some(&program.as_str()) {
+                continue;
+            }
+            if let Some(event) = STANDARD.decode(data).ok().and_then(|data| decode(&data)) {
+                events.push(event);
+            }
+        } else if line.starts_with("Program log: ") || line.starts_with("Program return: ") {
+            continue;
+        } else if let Some(rest) = line.strip_prefix("Program ") {
+            let mut words = rest.split_whitespace();
+            let (Some(id), Some(word)) = (words.next(), words.next()) else {
+                continue;
+            };
+            match word {
+                "invoke" => stack.push(id),
+                "success" | "failed:" => {
+                    stack.pop();
+                }
+                _ => {}
+            }
+        }
+    }
+    events
+}
+
+/// Formats an event the way it appears in the logs, for feeding test or replay data through
+/// [`parse_logs`].
+pub fn log_line(data: &[u8]) -> String {
+    format!("Program data: {}", STANDARD.encode(data))
+}
@@ -0,0 +1,925 @@
+//! One builder per program instruction. Accounts are derived with [`crate::pda`]; sequence
+//! numbers the program assigns itself (next order, receipt, proposal, freeze or withdrawal)
+//! have to be read from `GlobalState` or `Treasury` by the caller.
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::{AccountMeta, Instruction};
+use anchor_lang::solana_program::{system_program, sysvar};
+use anchor_lang::{InstructionData, ToAccountMetas};
+use ::solana_lending::{accounts, instruction, Collateral, ConfigParams, LoanReceipt, ReleaseAction, Role, TreasuryCategory, WitnessAction, ID};
+
+use crate::pda;
+
+fn ix<A: ToAccountMetas, D: InstructionData>(accounts: A, data: D) -> Instruction {
+    Instruction {
+        program_id: ID,
+        accounts: accounts.to_account_metas(None),
+        data: data.data(),
+    }
+}
+
+pub fn initialize(payer: &Pubkey) -> Instruction {
+    ix(
+        accounts::Initialize {
+            global_state: pda::global_state(),
+            reward_pool: pda::reward_pool(),
+            treasury: pda::treasury(),
+            program_data: pda::program_data(),
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::Initialize {},
+    )
+}
+
+pub fn propose_admin(payer: &Pubkey, new_admin: Pubkey) -> Instruction {
+    ix(
+        accounts::UpdateAdmin {
+            global: pda::global_state(),
+            payer: *payer,
+        },
+        instruction::ProposeAdmin { new_admin },
+    )
+}
+
+pub fn accept_admin(payer: &Pubkey) -> Instruction {
+    ix(
+        accounts::UpdateAdmin {
+            global: pda::global_state(),
+            payer: *payer,
+        },
+        instruction::AcceptAdmin {},
+    )
+}
+
+pub fn set_pause(payer: &Pubkey, paused: u64) -> Instruction {
+    ix(
+        accounts::SetPause {
+            global: pda::global_state(),
+            roles: pda::roles(),
+            payer: *payer,
+        },
+        instruction::SetPause { paused },
+    )
+}
+
+fn update_roles(payer: &Pubkey) -> accounts::UpdateRoles {
+    accounts::UpdateRoles {
+        roles: pda::roles(),
+        global: pda::global_state(),
+        payer: *payer,
+        system_program: system_program::ID,
+    }
+}
+
+pub fn grant_role(payer: &Pubkey, role: Role, key: Pubkey) -> Instruction {
+    ix(update_roles(payer), instruction::GrantRole { role, key })
+}
+
+pub fn revoke_role(payer: &Pubkey, role: Role, key: Pubkey) -> Instruction {
+    ix(update_roles(payer), instruction::RevokeRole { role, key })
+}
+
+pub fn queue_config(payer: &Pubkey, params: ConfigParams) -> Instruction {
+    ix(
+        accounts::QueueConfig {
+            pending_config: pda::pending_config(),
+            config: pda::config(),
+            roles: pda::roles(),
+            payer: *payer,
+            system_program: system_program::ID,
+            clock: sysvar::clock::ID,
+        },
+        instruction::QueueConfig { params },
+    )
+}
+
+pub fn apply_config(payer: &Pubkey) -> Instruction {
+    ix(
+        accounts::ApplyConfig {
+            pending_config: pda::pending_config(),
+            config: pda::config(),
+            roles: pda::roles(),
+            payer: *payer,
+            clock: sysvar::clock::ID,
+        },
+        instruction::ApplyConfig {},
+    )
+}
+
+pub fn queue_ccfee(payer: &Pubkey, chainid: u32, fee: u64) -> Instruction {
+    ix(
+        accounts::QueueCCFee {
+            pending_ccfee: pda::pending_ccfee(chainid),
+            roles: pda::roles(),
+            payer: *payer,
+            system_program: system_program::ID,
+            clock: sysvar::clock::ID,
+        },
+        instruction::QueueCcfee { chainid, fee },
+    )
+}
+
+pub fn apply_ccfee(payer: &Pubkey, chainid: u32) -> Instruction {
+    ix(
+        accounts::ApplyCCFee {
+            pending_ccfee: pda::pending_ccfee(chainid),
+            cc_fee: pda::ccfee(chainid),
+            roles: pda::roles(),
+            payer: *payer,
+            system_program: system_program::ID,
+            clock: sysvar::clock::ID,
+        },
+        instruction::ApplyCcfee { chainid },
+    )
+}
+
+pub fn deposit(payer: &Pubkey, amount: u64) -> Instruction {
+    ix(
+        accounts::Deposit {
+            user_balance: pda::user_balance(payer),
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::Deposit { amount },
+    )
+}
+
+pub fn withdraw(payer: &Pubkey, amount: u64) -> Instruction {
+    ix(
+        accounts::Withdraw {
+            user_balance: pda::user_balance(payer),
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::Withdraw { amount },
+    )
+}
+
+/// `order_sn` is the current `GlobalState.curr_order_sn`.
+pub fn place_order(payer: &Pubkey, order_sn: u64, amount: u64, rate: u64, term: u64, installments: u8) -> Instruction {
+    ix(
+        accounts::PlaceOrder {
+            order: pda::order(order_sn),
+            user_balance: pda::user_balance(payer),
+            global: pda::global_state(),
+            config: pda::config(),
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::PlaceOrder { amount, rate, term, installments },
+    )
+}
+
+pub fn cancel_order(payer: &Pubkey, order_sn: u64) -> Instruction {
+    ix(
+        accounts::CancelOrder {
+            order: pda::order(order_sn),
+            user_balance: pda::user_balance(payer),
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::CancelOrder { order_sn },
+    )
+}
+
+pub fn close_order(payer: &Pubkey, order_sn: u64, lender: &Pubkey) -> Instruction {
+    ix(
+        accounts::CloseOrder {
+            order: pda::order(order_sn),
+            lender: *lender,
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::CloseOrder { order_sn },
+    )
+}
+
+pub fn multisig_collateral(payer: &Pubkey, c: &Collateral) -> Instruction {
+    ix(
+        accounts::MultisigCollateral {
+            multisig: pda::collateral(c.chainid, c.collateral_sn),
+            order: pda::order(c.order_sn),
+            config: pda::config(),
+            witnesses: pda::witnesses(),
+            global: pda::global_state(),
+            reward: pda::reward(payer),
+            payer: *payer,
+            system_program: system_program::ID,
+            clock: sysvar::clock::ID,
+        },
+        instruction::MultisigCollateral {
+            chainid: c.chainid,
+            collateral_sn: c.collateral_sn,
+            source: c.source,
+            token: c.token,
+            frozen: c.frozen,
+            order_sn: c.order_sn,
+            borrower: c.borrower,
+            amount: c.amount,
+        },
+    )
+}
+
+/// `c` is the collateral of the winning branch and `receipt_sn` the current
+/// `GlobalState.curr_receipt_sn`.
+pub fn borrow(payer: &Pubkey, c: &Collateral, receipt_sn: u64) -> Instruction {
+    ix(
+        accounts::Borrow {
+            multisig: pda::collateral(c.chainid, c.collateral_sn),
+            witnesses: pda::witnesses(),
+            receipt: pda::receipt(receipt_sn),
+            order: pda::order(c.order_sn),
+            global: pda::global_state(),
+            config: pda::config(),
+            cc_fee: pda::ccfee(c.chainid),
+            recipient: c.borrower,
+            payer: *payer,
+            system_program: system_program::ID,
+            clock: sysvar::clock::ID,
+        },
+        instruction::Borrow { _chainid: c.chainid, _collateral_sn: c.collateral_sn },
+    )
+}
+
+/// Re-evaluates a round's consensus after the witness set changed; anyone can send it.
+pub fn recount_collateral(payer: &Pubkey, chainid: u32, collateral_sn: u64) -> Instruction {
+    ix(
+        accounts::RecountCollateral {
+            multisig: pda::collateral(chainid, collateral_sn),
+            witnesses: pda::witnesses(),
+            payer: *payer,
+        },
+        instruction::RecountCollateral { chainid, collateral_sn },
+    )
+}
+
+pub fn revoke_collateral_signature(payer: &Pubkey, chainid: u32, collateral_sn: u64) -> Instruction {
+    ix(
+        accounts::RevokeCollateralSignature {
+            multisig: pda::collateral(chainid, collateral_sn),
+            witnesses: pda::witnesses(),
+            payer: *payer,
+        },
+        instruction::RevokeCollateralSignature { chainid, collateral_sn },
+    )
+}
+
+/// Signed by the borrower of round `collateral_sn` once a witness has signed it.
+pub fn reserve_order(borrower: &Pubkey, chainid: u32, collateral_sn: u64, order_sn: u64, amount: u64) -> Instruction {
+    ix(
+        accounts::ReserveOrder {
+            multisig: pda::collateral(chainid, collateral_sn),
+            order: pda::order(order_sn),
+            config: pda::config(),
+            payer: *borrower,
+            clock: sysvar::clock::ID,
+        },
+        instruction::ReserveOrder { chainid, collateral_sn, _order_sn: order_sn, amount },
+    )
+}
+
+/// `order_sn` is the reserved order, `CollateralWithMultisig.reservation.order_sn`.
+pub fn release_reservation(payer: &Pubkey, chainid: u32, collateral_sn: u64, order_sn: u64) -> Instruction {
+    ix(
+        accounts::ReleaseReservation {
+            multisig: pda::collateral(chainid, collateral_sn),
+            order: pda::order(order_sn),
+            payer: *payer,
+            clock: sysvar::clock::ID,
+        },
+        instruction::ReleaseReservation { chainid, collateral_sn },
+    )
+}
+
+/// `c` is the collateral of the winning branch.
+pub fn fail_borrow(payer: &Pubkey, c: &Collateral) -> Instruction {
+    ix(
+        accounts::FailBorrow {
+            multisig: pda::collateral(c.chainid, c.collateral_sn),
+            order: pda::order(c.order_sn),
+            payer: *payer,
+        },
+        instruction::FailBorrow { _chainid: c.chainid, _collateral_sn: c.collateral_sn },
+    )
+}
+
+pub fn expire_collateral(payer: &Pubkey, chainid: u32, collateral_sn: u64) -> Instruction {
+    ix(
+        accounts::ExpireCollateral {
+            multisig: pda::collateral(chainid, collateral_sn),
+            config: pda::config(),
+            witnesses: pda::witnesses(),
+            payer: *payer,
+            clock: sysvar::clock::ID,
+        },
+        instruction::ExpireCollateral { chainid, collateral_sn },
+    )
+}
+
+pub fn clear_collateral(payer: &Pubkey, chainid: u32, collateral_sn: u64) -> Instruction {
+    ix(
+        accounts::ClearCollateral {
+            multisig: pda::collateral(chainid, collateral_sn),
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::ClearCollateral { _chainid: chainid, _collateral_sn: collateral_sn },
+    )
+}
+
+pub fn enable_auto_reinvest(payer: &Pubkey, order_sn: u64, mint: &Pubkey) -> Instruction {
+    ix(
+        accounts::EnableAutoReinvest {
+            order: pda::order(order_sn),
+            reinvest: pda::reinvest(payer, mint),
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::EnableAutoReinvest { order_sn },
+    )
+}
+
+pub fn disable_auto_reinvest(payer: &Pubkey, mint: Pubkey) -> Instruction {
+    ix(
+        accounts::DisableAutoReinvest {
+            reinvest: pda::reinvest(payer, &mint),
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::DisableAutoReinvest { mint },
+    )
+}
+
+// the fee of a repayment, extension or liquidation is credited to the reward account of
+// every witness in `LoanReceipt.signers`, passed in signer order after the named accounts
+fn with_rewards(mut ix: Instruction, receipt: &LoanReceipt) -> Instruction {
+    ix.accounts.extend(receipt.signers.iter().map(|s| AccountMeta::new(pda::reward(s), false)));
+    ix
+}
+
+// the program ignores `reinvest_order` unless the lender's auto-reinvest setting exists
+fn reinvest_order(reinvest_order_sn: Option<u64>) -> Pubkey {
+    pda::order(reinvest_order_sn.unwrap_or_default())
+}
+
+/// `reinvest_order_sn` is `AutoReinvest.order_sn` when the lender has auto-reinvest enabled.
+pub fn repay(payer: &Pubkey, receipt: &LoanReceipt, reinvest_order_sn: Option<u64>) -> Instruction {
+    with_rewards(ix(repay_accounts(payer, receipt, reinvest_order_sn), instruction::Repay { receipt_sn: receipt.sn }), receipt)
+}
+
+pub fn repay_partial(payer: &Pubkey, receipt: &LoanReceipt, reinvest_order_sn: Option<u64>, amount: u64) -> Instruction {
+    with_rewards(ix(repay_accounts(payer, receipt, reinvest_order_sn), instruction::RepayPartial { receipt_sn: receipt.sn, amount }), receipt)
+}
+
+fn repay_accounts(payer: &Pubkey, receipt: &LoanReceipt, reinvest_order_sn: Option<u64>) -> accounts::Repay {
+    accounts::Repay {
+        receipt: pda::receipt(receipt.sn),
+        lender_balance: pda::user_balance(&receipt.lender),
+        reinvest: pda::reinvest(&receipt.lender, &receipt.mint),
+        reinvest_order: reinvest_order(reinvest_order_sn),
+        config: pda::config(),
+        treasury: pda::treasury(),
+        cc_fee: pda::ccfee(receipt.chainid),
+        reward_pool: pda::reward_pool(),
+        payer: *payer,
+        system_program: system_program::ID,
+        clock: sysvar::clock::ID,
+    }
+}
+
+/// `lender` must co-sign when `rate` differs from the receipt's rate.
+pub fn extend_loan(payer: &Pubkey, receipt: &LoanReceipt, rate: u64, lender: Option<Pubkey>) -> Instruction {
+    with_rewards(
+        ix(
+            accounts::ExtendLoan {
+                receipt: pda::receipt(receipt.sn),
+                lender_balance: pda::user_balance(&receipt.lender),
+                config: pda::config(),
+                global: pda::global_state(),
+                treasury: pda::treasury(),
+                cc_fee: pda::ccfee(receipt.chainid),
+                reward_pool: pda::reward_pool(),
+                lender,
+                payer: *payer,
+                system_program: system_program::ID,
+                clock: sysvar::clock::ID,
+            },
+            instruction::ExtendLoan { receipt_sn: receipt.sn, rate },
+        ),
+        receipt,
+    )
+}
+
+pub fn liquidate(payer: &Pubkey, receipt: &LoanReceipt, receiver: [u8; 20]) -> Instruction {
+    with_rewards(
+        ix(
+            accounts::Liquidate {
+                receipt: pda::receipt(receipt.sn),
+                treasury: pda::treasury(),
+                cc_fee: pda::ccfee(receipt.chainid),
+                reward_pool: pda::reward_pool(),
+                payer: *payer,
+                system_program: system_program::ID,
+                clock: sysvar::clock::ID,
+            },
+            instruction::Liquidate { receipt_sn: receipt.sn, receiver },
+        ),
+        receipt,
+    )
+}
+
+pub fn init_witnesses(payer: &Pubkey, v: Vec<Pubkey>) -> Instruction {
+    ix(
+        accounts::UpdateWitnesses {
+            witnesses: pda::witnesses(),
+            roles: pda::roles(),
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::InitWitnesses { v },
+    )
+}
+
+/// `proposal_sn` is the current `GlobalState.curr_proposal_sn`.
+/// Pass `staked` when the affected witness has a stake account.
+pub fn propose_witness_change(payer: &Pubkey, proposal_sn: u64, action: WitnessAction, witness: Pubkey, staked: bool) -> Instruction {
+    ix(
+        accounts::ProposeWitnessChange {
+            proposal: pda::proposal(proposal_sn),
+            witnesses: pda::witnesses(),
+            global: pda::global_state(),
+            stake: staked.then(|| pda::stake(&witness)),
+            stake_config: pda::stake_config(),
+            treasury: pda::treasury(),
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::ProposeWitnessChange { action, witness },
+    )
+}
+
+/// `witness` is the proposal's `WitnessProposal.witness`.
+pub fn approve_witness_change(payer: &Pubkey, proposal_sn: u64, witness: &Pubkey, staked: bool) -> Instruction {
+    ix(
+        accounts::ApproveWitnessChange {
+            proposal: pda::proposal(proposal_sn),
+            witnesses: pda::witnesses(),
+            stake: staked.then(|| pda::stake(witness)),
+            stake_config: pda::stake_config(),
+            global: pda::global_state(),
+            treasury: pda::treasury(),
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::ApproveWitnessChange { proposal_sn },
+    )
+}
+
+pub fn claim_reward(payer: &Pubkey) -> Instruction {
+    ix(
+        accounts::ClaimReward {
+            reward_pool: pda::reward_pool(),
+            reward: pda::reward(payer),
+            payer: *payer,
+        },
+        instruction::ClaimReward {},
+    )
+}
+
+pub fn set_treasury_threshold(payer: &Pubkey, threshold: u8) -> Instruction {
+    ix(
+        accounts::SetTreasuryThreshold {
+            treasury: pda::treasury(),
+            global: pda::global_state(),
+            payer: *payer,
+        },
+        instruction::SetTreasuryThreshold { threshold },
+    )
+}
+
+/// `withdrawal_sn` is the current `Treasury.curr_withdrawal_sn`.
+pub fn propose_treasury_withdrawal(
+    payer: &Pubkey,
+    withdrawal_sn: u64,
+    category: TreasuryCategory,
+    mint: Pubkey,
+    amount: u64,
+    receiver: Pubkey,
+) -> Instruction {
+    ix(
+        accounts::ProposeTreasuryWithdrawal {
+            withdrawal: pda::withdrawal(withdrawal_sn),
+            treasury: pda::treasury(),
+            roles: pda::roles(),
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::ProposeTreasuryWithdrawal { category, mint, amount, receiver },
+    )
+}
+
+pub fn approve_treasury_withdrawal(payer: &Pubkey, withdrawal_sn: u64) -> Instruction {
+    ix(
+        accounts::ApproveTreasuryWithdrawal {
+            withdrawal: pda::withdrawal(withdrawal_sn),
+            roles: pda::roles(),
+            payer: *payer,
+        },
+        instruction::ApproveTreasuryWithdrawal { withdrawal_sn },
+    )
+}
+
+pub fn execute_treasury_withdrawal(payer: &Pubkey, withdrawal_sn: u64, receiver: &Pubkey) -> Instruction {
+    ix(
+        accounts::ExecuteTreasuryWithdrawal {
+            withdrawal: pda::withdrawal(withdrawal_sn),
+            treasury: pda::treasury(),
+            roles: pda::roles(),
+            receiver: *receiver,
+            payer: *payer,
+        },
+        instruction::ExecuteTreasuryWithdrawal { withdrawal_sn },
+    )
+}
+
+/// `receiver` is the token account recorded in the withdrawal.
+pub fn execute_token_treasury_withdrawal(payer: &Pubkey, withdrawal_sn: u64, mint: &Pubkey, receiver: &Pubkey) -> Instruction {
+    ix(
+        accounts::ExecuteTokenTreasuryWithdrawal {
+            withdrawal: pda::withdrawal(withdrawal_sn),
+            treasury: pda::treasury(),
+            treasury_balance: pda::treasury_balance(mint),
+            vault: pda::vault(mint),
+            roles: pda::roles(),
+            receiver: *receiver,
+            payer: *payer,
+            token_program: anchor_spl::token::ID,
+        },
+        instruction::ExecuteTokenTreasuryWithdrawal { withdrawal_sn },
+    )
+}
+
+pub fn set_stake_config(payer: &Pubkey, min_stake: u64, unbonding_period: u64) -> Instruction {
+    ix(
+        accounts::SetStakeConfig {
+            stake_config: pda::stake_config(),
+            roles: pda::roles(),
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::SetStakeConfig { min_stake, unbonding_period },
+    )
+}
+
+pub fn deposit_stake(payer: &Pubkey, amount: u64) -> Instruction {
+    ix(
+        accounts::DepositStake {
+            stake: pda::stake(payer),
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::DepositStake { amount },
+    )
+}
+
+pub fn unbond_stake(payer: &Pubkey) -> Instruction {
+    ix(
+        accounts::UnbondStake {
+            stake: pda::stake(payer),
+            stake_config: pda::stake_config(),
+            witnesses: pda::witnesses(),
+            payer: *payer,
+            clock: sysvar::clock::ID,
+        },
+        instruction::UnbondStake {},
+    )
+}
+
+pub fn withdraw_stake(payer: &Pubkey, amount: u64) -> Instruction {
+    ix(
+        accounts::WithdrawStake {
+            stake: pda::stake(payer),
+            witnesses: pda::witnesses(),
+            payer: *payer,
+            clock: sysvar::clock::ID,
+        },
+        instruction::WithdrawStake { amount },
+    )
+}
+
+pub fn submit_price(payer: &Pubkey, chainid: u32, token: [u8; 20], price: u64) -> Instruction {
+    ix(
+        accounts::SubmitPrice {
+            price_feed: pda::price_feed(chainid, &token),
+            witnesses: pda::witnesses(),
+            payer: *payer,
+            system_program: system_program::ID,
+            clock: sysvar::clock::ID,
+        },
+        instruction::SubmitPrice { chainid, token, price },
+    )
+}
+
+/// The median is returned through the transaction's return data.
+pub fn get_price(chainid: u32, token: [u8; 20], max_age: u64) -> Instruction {
+    ix(
+        accounts::GetPrice {
+            price_feed: pda::price_feed(chainid, &token),
+            witnesses: pda::witnesses(),
+            clock: sysvar::clock::ID,
+        },
+        instruction::GetPrice { _chainid: chainid, _token: token, max_age },
+    )
+}
+
+pub fn deposit_token(payer: &Pubkey, mint: &Pubkey, payer_token: &Pubkey, amount: u64) -> Instruction {
+    ix(
+        accounts::DepositToken {
+            user_balance: pda::token_balance(payer, mint),
+            vault: pda::vault(mint),
+            payer_token: *payer_token,
+            mint: *mint,
+            payer: *payer,
+            token_program: anchor_spl::token::ID,
+            system_program: system_program::ID,
+            rent: sysvar::rent::ID,
+        },
+        instruction::DepositToken { amount },
+    )
+}
+
+pub fn withdraw_token(payer: &Pubkey, mint: &Pubkey, recipient: &Pubkey, amount: u64) -> Instruction {
+    ix(
+        accounts::WithdrawToken {
+            user_balance: pda::token_balance(payer, mint),
+            vault: pda::vault(mint),
+            recipient: *recipient,
+            mint: *mint,
+            payer: *payer,
+            token_program: anchor_spl::token::ID,
+        },
+        instruction::WithdrawToken { amount },
+    )
+}
+
+/// `order_sn` is the current `GlobalState.curr_order_sn`.
+#[allow(clippy::too_many_arguments)]
+pub fn place_token_order(payer: &Pubkey, order_sn: u64, mint: &Pubkey, amount: u64, rate: u64, term: u64, installments: u8) -> Instruction {
+    ix(
+        accounts::PlaceTokenOrder {
+            order: pda::order(order_sn),
+            user_balance: pda::token_balance(payer, mint),
+            mint: *mint,
+            global: pda::global_state(),
+            config: pda::config(),
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::PlaceTokenOrder { amount, rate, term, installments },
+    )
+}
+
+pub fn cancel_token_order(payer: &Pubkey, order_sn: u64, mint: &Pubkey) -> Instruction {
+    ix(
+        accounts::CancelTokenOrder {
+            order: pda::order(order_sn),
+            user_balance: pda::token_balance(payer, mint),
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::CancelTokenOrder { order_sn },
+    )
+}
+
+/// `recipient` is a token account of the order's mint owned by the borrower.
+pub fn borrow_token(payer: &Pubkey, c: &Collateral, receipt_sn: u64, mint: &Pubkey, recipient: &Pubkey) -> Instruction {
+    ix(
+        accounts::BorrowToken {
+            multisig: pda::collateral(c.chainid, c.collateral_sn),
+            witnesses: pda::witnesses(),
+            receipt: pda::receipt(receipt_sn),
+            order: pda::order(c.order_sn),
+            vault: pda::vault(mint),
+            global: pda::global_state(),
+            config: pda::config(),
+            cc_fee: pda::ccfee(c.chainid),
+            recipient: *recipient,
+            payer: *payer,
+            token_program: anchor_spl::token::ID,
+            system_program: system_program::ID,
+            clock: sysvar::clock::ID,
+        },
+        instruction::BorrowToken { _chainid: c.chainid, _collateral_sn: c.collateral_sn },
+    )
+}
+
+pub fn repay_token(payer: &Pubkey, receipt: &LoanReceipt, reinvest_order_sn: Option<u64>, payer_token: &Pubkey) -> Instruction {
+    with_rewards(
+        ix(
+            repay_token_accounts(payer, receipt, reinvest_order_sn, payer_token),
+            instruction::RepayToken { receipt_sn: receipt.sn },
+        ),
+        receipt,
+    )
+}
+
+pub fn repay_token_partial(payer: &Pubkey, receipt: &LoanReceipt, reinvest_order_sn: Option<u64>, payer_token: &Pubkey, amount: u64) -> Instruction {
+    with_rewards(
+        ix(
+            repay_token_accounts(payer, receipt, reinvest_order_sn, payer_token),
+            instruction::RepayTokenPartial { receipt_sn: receipt.sn, amount },
+        ),
+        receipt,
+    )
+}
+
+fn repay_token_accounts(payer: &Pubkey, receipt: &LoanReceipt, reinvest_order_sn: Option<u64>, payer_token: &Pubkey) -> accounts::RepayToken {
+    accounts::RepayToken {
+        receipt: pda::receipt(receipt.sn),
+        lender_balance: pda::token_balance(&receipt.lender, &receipt.mint),
+        vault: pda::vault(&receipt.mint),
+        reinvest: pda::reinvest(&receipt.lender, &receipt.mint),
+        reinvest_order: reinvest_order(reinvest_order_sn),
+        payer_token: *payer_token,
+        config: pda::config(),
+        treasury: pda::treasury(),
+        treasury_balance: pda::treasury_balance(&receipt.mint),
+        cc_fee: pda::ccfee(receipt.chainid),
+        reward_pool: pda::reward_pool(),
+        payer: *payer,
+        token_program: anchor_spl::token::ID,
+        system_program: system_program::ID,
+        clock: sysvar::clock::ID,
+    }
+}
+
+pub fn extend_token_loan(payer: &Pubkey, receipt: &LoanReceipt, payer_token: &Pubkey, rate: u64, lender: Option<Pubkey>) -> Instruction {
+    with_rewards(
+        ix(
+            accounts::ExtendTokenLoan {
+                receipt: pda::receipt(receipt.sn),
+                lender_balance: pda::token_balance(&receipt.lender, &receipt.mint),
+                vault: pda::vault(&receipt.mint),
+                payer_token: *payer_token,
+                global: pda::global_state(),
+                config: pda::config(),
+                treasury: pda::treasury(),
+                treasury_balance: pda::treasury_balance(&receipt.mint),
+                cc_fee: pda::ccfee(receipt.chainid),
+                reward_pool: pda::reward_pool(),
+                lender,
+                payer: *payer,
+                token_program: anchor_spl::token::ID,
+                system_program: system_program::ID,
+                clock: sysvar::clock::ID,
+            },
+            instruction::ExtendTokenLoan { receipt_sn: receipt.sn, rate },
+        ),
+        receipt,
+    )
+}
+
+pub fn deposit_collateral(payer: &Pubkey, amount: u64) -> Instruction {
+    ix(
+        accounts::DepositCollateral {
+            user_collateral: pda::mortgage(payer, &Pubkey::default()),
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::DepositCollateral { amount },
+    )
+}
+
+pub fn withdraw_collateral(payer: &Pubkey, amount: u64) -> Instruction {
+    ix(
+        accounts::WithdrawCollateral {
+            user_collateral: pda::mortgage(payer, &Pubkey::default()),
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::WithdrawCollateral { amount },
+    )
+}
+
+pub fn deposit_token_collateral(payer: &Pubkey, mint: &Pubkey, payer_token: &Pubkey, amount: u64) -> Instruction {
+    ix(
+        accounts::DepositTokenCollateral {
+            user_collateral: pda::mortgage(payer, mint),
+            vault: pda::vault(mint),
+            payer_token: *payer_token,
+            mint: *mint,
+            payer: *payer,
+            token_program: anchor_spl::token::ID,
+            system_program: system_program::ID,
+            rent: sysvar::rent::ID,
+        },
+        instruction::DepositTokenCollateral { amount },
+    )
+}
+
+pub fn withdraw_token_collateral(payer: &Pubkey, mint: &Pubkey, recipient: &Pubkey, amount: u64) -> Instruction {
+    ix(
+        accounts::WithdrawTokenCollateral {
+            user_collateral: pda::mortgage(payer, mint),
+            vault: pda::vault(mint),
+            recipient: *recipient,
+            mint: *mint,
+            payer: *payer,
+            token_program: anchor_spl::token::ID,
+        },
+        instruction::WithdrawTokenCollateral { amount },
+    )
+}
+
+/// `freeze_sn` is the current `GlobalState.curr_freeze_sn`.
+#[allow(clippy::too_many_arguments)]
+pub fn freeze_collateral(
+    payer: &Pubkey,
+    freeze_sn: u64,
+    mint: Pubkey,
+    chainid: u32,
+    order_sn: u64,
+    frozen: u64,
+    amount: u64,
+    receiver: [u8; 20],
+) -> Instruction {
+    ix(
+        accounts::FreezeCollateral {
+            user_collateral: pda::mortgage(payer, &mint),
+            freeze: pda::freeze(freeze_sn),
+            global: pda::global_state(),
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::FreezeCollateral { mint, chainid, order_sn, frozen, amount, receiver },
+    )
+}
+
+pub fn multisig_release(payer: &Pubkey, freeze_sn: u64, action: ReleaseAction, lender: Pubkey) -> Instruction {
+    ix(
+        accounts::MultisigRelease {
+            multisig: pda::release(freeze_sn),
+            witnesses: pda::witnesses(),
+            global: pda::global_state(),
+            payer: *payer,
+            system_program: system_program::ID,
+            clock: sysvar::clock::ID,
+        },
+        instruction::MultisigRelease { freeze_sn, action, lender },
+    )
+}
+
+pub fn revoke_release_signature(payer: &Pubkey, freeze_sn: u64) -> Instruction {
+    ix(
+        accounts::RevokeReleaseSignature {
+            multisig: pda::release(freeze_sn),
+            witnesses: pda::witnesses(),
+            payer: *payer,
+        },
+        instruction::RevokeReleaseSignature { freeze_sn },
+    )
+}
+
+pub fn expire_release(payer: &Pubkey, freeze_sn: u64) -> Instruction {
+    ix(
+        accounts::ExpireRelease {
+            multisig: pda::release(freeze_sn),
+            config: pda::config(),
+            witnesses: pda::witnesses(),
+            payer: *payer,
+            clock: sysvar::clock::ID,
+        },
+        instruction::ExpireRelease { freeze_sn },
+    )
+}
+
+/// `owner` and `mint` come from the `FrozenCollateral` account.
+pub fn unfreeze_collateral(payer: &Pubkey, freeze_sn: u64, owner: &Pubkey, mint: &Pubkey) -> Instruction {
+    ix(
+        accounts::UnfreezeCollateral {
+            multisig: pda::release(freeze_sn),
+            witnesses: pda::witnesses(),
+            freeze: pda::freeze(freeze_sn),
+            owner_collateral: pda::mortgage(owner, mint),
+            payer: *payer,
+        },
+        instruction::UnfreezeCollateral { freeze_sn },
+    )
+}
+
+/// `lender` is the lender of the agreed release.
+pub fn liquidate_collateral(payer: &Pubkey, freeze_sn: u64, owner: &Pubkey, mint: &Pubkey, lender: &Pubkey) -> Instruction {
+    ix(
+        accounts::LiquidateCollateral {
+            multisig: pda::release(freeze_sn),
+            witnesses: pda::witnesses(),
+            freeze: pda::freeze(freeze_sn),
+            owner_collateral: pda::mortgage(owner, mint),
+            lender_collateral: pda::mortgage(lender, mint),
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::LiquidateCollateral { freeze_sn },
+    )
+}
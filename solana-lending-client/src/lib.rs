@@ -0,0 +1,21 @@
+//! Client-side helpers for the solana-lending program: PDA derivations, instruction builders,
+//! account deserializers and event decoders.
+
+pub mod events;
+pub mod instructions;
+pub mod pda;
+
+use anchor_lang::solana_program::program_error::ProgramError;
+use anchor_lang::AccountDeserialize;
+
+pub use ::solana_lending::{
+    amount_due, next_due, AutoReinvest, CCFee, Collateral, CollateralWithMultisig, CollateralWithSigners, Config, ConfigParams, FreezeStatus, FrozenCollateral,
+    GlobalState, LoanReceipt, MultisigStatus, MyError, Order, PendingCCFee, PendingConfig, PriceFeed, PriceSubmission, Release, ReleaseAction, ReleaseWithMultisig,
+    ReleaseWithSigners, Reservation, RewardPool, Role, Roles, StakeConfig, Treasury, TreasuryCategory, TreasuryWithdrawal, UserBalance, WitnessAction,
+    WitnessProposal, WitnessReward, WitnessStake, Witnesses, ID,
+};
+
+/// Deserializes account data fetched from RPC, checking the account discriminator.
+pub fn decode_account<T: AccountDeserialize>(mut data: &[u8]) -> Result<T, ProgramError> {
+    T::try_deserialize(&mut data).map_err(Into::into)
+}
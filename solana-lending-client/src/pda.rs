@@ -0,0 +1,125 @@
+//! Addresses of the program accounts, derived from the same seeds as the program.
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::bpf_loader_upgradeable;
+use ::solana_lending::*;
+
+pub fn find(seeds: &[&[u8]]) -> (Pubkey, u8) {
+    Pubkey::find_program_address(seeds, &ID)
+}
+
+fn address(seeds: &[&[u8]]) -> Pubkey {
+    find(seeds).0
+}
+
+/// The program's `ProgramData` account, which records its upgrade authority.
+pub fn program_data() -> Pubkey {
+    Pubkey::find_program_address(&[ID.as_ref()], &bpf_loader_upgradeable::ID).0
+}
+
+pub fn global_state() -> Pubkey {
+    address(&[PREFIX_STATE.as_bytes()])
+}
+
+pub fn config() -> Pubkey {
+    address(&[PREFIX_CONFIG.as_bytes()])
+}
+
+pub fn pending_config() -> Pubkey {
+    address(&[PREFIX_PENDING.as_bytes(), PREFIX_CONFIG.as_bytes()])
+}
+
+pub fn roles() -> Pubkey {
+    address(&[PREFIX_ROLES.as_bytes()])
+}
+
+pub fn witnesses() -> Pubkey {
+    address(&[PREFIX_WITNESSES.as_bytes()])
+}
+
+pub fn reward_pool() -> Pubkey {
+    address(&[PREFIX_REWARD.as_bytes()])
+}
+
+pub fn reward(witness: &Pubkey) -> Pubkey {
+    address(&[PREFIX_REWARD.as_bytes(), witness.as_ref()])
+}
+
+pub fn treasury() -> Pubkey {
+    address(&[PREFIX_TREASURY.as_bytes()])
+}
+
+pub fn stake_config() -> Pubkey {
+    address(&[PREFIX_STAKE_CONFIG.as_bytes()])
+}
+
+pub fn ccfee(chainid: u32) -> Pubkey {
+    address(&[PREFIX_CCFEE.as_bytes(), chainid.to_le_bytes().as_ref()])
+}
+
+pub fn pending_ccfee(chainid: u32) -> Pubkey {
+    address(&[PREFIX_PENDING.as_bytes(), PREFIX_CCFEE.as_bytes(), chainid.to_le_bytes().as_ref()])
+}
+
+/// Native SOL balance of a lender.
+pub fn user_balance(owner: &Pubkey) -> Pubkey {
+    address(&[PREFIX_BALANCE.as_bytes(), owner.as_ref()])
+}
+
+/// Token balance of a lender, also used for the treasury's token commission.
+pub fn token_balance(owner: &Pubkey, mint: &Pubkey) -> Pubkey {
+    address(&[PREFIX_BALANCE.as_bytes(), owner.as_ref(), mint.as_ref()])
+}
+
+pub fn treasury_balance(mint: &Pubkey) -> Pubkey {
+    token_balance(&treasury(), mint)
+}
+
+pub fn vault(mint: &Pubkey) -> Pubkey {
+    address(&[PREFIX_VAULT.as_bytes(), mint.as_ref()])
+}
+
+pub fn order(sn: u64) -> Pubkey {
+    address(&[PREFIX_ORDER.as_bytes(), sn.to_le_bytes().as_ref()])
+}
+
+pub fn receipt(sn: u64) -> Pubkey {
+    address(&[PREFIX_RECEIPT.as_bytes(), sn.to_le_bytes().as_ref()])
+}
+
+pub fn collateral(chainid: u32, collateral_sn: u64) -> Pubkey {
+    address(&[PREFIX_COLLATERAL.as_bytes(), chainid.to_le_bytes().as_ref(), collateral_sn.to_le_bytes().as_ref()])
+}
+
+pub fn reinvest(lender: &Pubkey, mint: &Pubkey) -> Pubkey {
+    address(&[PREFIX_REINVEST.as_bytes(), lender.as_ref(), mint.as_ref()])
+}
+
+pub fn proposal(sn: u64) -> Pubkey {
+    address(&[PREFIX_PROPOSAL.as_bytes(), sn.to_le_bytes().as_ref()])
+}
+
+pub fn stake(witness: &Pubkey) -> Pubkey {
+    address(&[PREFIX_STAKE.as_bytes(), witness.as_ref()])
+}
+
+pub fn price_feed(chainid: u32, token: &[u8; 20]) -> Pubkey {
+    address(&[PREFIX_PRICE.as_bytes(), chainid.to_le_bytes().as_ref(), token.as_ref()])
+}
+
+/// Collateral deposited on Solana; `Pubkey::default()` for native SOL.
+pub fn mortgage(owner: &Pubkey, mint: &Pubkey) -> Pubkey {
+    address(&[PREFIX_MORTGAGE.as_bytes(), owner.as_ref(), mint.as_ref()])
+}
+
+pub fn freeze(sn: u64) -> Pubkey {
+    address(&[PREFIX_FREEZE.as_bytes(), sn.to_le_bytes().as_ref()])
+}
+
+pub fn release(freeze_sn: u64) -> Pubkey {
+    address(&[PREFIX_RELEASE.as_bytes(), freeze_sn.to_le_bytes().as_ref()])
+}
+
+pub fn withdrawal(sn: u64) -> Pubkey {
+    address(&[PREFIX_WITHDRAWAL.as_bytes(), sn.to_le_bytes().as_ref()])
+}
@@ -0,0 +1,82 @@
+use anchor_lang::prelude::*;
+use anchor_lang::Discriminator;
+use solana_lending_testkit::*;
+use solana_lending_client::events::{self, LendingEvent};
+use solana_lending_client::decode_account;
+
+fn logs(program: &Pubkey, data: &[Vec<u8>]) -> Vec<String> {
+    let mut logs = vec![format!("Program {program} invoke [1]"), "Program log: Instruction: PlaceOrder".to_string()];
+    logs.extend(data.iter().map(|d| events::log_line(d)));
+    logs.push(format!("Program {program} consumed 5000 of 200000 compute units"));
+    logs.push(format!("Program {program} success"));
+    logs
+}
+
+#[test]
+fn decodes_events_emitted_by_the_program() {
+    let mut p = Protocol::new();
+    p.env.take_events();
+    let loan = p.loan(LAMPORTS, 500, 30 * DAY, 1);
+    p.repay(&loan.borrower, loan.receipt_sn).unwrap();
+
+    let events = events::parse_logs(&logs(&::solana_lending::ID, &p.env.take_events()));
+    let names: Vec<&str> = events.iter().map(LendingEvent::name).collect();
+    assert_eq!(names, ["EventPlaceOrder", "EventReserveOrder", "EventBorrowSuccess", "EventCreditRewards", "EventRepaySuccess"]);
+    match &events[0] {
+        LendingEvent::PlaceOrder(e) => assert_eq!((e.order_sn, e.lender, e.rate), (loan.order_sn, loan.lender, 500)),
+        _ => unreachable!(),
+    }
+    match &events[1] {
+        LendingEvent::ReserveOrder(e) => assert_eq!((e.order_sn, e.borrower, e.amount), (loan.order_sn, loan.borrower, LAMPORTS)),
+        _ => unreachable!(),
+    }
+    match &events[4] {
+        LendingEvent::RepaySuccess(e) => assert_eq!((e.receipt_sn, e.borrower, e.chainid, e.c_sn), (loan.receipt_sn, loan.borrower, CHAIN, loan.collateral_sn)),
+        _ => unreachable!(),
+    }
+}
+
+#[test]
+fn ignores_data_logged_by_other_programs() {
+    let mut p = Protocol::new();
+    p.env.take_events();
+    p.deposit(&p.admin.clone(), LAMPORTS).unwrap();
+    p.lender_with_order(LAMPORTS, 500, 30 * DAY, 1);
+    let data = p.env.take_events();
+
+    let other = Pubkey::new_unique();
+    let mut lines = logs(&::solana_lending::ID, &data);
+    let nested = logs(&other, &data);
+    lines.splice(2..2, nested);
+    assert_eq!(events::parse_logs(&lines).len(), data.len());
+    assert!(events::parse_logs(&logs(&other, &data)).is_empty());
+
+    let failed = [format!("Program {} invoke [1]", ::solana_lending::ID), format!("Program {} failed: custom program error: 0x1", ::solana_lending::ID), events::log_line(&data[0])];
+    assert!(events::parse_logs(&failed).is_empty());
+}
+
+#[test]
+fn rejects_unknown_or_truncated_data() {
+    assert!(events::decode(&[]).is_none());
+    assert!(events::decode(&[0; 8]).is_none());
+    let truncated = ::solana_lending::EventPlaceOrder::discriminator();
+    assert!(events::decode(&truncated).is_none());
+}
+
+#[test]
+fn decodes_program_accounts() {
+    let mut p = Protocol::new();
+    let loan = p.loan(LAMPORTS, 500, 30 * DAY, 1);
+
+    let data = |key: &Pubkey| p.env.accounts[key].data.clone();
+    let o: ::solana_lending::Order = decode_account(&data(&order(loan.order_sn))).unwrap();
+    assert_eq!((o.sn, o.lender), (loan.order_sn, loan.lender));
+    let r: ::solana_lending::LoanReceipt = decode_account(&data(&receipt(loan.receipt_sn))).unwrap();
+    assert_eq!((r.sn, r.borrower, r.amount), (loan.receipt_sn, loan.borrower, LAMPORTS));
+    let c: ::solana_lending::CollateralWithMultisig = decode_account(&data(&collateral(CHAIN, loan.collateral_sn))).unwrap();
+    assert_eq!(c.branches[0].collateral.borrower, loan.borrower);
+    let w: ::solana_lending::Witnesses = decode_account(&data(&witnesses())).unwrap();
+    assert_eq!(w.v, p.witnesses);
+
+    assert!(decode_account::<::solana_lending::Order>(&data(&receipt(loan.receipt_sn))).is_err());
+}
@@ -0,0 +1,295 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::solana_program::program_error::ProgramError;
+use solana_lending_testkit::{error, Env, CHAIN, DAY, FEE, LAMPORTS, SOURCE, TOKEN};
+use ::solana_lending::CONFIG_DELAY;
+use solana_lending_client::instructions as ix;
+use solana_lending_client::*;
+
+const RATE: u64 = 500;
+const TERM: u64 = 30 * DAY;
+
+// A protocol driven only through the SDK builders. Every account the program checks by seeds
+// is derived by `pda`, so a wrong derivation fails the instruction with ConstraintSeeds.
+struct Sdk {
+    env: Env,
+    admin: Pubkey,
+    witnesses: Vec<Pubkey>,
+}
+
+impl Sdk {
+    fn new() -> Self {
+        let mut env = Env::new();
+        let admin = env.wallet();
+        env.set_upgrade_authority(&admin);
+        let mut s = Sdk {
+            env,
+            admin,
+            witnesses: vec![],
+        };
+        s.run(ix::initialize(&admin)).unwrap();
+        for role in [Role::ConfigManager, Role::FeeManager, Role::WitnessManager, Role::Pauser, Role::TreasuryWithdrawer] {
+            s.run(ix::grant_role(&admin, role, admin)).unwrap();
+        }
+        let params = ConfigParams {
+            min_ir: 100,
+            max_ir: 2000,
+            penalty_ir: 1,
+            penalty_days: 30,
+            commission_rate: 10,
+            min_term: DAY,
+            max_term: 365 * DAY,
+            grace: 3 * DAY,
+            multisig_timeout: DAY,
+        };
+        s.run(ix::queue_config(&admin, params)).unwrap();
+        s.run(ix::apply_config(&admin)).unwrap();
+        let witnesses: Vec<Pubkey> = (0..3).map(|_| s.env.wallet()).collect();
+        s.run(ix::init_witnesses(&admin, witnesses.clone())).unwrap();
+        s.witnesses = witnesses;
+        s.run(ix::queue_ccfee(&admin, CHAIN, FEE)).unwrap();
+        s.env.warp(CONFIG_DELAY);
+        s.run(ix::apply_ccfee(&admin, CHAIN)).unwrap();
+        s
+    }
+
+    fn run(&mut self, ix: Instruction) -> std::result::Result<(), ProgramError> {
+        assert_eq!(ix.program_id, ID);
+        self.env.process_metas(ix.accounts, ix.data)
+    }
+
+    fn global(&self) -> GlobalState {
+        self.env.get(&pda::global_state())
+    }
+
+    fn consensus(&mut self, order_sn: u64, borrower: &Pubkey, amount: u64) -> Collateral {
+        let collateral_sn = (1..).find(|sn| !self.env.exists(&pda::collateral(CHAIN, *sn))).unwrap();
+        let c = Collateral {
+            chainid: CHAIN,
+            collateral_sn,
+            source: SOURCE,
+            token: TOKEN,
+            frozen: 10 * amount,
+            borrower: *borrower,
+            order_sn,
+            amount,
+        };
+        for witness in self.witnesses.clone().iter().take(2) {
+            self.run(ix::multisig_collateral(witness, &c)).unwrap();
+        }
+        c
+    }
+
+    // A native loan of `amount`; returns the lender and the receipt.
+    fn loan(&mut self, amount: u64) -> (Pubkey, LoanReceipt) {
+        let lender = self.env.wallet();
+        self.run(ix::deposit(&lender, 10 * amount)).unwrap();
+        let order_sn = self.global().curr_order_sn;
+        self.run(ix::place_order(&lender, order_sn, 10 * amount, RATE, TERM, 1)).unwrap();
+
+        let borrower = self.env.wallet();
+        let c = self.consensus(order_sn, &borrower, amount);
+        let receipt_sn = self.global().curr_receipt_sn;
+        self.run(ix::borrow(&borrower, &c, receipt_sn)).unwrap();
+        self.run(ix::clear_collateral(&borrower, CHAIN, c.collateral_sn)).unwrap();
+        (lender, self.env.get(&pda::receipt(receipt_sn)))
+    }
+}
+
+#[test]
+fn admin_instructions() {
+    let mut s = Sdk::new();
+    let admin = s.admin;
+    let next = s.env.wallet();
+    s.run(ix::set_pause(&admin, 1)).unwrap();
+    assert_eq!(s.global().paused, 1);
+    s.run(ix::set_pause(&admin, 0)).unwrap();
+    s.run(ix::revoke_role(&admin, Role::Pauser, admin)).unwrap();
+    assert!(s.env.get::<Roles>(&pda::roles()).members(Role::Pauser).is_empty());
+
+    s.run(ix::set_treasury_threshold(&admin, 1)).unwrap();
+    s.run(ix::set_stake_config(&admin, LAMPORTS, DAY)).unwrap();
+    s.run(ix::propose_admin(&admin, next)).unwrap();
+    s.run(ix::accept_admin(&next)).unwrap();
+    assert_eq!(s.global().admin, next);
+}
+
+#[test]
+fn native_loan_lifecycle() {
+    let mut s = Sdk::new();
+    let (lender, r) = s.loan(LAMPORTS);
+    assert_eq!(r.amount, LAMPORTS);
+    assert!(s.env.get::<CollateralWithMultisig>(&pda::collateral(CHAIN, r.c_sn)).status == MultisigStatus::Finished);
+
+    s.run(ix::extend_loan(&r.borrower, &r, r.rate, None)).unwrap();
+    s.run(ix::repay_partial(&r.borrower, &r, None, LAMPORTS / 2)).unwrap();
+    s.run(ix::repay(&r.borrower, &r, None)).unwrap();
+    assert!(!s.env.exists(&pda::receipt(r.sn)));
+    s.run(ix::claim_reward(&s.witnesses[0].clone())).unwrap();
+
+    let balance = s.env.get::<UserBalance>(&pda::user_balance(&lender)).amount;
+    s.run(ix::withdraw(&lender, balance)).unwrap();
+}
+
+#[test]
+fn expire_and_clear_collateral() {
+    let mut s = Sdk::new();
+    let borrower = s.env.wallet();
+    let mut c = s.consensus(0, &borrower, LAMPORTS);
+    c.collateral_sn += 1;
+    s.run(ix::multisig_collateral(&s.witnesses[0].clone(), &c)).unwrap();
+    s.run(ix::revoke_collateral_signature(&s.witnesses[0].clone(), CHAIN, c.collateral_sn)).unwrap();
+    assert!(s.env.get::<CollateralWithMultisig>(&pda::collateral(CHAIN, c.collateral_sn)).branches.is_empty());
+    s.run(ix::recount_collateral(&borrower, CHAIN, c.collateral_sn)).unwrap();
+    s.env.warp(DAY);
+    s.run(ix::expire_collateral(&borrower, CHAIN, c.collateral_sn)).unwrap();
+    s.run(ix::clear_collateral(&borrower, CHAIN, c.collateral_sn)).unwrap();
+    assert!(s.env.get::<CollateralWithMultisig>(&pda::collateral(CHAIN, c.collateral_sn)).status == MultisigStatus::Expired);
+}
+
+#[test]
+fn repay_into_reinvested_order() {
+    let mut s = Sdk::new();
+    let (lender, r) = s.loan(LAMPORTS);
+    let order_sn = s.global().curr_order_sn - 1;
+    s.run(ix::enable_auto_reinvest(&lender, order_sn, &Pubkey::default())).unwrap();
+    let setting: AutoReinvest = s.env.get(&pda::reinvest(&lender, &Pubkey::default()));
+
+    let before = s.env.get::<Order>(&pda::order(setting.order_sn)).balance;
+    s.run(ix::repay(&r.borrower, &r, Some(setting.order_sn))).unwrap();
+    assert!(s.env.get::<Order>(&pda::order(setting.order_sn)).balance > before);
+
+    s.run(ix::disable_auto_reinvest(&lender, Pubkey::default())).unwrap();
+    s.run(ix::cancel_order(&lender, setting.order_sn)).unwrap();
+}
+
+#[test]
+fn liquidate_and_close_order() {
+    let mut s = Sdk::new();
+    let (lender, r) = s.loan(LAMPORTS);
+    s.env.warp(r.term + r.grace + 1);
+    s.run(ix::liquidate(&lender, &r, SOURCE)).unwrap();
+    assert!(!s.env.exists(&pda::receipt(r.sn)));
+
+    let order_sn = s.global().curr_order_sn - 1;
+    let other = s.env.wallet();
+    assert_eq!(s.run(ix::close_order(&other, order_sn, &lender)), Err(error(MyError::CloseOrderWithNonZeroBalance)));
+}
+
+#[test]
+fn token_loan_lifecycle() {
+    let mut s = Sdk::new();
+    let admin = s.admin;
+    let mint = s.env.create_mint(6);
+    let lender = s.env.wallet();
+    let lender_token = s.env.create_token_account(&mint, &lender, 10 * LAMPORTS);
+    s.run(ix::deposit_token(&lender, &mint, &lender_token, 10 * LAMPORTS)).unwrap();
+    s.run(ix::withdraw_token(&lender, &mint, &lender_token, LAMPORTS)).unwrap();
+    let order_sn = s.global().curr_order_sn;
+    s.run(ix::place_token_order(&lender, order_sn, &mint, 8 * LAMPORTS, RATE, TERM, 1)).unwrap();
+    assert_eq!(s.env.get::<Order>(&pda::order(order_sn)).mint, mint);
+
+    let borrower = s.env.wallet();
+    let borrower_token = s.env.create_token_account(&mint, &borrower, LAMPORTS);
+    let c = s.consensus(order_sn, &borrower, LAMPORTS);
+    let receipt_sn = s.global().curr_receipt_sn;
+    s.run(ix::borrow_token(&borrower, &c, receipt_sn, &mint, &borrower_token)).unwrap();
+    assert_eq!(s.env.token_balance(&borrower_token), 2 * LAMPORTS);
+
+    let r: LoanReceipt = s.env.get(&pda::receipt(receipt_sn));
+    s.run(ix::extend_token_loan(&borrower, &r, &borrower_token, r.rate, None)).unwrap();
+    s.run(ix::repay_token_partial(&borrower, &r, None, &borrower_token, LAMPORTS / 2)).unwrap();
+    s.run(ix::repay_token(&borrower, &r, None, &borrower_token)).unwrap();
+    assert!(!s.env.exists(&pda::receipt(receipt_sn)));
+    s.run(ix::cancel_token_order(&lender, order_sn, &mint)).unwrap();
+
+    let commission = s.env.get::<UserBalance>(&pda::treasury_balance(&mint)).amount;
+    let receiver = s.env.create_token_account(&mint, &admin, 0);
+    let withdrawal_sn = s.env.get::<Treasury>(&pda::treasury()).curr_withdrawal_sn;
+    s.run(ix::propose_treasury_withdrawal(&admin, withdrawal_sn, TreasuryCategory::Commission, mint, commission, receiver)).unwrap();
+    s.run(ix::execute_token_treasury_withdrawal(&admin, withdrawal_sn, &mint, &receiver)).unwrap();
+    assert_eq!(s.env.token_balance(&receiver), commission);
+}
+
+#[test]
+fn native_treasury_withdrawal() {
+    let mut s = Sdk::new();
+    let admin = s.admin;
+    let (_, r) = s.loan(LAMPORTS);
+    s.run(ix::repay(&r.borrower, &r, None)).unwrap();
+
+    let receiver = Pubkey::new_unique();
+    let commission = s.env.get::<Treasury>(&pda::treasury()).commission;
+    s.run(ix::set_treasury_threshold(&admin, 2)).unwrap();
+    let withdrawer = s.env.wallet();
+    s.run(ix::grant_role(&admin, Role::TreasuryWithdrawer, withdrawer)).unwrap();
+    s.run(ix::propose_treasury_withdrawal(&admin, 1, TreasuryCategory::Commission, Pubkey::default(), commission, receiver)).unwrap();
+    s.run(ix::approve_treasury_withdrawal(&withdrawer, 1)).unwrap();
+    s.run(ix::execute_treasury_withdrawal(&admin, 1, &receiver)).unwrap();
+    assert_eq!(s.env.lamports(&receiver), commission);
+}
+
+#[test]
+fn collateral_freeze_and_release() {
+    let mut s = Sdk::new();
+    let owner = s.env.wallet();
+    let lender = s.env.wallet();
+    let native = Pubkey::default();
+    s.run(ix::deposit_collateral(&owner, 3 * LAMPORTS)).unwrap();
+    s.run(ix::withdraw_collateral(&owner, LAMPORTS)).unwrap();
+
+    for action in [ReleaseAction::Unfreeze, ReleaseAction::Liquidate] {
+        let freeze_sn = s.global().curr_freeze_sn;
+        s.run(ix::freeze_collateral(&owner, freeze_sn, native, CHAIN, 7, LAMPORTS, LAMPORTS, SOURCE)).unwrap();
+        for witness in s.witnesses.clone().iter().take(2) {
+            s.run(ix::multisig_release(witness, freeze_sn, action, lender)).unwrap();
+        }
+        let settle = match action {
+            ReleaseAction::Unfreeze => ix::unfreeze_collateral(&owner, freeze_sn, &owner, &native),
+            ReleaseAction::Liquidate => ix::liquidate_collateral(&lender, freeze_sn, &owner, &native, &lender),
+        };
+        s.run(settle).unwrap();
+    }
+    assert!(s.env.get::<UserBalance>(&pda::mortgage(&lender, &native)).amount > 0);
+
+    let mint = s.env.create_mint(6);
+    let owner_token = s.env.create_token_account(&mint, &owner, LAMPORTS);
+    s.run(ix::deposit_token_collateral(&owner, &mint, &owner_token, LAMPORTS)).unwrap();
+    s.run(ix::withdraw_token_collateral(&owner, &mint, &owner_token, LAMPORTS)).unwrap();
+    assert_eq!(s.env.token_balance(&owner_token), LAMPORTS);
+}
+
+#[test]
+fn witness_changes_and_staking() {
+    let mut s = Sdk::new();
+    let [w0, w1] = [s.witnesses[0], s.witnesses[1]];
+    let candidate = s.env.wallet();
+
+    let sn = s.global().curr_proposal_sn;
+    s.run(ix::propose_witness_change(&w0, sn, WitnessAction::Add, candidate, false)).unwrap();
+    s.run(ix::approve_witness_change(&w1, sn, &candidate, false)).unwrap();
+    assert!(s.env.get::<Witnesses>(&pda::witnesses()).v.contains(&candidate));
+
+    s.run(ix::set_stake_config(&s.admin.clone(), LAMPORTS, DAY)).unwrap();
+    s.run(ix::deposit_stake(&candidate, LAMPORTS)).unwrap();
+    let sn = s.global().curr_proposal_sn;
+    s.run(ix::propose_witness_change(&w0, sn, WitnessAction::Remove, candidate, true)).unwrap();
+    s.run(ix::approve_witness_change(&w1, sn, &candidate, true)).unwrap();
+    s.run(ix::approve_witness_change(&s.witnesses[2].clone(), sn, &candidate, true)).unwrap();
+    assert!(!s.env.get::<Witnesses>(&pda::witnesses()).v.contains(&candidate));
+
+    s.run(ix::unbond_stake(&candidate)).unwrap();
+    s.env.warp(DAY);
+    s.run(ix::withdraw_stake(&candidate, LAMPORTS)).unwrap();
+    assert_eq!(s.env.get::<WitnessStake>(&pda::stake(&candidate)).amount, 0);
+}
+
+#[test]
+fn price_feed() {
+    let mut s = Sdk::new();
+    for (witness, price) in s.witnesses.clone().iter().zip([100, 300, 110]) {
+        s.run(ix::submit_price(witness, CHAIN, TOKEN, price)).unwrap();
+    }
+    s.run(ix::get_price(CHAIN, TOKEN, 60)).unwrap();
+    assert_eq!(s.env.return_data(), Some(110u64.to_le_bytes().to_vec()));
+}
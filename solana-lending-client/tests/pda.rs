@@ -0,0 +1,55 @@
+use anchor_lang::prelude::*;
+use ::solana_lending::*;
+use solana_lending_client::pda;
+use solana_lending_testkit as testkit;
+
+// The harness derives every address straight from the program's seed constants; the SDK must
+// agree with it. The instruction tests additionally run SDK-derived accounts through the
+// program's own seed constraints.
+#[test]
+fn singletons_match_program_seeds() {
+    assert_eq!(pda::global_state(), testkit::global());
+    assert_eq!(pda::config(), testkit::config());
+    assert_eq!(pda::pending_config(), testkit::pending_config());
+    assert_eq!(pda::roles(), testkit::roles());
+    assert_eq!(pda::witnesses(), testkit::witnesses());
+    assert_eq!(pda::reward_pool(), testkit::reward_pool());
+    assert_eq!(pda::treasury(), testkit::treasury());
+    assert_eq!(pda::stake_config(), testkit::stake_config());
+    assert_eq!(pda::program_data(), testkit::program_data());
+}
+
+#[test]
+fn keyed_accounts_match_program_seeds() {
+    let owner = Pubkey::new_unique();
+    let mint = Pubkey::new_unique();
+    for sn in [0, 1, 42, u64::MAX] {
+        assert_eq!(pda::order(sn), testkit::order(sn));
+        assert_eq!(pda::receipt(sn), testkit::receipt(sn));
+        assert_eq!(pda::proposal(sn), testkit::proposal(sn));
+        assert_eq!(pda::freeze(sn), testkit::freeze(sn));
+        assert_eq!(pda::release(sn), testkit::release(sn));
+        assert_eq!(pda::withdrawal(sn), testkit::withdrawal(sn));
+        assert_eq!(pda::collateral(testkit::CHAIN, sn), testkit::collateral(testkit::CHAIN, sn));
+    }
+    for chainid in [1, testkit::CHAIN, u32::MAX] {
+        assert_eq!(pda::ccfee(chainid), testkit::ccfee(chainid));
+        assert_eq!(pda::pending_ccfee(chainid), testkit::pending_ccfee(chainid));
+        assert_eq!(pda::price_feed(chainid, &testkit::TOKEN), testkit::price_feed(chainid, &testkit::TOKEN));
+    }
+    assert_eq!(pda::user_balance(&owner), testkit::balance(&owner));
+    assert_eq!(pda::token_balance(&owner, &mint), testkit::token_balance(&owner, &mint));
+    assert_eq!(pda::treasury_balance(&mint), testkit::token_balance(&testkit::treasury(), &mint));
+    assert_eq!(pda::vault(&mint), testkit::vault(&mint));
+    assert_eq!(pda::reinvest(&owner, &mint), testkit::reinvest(&owner, &mint));
+    assert_eq!(pda::stake(&owner), testkit::stake(&owner));
+    assert_eq!(pda::mortgage(&owner, &Pubkey::default()), testkit::mortgage(&owner, &Pubkey::default()));
+}
+
+#[test]
+fn sequence_numbers_are_little_endian() {
+    let (key, bump) = pda::find(&[PREFIX_ORDER.as_bytes(), &[1, 0, 0, 0, 0, 0, 0, 0]]);
+    assert_eq!(pda::order(1), key);
+    assert_eq!(Pubkey::create_program_address(&[PREFIX_ORDER.as_bytes(), &1u64.to_le_bytes(), &[bump]], &ID), Ok(key));
+    assert_ne!(pda::order(1), pda::receipt(1));
+}
@@ -0,0 +1,450 @@
+//! The SQLite schema and the folding of events into it.
+//!
+//! Every transaction is indexed at most once, keyed by its signature, so a file or an RPC range
+//! can be replayed any number of times. Its logs are kept, and a transaction older than the
+//! newest one indexed so far rebuilds the derived tables in slot order, so a backfill never
+//! leaves state behind that a newer transaction already changed.
+
+use std::path::Path;
+
+use anchor_lang::prelude::Pubkey;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use solana_lending_client::events::{self, LendingEvent};
+
+use crate::{Result, TransactionLogs};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS transactions (
+    signature TEXT PRIMARY KEY,
+    slot INTEGER NOT NULL,
+    block_time INTEGER,
+    logs TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS events (
+    signature TEXT NOT NULL,
+    idx INTEGER NOT NULL,
+    slot INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    PRIMARY KEY (signature, idx)
+);
+CREATE TABLE IF NOT EXISTS orders (
+    order_sn INTEGER PRIMARY KEY,
+    lender TEXT NOT NULL,
+    mint TEXT NOT NULL,
+    rate INTEGER NOT NULL,
+    term INTEGER NOT NULL,
+    installments INTEGER NOT NULL,
+    balance INTEGER NOT NULL,
+    reserved INTEGER NOT NULL,
+    status TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS orders_by_rate ON orders (status, mint, rate);
+CREATE TABLE IF NOT EXISTS receipts (
+    receipt_sn INTEGER PRIMARY KEY,
+    order_sn INTEGER NOT NULL,
+    borrower TEXT NOT NULL,
+    lender TEXT NOT NULL,
+    mint TEXT NOT NULL,
+    chainid INTEGER NOT NULL,
+    collateral_sn INTEGER NOT NULL,
+    source TEXT NOT NULL,
+    token TEXT NOT NULL,
+    frozen INTEGER NOT NULL,
+    amount INTEGER NOT NULL,
+    rate INTEGER NOT NULL,
+    time INTEGER NOT NULL,
+    term INTEGER NOT NULL,
+    installments INTEGER NOT NULL,
+    grace INTEGER NOT NULL,
+    repaid INTEGER NOT NULL,
+    status TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS receipts_by_status ON receipts (status);
+CREATE TABLE IF NOT EXISTS reservations (
+    chainid INTEGER NOT NULL,
+    collateral_sn INTEGER NOT NULL,
+    order_sn INTEGER NOT NULL,
+    borrower TEXT NOT NULL,
+    amount INTEGER NOT NULL,
+    PRIMARY KEY (chainid, collateral_sn)
+);
+CREATE TABLE IF NOT EXISTS repayments (
+    signature TEXT NOT NULL,
+    idx INTEGER NOT NULL,
+    slot INTEGER NOT NULL,
+    receipt_sn INTEGER NOT NULL,
+    amount INTEGER NOT NULL,
+    income INTEGER NOT NULL,
+    remaining INTEGER NOT NULL,
+    full INTEGER NOT NULL,
+    PRIMARY KEY (signature, idx)
+);
+CREATE TABLE IF NOT EXISTS liquidations (
+    signature TEXT NOT NULL,
+    idx INTEGER NOT NULL,
+    slot INTEGER NOT NULL,
+    receipt_sn INTEGER NOT NULL,
+    chainid INTEGER NOT NULL,
+    collateral_sn INTEGER NOT NULL,
+    source TEXT NOT NULL,
+    frozen INTEGER NOT NULL,
+    receiver TEXT NOT NULL,
+    PRIMARY KEY (signature, idx)
+);
+";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct OrderRow {
+    pub order_sn: u64,
+    pub lender: Pubkey,
+    pub mint: Pubkey,
+    pub rate: u64,
+    pub term: u64,
+    pub installments: u8,
+    pub balance: u64,
+    /// Part of the balance earmarked by pending collateral rounds.
+    pub reserved: u64,
+    /// `open`, `cancelled` or `closed`.
+    pub status: String,
+}
+
+impl OrderRow {
+    /// What a new borrow can take, or what cancelling a cancelled order again returns.
+    pub fn available(&self) -> u64 {
+        self.balance - self.reserved
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReceiptRow {
+    pub receipt_sn: u64,
+    pub order_sn: u64,
+    pub borrower: Pubkey,
+    pub lender: Pubkey,
+    pub mint: Pubkey,
+    pub chainid: u32,
+    pub collateral_sn: u64,
+    pub source: [u8; 20],
+    pub token: [u8; 20],
+    pub frozen: u64,
+    pub amount: u64,
+    pub rate: u64,
+    pub time: u64,
+    pub term: u64,
+    pub installments: u8,
+    pub grace: u64,
+    pub repaid: u64,
+    /// `open`, `repaid` or `liquidated`.
+    pub status: String,
+}
+
+impl ReceiptRow {
+    /// Due time of the earliest installment that is not fully repaid, the way the program
+    /// schedules it; extensions move it forward.
+    pub fn due(&self) -> u64 {
+        let n = self.installments.max(1) as u64;
+        let total = (self.amount + self.amount * self.rate / 10000) as u128;
+        (1..=n)
+            .find(|&k| (total * k as u128 / n as u128) as u64 > self.repaid)
+            .map_or(self.time + self.term, |k| self.time + self.term * k / n)
+    }
+
+    /// When the receipt can be liquidated: the due time plus the grace period.
+    pub fn deadline(&self) -> u64 {
+        self.due() + self.grace
+    }
+}
+
+pub struct Indexer {
+    conn: Connection,
+}
+
+impl Indexer {
+    pub fn open(path: &Path) -> Result<Self> {
+        Self::with_connection(Connection::open(path)?)
+    }
+
+    pub fn open_in_memory() -> Result<Self> {
+        Self::with_connection(Connection::open_in_memory()?)
+    }
+
+    fn with_connection(conn: Connection) -> Result<Self> {
+        conn.execute_batch(SCHEMA)?;
+        Ok(Indexer { conn })
+    }
+
+    pub fn connection(&self) -> &Connection {
+        &self.conn
+    }
+
+    /// Indexes one transaction. Returns false when its signature was indexed before.
+    pub fn index(&mut self, tx: &TransactionLogs) -> Result<bool> {
+        let db = self.conn.transaction()?;
+        let newest: Option<u64> = db.query_row("SELECT MAX(slot) FROM transactions", [], |r| r.get(0))?;
+        let inserted = db.execute(
+            "INSERT OR IGNORE INTO transactions (signature, slot, block_time, logs) VALUES (?1, ?2, ?3, ?4)",
+            params![tx.signature, tx.slot, tx.block_time, serde_json::to_string(&tx.logs)?],
+        )?;
+        if inserted == 0 {
+            return Ok(false);
+        }
+        if newest.is_some_and(|newest| tx.slot < newest) {
+            rebuild(&db)?;
+        } else {
+            apply_transaction(&db, &tx.signature, tx.slot, &tx.logs)?;
+        }
+        db.commit()?;
+        Ok(true)
+    }
+
+    /// Indexes a batch in slot order and returns how many transactions were new.
+    pub fn index_all<'a>(&mut self, txs: impl IntoIterator<Item = &'a TransactionLogs>) -> Result<usize> {
+        let mut txs: Vec<_> = txs.into_iter().collect();
+        txs.sort_by_key(|tx| tx.slot);
+        let mut count = 0;
+        for tx in txs {
+            count += self.index(tx)? as usize;
+        }
+        Ok(count)
+    }
+
+    /// The newest indexed transaction, where an RPC catch-up resumes.
+    pub fn latest_signature(&self) -> Result<Option<String>> {
+        Ok(self
+            .conn
+            .query_row("SELECT signature FROM transactions ORDER BY slot DESC, rowid DESC LIMIT 1", [], |r| r.get(0))
+            .optional()?)
+    }
+
+    pub fn order(&self, order_sn: u64) -> Result<Option<OrderRow>> {
+        Ok(self.conn.query_row(&format!("{ORDER} WHERE order_sn = ?1"), [order_sn], order_row).optional()?)
+    }
+
+    /// Orders with unreserved lendable balance, cheapest first, optionally for a single mint.
+    pub fn open_orders(&self, mint: Option<&Pubkey>) -> Result<Vec<OrderRow>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "{ORDER} WHERE status = 'open' AND balance > reserved AND (?1 IS NULL OR mint = ?1) ORDER BY rate, order_sn"
+        ))?;
+        let rows = stmt.query_map([mint.map(|m| m.to_string())], order_row)?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    pub fn receipt(&self, receipt_sn: u64) -> Result<Option<ReceiptRow>> {
+        Ok(self.conn.query_row(&format!("{RECEIPT} WHERE receipt_sn = ?1"), [receipt_sn], receipt_row).optional()?)
+    }
+
+    /// Unsettled receipts whose grace period after the next due installment ended before `now`,
+    /// so they can be liquidated, longest overdue first.
+    pub fn overdue_receipts(&self, now: u64) -> Result<Vec<ReceiptRow>> {
+        // the due installment depends on what was repaid, so it is worked out here and not in SQL
+        let mut stmt = self.conn.prepare(&format!("{RECEIPT} WHERE status = 'open'"))?;
+        let mut overdue = vec![];
+        for row in stmt.query_map([], receipt_row)? {
+            let row = row?;
+            if row.deadline() < now {
+                overdue.push(row);
+            }
+        }
+        overdue.sort_by_key(|r| (r.deadline(), r.receipt_sn));
+        Ok(overdue)
+    }
+}
+
+const ORDER: &str = "SELECT order_sn, lender, mint, rate, term, installments, balance, reserved, status FROM orders";
+
+const RECEIPT: &str = "SELECT receipt_sn, order_sn, borrower, lender, mint, chainid, collateral_sn, source, token, \
+    frozen, amount, rate, time, term, installments, grace, repaid, status FROM receipts";
+
+fn order_row(r: &Row) -> rusqlite::Result<OrderRow> {
+    Ok(OrderRow {
+        order_sn: r.get(0)?,
+        lender: pubkey(r, 1)?,
+        mint: pubkey(r, 2)?,
+        rate: r.get(3)?,
+        term: r.get(4)?,
+        installments: r.get(5)?,
+        balance: r.get(6)?,
+        reserved: r.get(7)?,
+        status: r.get(8)?,
+    })
+}
+
+fn receipt_row(r: &Row) -> rusqlite::Result<ReceiptRow> {
+    Ok(ReceiptRow {
+        receipt_sn: r.get(0)?,
+        order_sn: r.get(1)?,
+        borrower: pubkey(r, 2)?,
+        lender: pubkey(r, 3)?,
+        mint: pubkey(r, 4)?,
+        chainid: r.get(5)?,
+        collateral_sn: r.get(6)?,
+        source: address(r, 7)?,
+        token: address(r, 8)?,
+        frozen: r.get(9)?,
+        amount: r.get(10)?,
+        rate: r.get(11)?,
+        time: r.get(12)?,
+        term: r.get(13)?,
+        installments: r.get(14)?,
+        grace: r.get(15)?,
+        repaid: r.get(16)?,
+        status: r.get(17)?,
+    })
+}
+
+fn conversion_error<E: std::error::Error + Send + Sync + 'static>(idx: usize, e: E) -> rusqlite::Error {
+    rusqlite::Error::FromSqlConversionFailure(idx, rusqlite::types::Type::Text, Box::new(e))
+}
+
+fn pubkey(r: &Row, idx: usize) -> rusqlite::Result<Pubkey> {
+    r.get::<_, String>(idx)?.parse().map_err(|e| conversion_error(idx, e))
+}
+
+// source-chain addresses are stored as 0x-prefixed hex
+fn hex(bytes: &[u8; 20]) -> String {
+    format!("0x{}", bytes.iter().map(|b| format!("{b:02x}")).collect::<String>())
+}
+
+fn address(r: &Row, idx: usize) -> rusqlite::Result<[u8; 20]> {
+    let s: String = r.get(idx)?;
+    let hex = s.strip_prefix("0x").unwrap_or(&s);
+    if hex.len() != 40 || !hex.is_ascii() {
+        return Err(conversion_error(idx, std::fmt::Error));
+    }
+    let mut bytes = [0; 20];
+    for (i, b) in bytes.iter_mut().enumerate() {
+        *b = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).map_err(|e| conversion_error(idx, e))?;
+    }
+    Ok(bytes)
+}
+
+const DERIVED: &str = "DELETE FROM events; DELETE FROM orders; DELETE FROM receipts; DELETE FROM repayments; DELETE FROM liquidations; \
+    DELETE FROM reservations;";
+
+// replays every stored transaction from scratch, oldest first
+fn rebuild(db: &Connection) -> Result<()> {
+    db.execute_batch(DERIVED)?;
+    let mut stmt = db.prepare("SELECT signature, slot, logs FROM transactions ORDER BY slot, rowid")?;
+    let txs = stmt.query_map([], |r| Ok((r.get::<_, String>(0)?, r.get::<_, u64>(1)?, r.get::<_, String>(2)?)))?;
+    for tx in txs {
+        let (signature, slot, logs) = tx?;
+        let logs: Vec<String> = serde_json::from_str(&logs)?;
+        apply_transaction(db, &signature, slot, &logs)?;
+    }
+    Ok(())
+}
+
+fn apply_transaction(db: &Connection, signature: &str, slot: u64, logs: &[String]) -> Result<()> {
+    for (idx, event) in events::parse_logs(logs).iter().enumerate() {
+        db.execute(
+            "INSERT INTO events (signature, idx, slot, name) VALUES (?1, ?2, ?3, ?4)",
+            params![signature, idx, slot, event.name()],
+        )?;
+        apply(db, signature, slot, idx, event)?;
+    }
+    Ok(())
+}
+
+fn apply(db: &Connection, signature: &str, slot: u64, idx: usize, event: &LendingEvent) -> Result<()> {
+    match event {
+        LendingEvent::PlaceOrder(e) => {
+            db.execute(
+                "INSERT INTO orders (order_sn, lender, mint, rate, term, installments, balance, reserved, status)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, 0, 'open')",
+                params![e.order_sn, e.lender.to_string(), e.mint.to_string(), e.rate, e.term, e.installments, e.balance],
+            )?;
+        }
+        LendingEvent::CancelOrder(e) => {
+            // what is still reserved stays on the order until it is borrowed or released
+            db.execute(
+                "UPDATE orders SET balance = ?2, reserved = ?2, status = 'cancelled' WHERE order_sn = ?1",
+                params![e.order_sn, e.reserved],
+            )?;
+        }
+        LendingEvent::CloseOrder(e) => {
+            db.execute("UPDATE orders SET balance = 0, reserved = 0, status = 'closed' WHERE order_sn = ?1", [e.order_sn])?;
+        }
+        LendingEvent::Reinvest(e) => {
+            db.execute("UPDATE orders SET balance = ?2 WHERE order_sn = ?1", params![e.order_sn, e.order_balance])?;
+        }
+        LendingEvent::ReserveOrder(e) => {
+            db.execute(
+                "INSERT INTO reservations (chainid, collateral_sn, order_sn, borrower, amount) VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![e.chainid, e.collateral_sn, e.order_sn, e.borrower.to_string(), e.amount],
+            )?;
+            db.execute("UPDATE orders SET reserved = reserved + ?2 WHERE order_sn = ?1", params![e.order_sn, e.amount])?;
+        }
+        LendingEvent::ReleaseReservation(e) => {
+            db.execute("DELETE FROM reservations WHERE chainid = ?1 AND collateral_sn = ?2", params![e.chainid, e.collateral_sn])?;
+            db.execute("UPDATE orders SET reserved = reserved - ?2 WHERE order_sn = ?1", params![e.order_sn, e.amount])?;
+        }
+        LendingEvent::BorrowSuccess(e) => {
+            // a borrow consumes the round's reservation when it was made on the same order for the
+            // same borrower; any other reservation stays until it is released
+            let own: Option<u64> = db
+                .query_row(
+                    "DELETE FROM reservations WHERE chainid = ?1 AND collateral_sn = ?2 AND order_sn = ?3 AND borrower = ?4
+                     RETURNING amount",
+                    params![e.chainid, e.c_sn, e.order_sn, e.borrower.to_string()],
+                    |r| r.get(0),
+                )
+                .optional()?;
+            db.execute(
+                "UPDATE orders SET balance = ?2, reserved = reserved - ?3 WHERE order_sn = ?1",
+                params![e.order_sn, e.order_balance, own.unwrap_or(0)],
+            )?;
+            db.execute(
+                "INSERT INTO receipts (receipt_sn, order_sn, borrower, lender, mint, chainid, collateral_sn,
+                     source, token, frozen, amount, rate, time, term, installments, grace, repaid, status)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, 0, 'open')",
+                params![
+                    e.receipt_sn,
+                    e.order_sn,
+                    e.borrower.to_string(),
+                    e.lender.to_string(),
+                    e.mint.to_string(),
+                    e.chainid,
+                    e.c_sn,
+                    hex(&e.source),
+                    hex(&e.token),
+                    e.frozen,
+                    e.amount,
+                    e.rate,
+                    e.time,
+                    e.term,
+                    e.installments,
+                    e.grace
+                ],
+            )?;
+        }
+        LendingEvent::ExtendLoan(e) => {
+            db.execute("UPDATE receipts SET time = ?2, rate = ?3 WHERE receipt_sn = ?1", params![e.receipt_sn, e.time, e.rate])?;
+        }
+        LendingEvent::PartialRepay(e) => {
+            db.execute("UPDATE receipts SET repaid = ?2 WHERE receipt_sn = ?1", params![e.receipt_sn, e.repaid])?;
+            db.execute(
+                "INSERT INTO repayments (signature, idx, slot, receipt_sn, amount, income, remaining, full)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, 0)",
+                params![signature, idx, slot, e.receipt_sn, e.amount, e.income, e.remaining],
+            )?;
+        }
+        LendingEvent::RepaySuccess(e) => {
+            db.execute("UPDATE receipts SET status = 'repaid' WHERE receipt_sn = ?1", [e.receipt_sn])?;
+            db.execute(
+                "INSERT INTO repayments (signature, idx, slot, receipt_sn, amount, income, remaining, full)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, 0, 1)",
+                params![signature, idx, slot, e.receipt_sn, e.amount, e.income],
+            )?;
+        }
+        LendingEvent::Liquidate(e) => {
+            db.execute("UPDATE receipts SET status = 'liquidated' WHERE receipt_sn = ?1", [e.receipt_sn])?;
+            db.execute(
+                "INSERT INTO liquidations (signature, idx, slot, receipt_sn, chainid, collateral_sn, source, frozen, receiver)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                params![signature, idx, slot, e.receipt_sn, e.chainid, e.c_sn, hex(&e.source), e.frozen, hex(&e.receiver)],
+            )?;
+        }
+        _ => {}
+    }
+    Ok(())
+}
@@ -0,0 +1,98 @@
+//! Where transactions come from: a JSON lines file of [`TransactionLogs`], or an RPC node queried
+//! for the lending program's signatures.
+
+use std::io::BufRead;
+use std::path::Path;
+use std::str::FromStr;
+
+use solana_client::rpc_client::{GetConfirmedSignaturesForAddress2Config, RpcClient};
+use solana_client::rpc_config::RpcTransactionConfig;
+use solana_lending_client::ID;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::signature::Signature;
+use solana_transaction_status::option_serializer::OptionSerializer;
+use solana_transaction_status::UiTransactionEncoding;
+
+use crate::{IndexerError, Result, TransactionLogs};
+
+/// Reads one [`TransactionLogs`] JSON object per line; blank lines are skipped.
+pub fn read_file(path: &Path) -> Result<Vec<TransactionLogs>> {
+    let file = std::io::BufReader::new(std::fs::File::open(path)?);
+    let mut txs = Vec::new();
+    for line in file.lines() {
+        let line = line?;
+        if !line.trim().is_empty() {
+            txs.push(serde_json::from_str(&line)?);
+        }
+    }
+    Ok(txs)
+}
+
+pub struct RpcInput {
+    client: RpcClient,
+}
+
+fn rpc_error<E: std::fmt::Display>(e: E) -> IndexerError {
+    IndexerError::Solana(e.to_string())
+}
+
+impl RpcInput {
+    pub fn new(url: &str) -> Self {
+        RpcInput {
+            client: RpcClient::new_with_commitment(url.to_string(), CommitmentConfig::confirmed()),
+        }
+    }
+
+    /// Successful lending program transactions after `until`, oldest first.
+    pub fn transactions_since(&self, until: Option<&str>) -> Result<Vec<TransactionLogs>> {
+        let until = until.map(Signature::from_str).transpose().map_err(rpc_error)?;
+        let mut signatures = Vec::new();
+        let mut before = None;
+        // newest first, one page at a time back to `until`
+        loop {
+            let page = self
+                .client
+                .get_signatures_for_address_with_config(
+                    &ID,
+                    GetConfirmedSignaturesForAddress2Config {
+                        before,
+                        until,
+                        limit: None,
+                        commitment: Some(self.client.commitment()),
+                    },
+                )
+                .map_err(rpc_error)?;
+            let Some(last) = page.last() else {
+                break;
+            };
+            before = Some(Signature::from_str(&last.signature).map_err(rpc_error)?);
+            signatures.extend(page.into_iter().filter(|s| s.err.is_none()).map(|s| s.signature));
+        }
+
+        let mut txs = Vec::new();
+        for signature in signatures.into_iter().rev() {
+            let tx = self
+                .client
+                .get_transaction_with_config(
+                    &Signature::from_str(&signature).map_err(rpc_error)?,
+                    RpcTransactionConfig {
+                        encoding: Some(UiTransactionEncoding::Json),
+                        commitment: Some(self.client.commitment()),
+                        max_supported_transaction_version: Some(0),
+                    },
+                )
+                .map_err(rpc_error)?;
+            let logs = match tx.transaction.meta.map(|m| m.log_messages) {
+                Some(OptionSerializer::Some(logs)) => logs,
+                _ => vec![],
+            };
+            txs.push(TransactionLogs {
+                signature,
+                slot: tx.slot,
+                block_time: tx.block_time,
+                logs,
+            });
+        }
+        Ok(txs)
+    }
+}
@@ -0,0 +1,37 @@
+//! Indexes the lending program's events into SQLite. Transactions are read from a JSON lines
+//! file or straight from an RPC node, their `Program data:` logs are decoded with the client
+//! SDK, and the events are folded into tables of orders, receipts, repayments and liquidations.
+
+pub mod db;
+pub mod input;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+pub use db::{Indexer, OrderRow, ReceiptRow};
+pub use input::{read_file, RpcInput};
+
+/// One lending program transaction as it comes out of `getTransaction`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TransactionLogs {
+    pub signature: String,
+    pub slot: u64,
+    pub block_time: Option<i64>,
+    pub logs: Vec<String>,
+}
+
+#[derive(Debug, Error)]
+pub enum IndexerError {
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("json error: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("sqlite error: {0}")]
+    Sqlite(#[from] rusqlite::Error),
+    #[error("solana rpc error: {0}")]
+    Solana(String),
+    #[error("{0}")]
+    Usage(String),
+}
+
+pub type Result<T> = std::result::Result<T, IndexerError>;
@@ -0,0 +1,70 @@
+//! solana-lending-indexer <db> file <transactions.jsonl>
+//! solana-lending-indexer <db> rpc <url> [poll-secs]
+//! solana-lending-indexer <db> open-orders [mint]
+//! solana-lending-indexer <db> overdue [unix-time]
+
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use solana_lending_indexer::{read_file, Indexer, IndexerError, Result, RpcInput};
+
+const USAGE: &str = "usage: solana-lending-indexer <db> (file <path> | rpc <url> [poll-secs] | open-orders [mint] | overdue [unix-time])";
+
+fn usage() -> IndexerError {
+    IndexerError::Usage(USAGE.into())
+}
+
+fn parse<T: std::str::FromStr>(arg: &str) -> Result<T> {
+    arg.parse().map_err(|_| IndexerError::Usage(format!("invalid argument {arg}\n{USAGE}")))
+}
+
+fn catch_up(indexer: &mut Indexer, rpc: &RpcInput) -> Result<()> {
+    let txs = rpc.transactions_since(indexer.latest_signature()?.as_deref())?;
+    let count = indexer.index_all(&txs)?;
+    println!("indexed {count} of {} transactions", txs.len());
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let [db, command, rest @ ..] = args.as_slice() else {
+        return Err(usage());
+    };
+    let mut indexer = Indexer::open(Path::new(db))?;
+    match (command.as_str(), rest) {
+        ("file", [path]) => {
+            let txs = read_file(Path::new(path))?;
+            let count = indexer.index_all(&txs)?;
+            println!("indexed {count} of {} transactions", txs.len());
+        }
+        ("rpc", [url]) => catch_up(&mut indexer, &RpcInput::new(url))?,
+        ("rpc", [url, secs]) => {
+            let rpc = RpcInput::new(url);
+            loop {
+                if let Err(e) = catch_up(&mut indexer, &rpc) {
+                    eprintln!("{e}");
+                }
+                std::thread::sleep(Duration::from_secs(parse(secs)?));
+            }
+        }
+        ("open-orders", [] | [_]) => {
+            let mint = rest.first().map(|m| parse(m)).transpose()?;
+            println!("order_sn\trate\tavailable\tterm\tinstallments\tmint\tlender");
+            for o in indexer.open_orders(mint.as_ref())? {
+                println!("{}\t{}\t{}\t{}\t{}\t{}\t{}", o.order_sn, o.rate, o.available(), o.term, o.installments, o.mint, o.lender);
+            }
+        }
+        ("overdue", [] | [_]) => {
+            let now = match rest.first() {
+                Some(t) => parse(t)?,
+                None => SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs(),
+            };
+            println!("receipt_sn\tdue\tdeadline\tamount\trepaid\tchainid\tcollateral_sn\tborrower\tlender");
+            for r in indexer.overdue_receipts(now)? {
+                println!("{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}", r.receipt_sn, r.due(), r.deadline(), r.amount, r.repaid, r.chainid, r.collateral_sn, r.borrower, r.lender);
+            }
+        }
+        _ => return Err(usage()),
+    }
+    Ok(())
+}
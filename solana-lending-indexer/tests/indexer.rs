@@ -0,0 +1,202 @@
+use anchor_lang::prelude::Pubkey;
+use solana_lending_testkit::{Loan, Protocol, CHAIN, DAY, GRACE, LAMPORTS, MULTISIG_TIMEOUT, SOURCE};
+use solana_lending_client::events::log_line;
+use solana_lending_client::ID;
+use solana_lending_indexer::*;
+
+const TERM: u64 = 30 * DAY;
+
+// Runs protocol actions and records each one as a transaction in its own slot, with the events
+// logged the way the runtime logs them.
+struct Recorder {
+    p: Protocol,
+    txs: Vec<TransactionLogs>,
+}
+
+impl Recorder {
+    fn new() -> Self {
+        let p = Protocol::new();
+        p.env.take_events();
+        Recorder { p, txs: vec![] }
+    }
+
+    fn record<T>(&mut self, f: impl FnOnce(&mut Protocol) -> T) -> T {
+        let out = f(&mut self.p);
+        let mut logs = vec![format!("Program {ID} invoke [1]")];
+        logs.extend(self.p.env.take_events().iter().map(|data| log_line(data)));
+        logs.push(format!("Program {ID} success"));
+        let slot = self.txs.len() as u64 + 1;
+        self.txs.push(TransactionLogs {
+            signature: format!("sig{slot}"),
+            slot,
+            block_time: Some(self.p.env.now() as i64),
+            logs,
+        });
+        out
+    }
+}
+
+// Two loans, one repaid and one liquidated, next to a cheap cancelled order and a dear open one.
+fn history() -> (Recorder, Loan, Loan) {
+    let mut r = Recorder::new();
+    let repaid = r.record(|p| p.loan(LAMPORTS, 500, TERM, 1));
+    let liquidated = r.record(|p| p.loan(LAMPORTS, 800, TERM, 1));
+    r.record(|p| p.lender_with_order(LAMPORTS, 700, TERM, 1));
+    r.record(|p| {
+        let (lender, sn) = p.lender_with_order(LAMPORTS, 100, TERM, 1);
+        p.cancel_order(&lender, sn).unwrap();
+    });
+    r.record(|p| p.repay(&repaid.borrower, repaid.receipt_sn).unwrap());
+    r.record(|p| {
+        p.env.warp(TERM + GRACE + 1);
+        p.liquidate(&liquidated.lender, liquidated.receipt_sn).unwrap();
+    });
+    (r, repaid, liquidated)
+}
+
+fn count(indexer: &Indexer, table: &str) -> u64 {
+    indexer.connection().query_row(&format!("SELECT COUNT(*) FROM {table}"), [], |r| r.get(0)).unwrap()
+}
+
+#[test]
+fn indexes_orders_receipts_repayments_and_liquidations() {
+    let (r, repaid, liquidated) = history();
+    let mut indexer = Indexer::open_in_memory().unwrap();
+    assert_eq!(indexer.index_all(&r.txs).unwrap(), r.txs.len());
+
+    let rates: Vec<u64> = indexer.open_orders(None).unwrap().iter().map(|o| o.rate).collect();
+    assert_eq!(rates, vec![500, 700, 800]);
+    assert!(indexer.open_orders(Some(&Pubkey::new_unique())).unwrap().is_empty());
+    let order = indexer.order(repaid.order_sn).unwrap().unwrap();
+    assert_eq!((order.lender, order.balance, order.status.as_str()), (repaid.lender, 9 * LAMPORTS, "open"));
+    assert_eq!(order.reserved, 0);
+
+    let receipt = indexer.receipt(repaid.receipt_sn).unwrap().unwrap();
+    assert_eq!(receipt.borrower, repaid.borrower);
+    assert_eq!((receipt.chainid, receipt.collateral_sn, receipt.source), (CHAIN, repaid.collateral_sn, SOURCE));
+    assert_eq!((receipt.amount, receipt.rate, receipt.term), (LAMPORTS, 500, TERM));
+    assert_eq!((receipt.installments, receipt.grace), (1, GRACE));
+    assert_eq!(receipt.status, "repaid");
+    assert_eq!(indexer.receipt(liquidated.receipt_sn).unwrap().unwrap().status, "liquidated");
+    assert_eq!(count(&indexer, "repayments"), 1);
+    assert_eq!(count(&indexer, "liquidations"), 1);
+    assert_eq!(indexer.latest_signature().unwrap(), r.txs.last().map(|tx| tx.signature.clone()));
+}
+
+#[test]
+fn overdue_receipts() {
+    let mut r = Recorder::new();
+    let early = r.record(|p| p.loan(LAMPORTS, 500, TERM, 1));
+    r.record(|p| p.env.warp(DAY));
+    let late = r.record(|p| p.loan(LAMPORTS, 500, TERM, 1));
+    let mut indexer = Indexer::open_in_memory().unwrap();
+    indexer.index_all(&r.txs).unwrap();
+
+    let receipt = indexer.receipt(early.receipt_sn).unwrap().unwrap();
+    assert_eq!(receipt.deadline(), receipt.time + TERM + GRACE);
+    let deadline = receipt.deadline();
+    assert!(indexer.overdue_receipts(deadline).unwrap().is_empty());
+    let overdue: Vec<u64> = indexer.overdue_receipts(deadline + 2 * DAY).unwrap().iter().map(|r| r.receipt_sn).collect();
+    assert_eq!(overdue, vec![early.receipt_sn, late.receipt_sn]);
+
+    r.record(|p| p.repay(&early.borrower, early.receipt_sn).unwrap());
+    indexer.index_all(&r.txs).unwrap();
+    let overdue: Vec<u64> = indexer.overdue_receipts(deadline + 2 * DAY).unwrap().iter().map(|r| r.receipt_sn).collect();
+    assert_eq!(overdue, vec![late.receipt_sn]);
+}
+
+#[test]
+fn overdue_installments() {
+    let mut r = Recorder::new();
+    let loan = r.record(|p| p.loan(LAMPORTS, 500, TERM, 2));
+    let mut indexer = Indexer::open_in_memory().unwrap();
+    indexer.index_all(&r.txs).unwrap();
+
+    // the first of two installments falls due half way through the term
+    let receipt = indexer.receipt(loan.receipt_sn).unwrap().unwrap();
+    assert_eq!(receipt.due(), receipt.time + TERM / 2);
+    assert!(indexer.overdue_receipts(receipt.time + TERM / 2 + GRACE).unwrap().is_empty());
+    assert_eq!(indexer.overdue_receipts(receipt.time + TERM / 2 + GRACE + 1).unwrap(), vec![receipt.clone()]);
+
+    // paying it moves the receipt on to the final installment
+    let first = (LAMPORTS + LAMPORTS * 500 / 10000) / 2;
+    r.record(|p| p.repay_partial(&loan.borrower, loan.receipt_sn, first).unwrap());
+    indexer.index_all(&r.txs).unwrap();
+    let receipt = indexer.receipt(loan.receipt_sn).unwrap().unwrap();
+    assert_eq!(receipt.due(), receipt.time + TERM);
+    assert!(indexer.overdue_receipts(receipt.time + TERM / 2 + GRACE + 1).unwrap().is_empty());
+    assert_eq!(indexer.overdue_receipts(receipt.time + TERM + GRACE + 1).unwrap(), vec![receipt]);
+}
+
+#[test]
+fn tracks_reservations() {
+    let mut r = Recorder::new();
+    let (lender, sn) = r.record(|p| p.lender_with_order(3 * LAMPORTS, 500, TERM, 1));
+    let borrower = Pubkey::new_unique();
+    // the first witness signature reserves the amount for the round
+    r.record(|p| p.multisig_collateral(&p.witnesses[0].clone(), 1, sn, &borrower, LAMPORTS).unwrap());
+    let mut indexer = Indexer::open_in_memory().unwrap();
+    indexer.index_all(&r.txs).unwrap();
+    let order = indexer.order(sn).unwrap().unwrap();
+    assert_eq!((order.balance, order.reserved, order.available()), (3 * LAMPORTS, LAMPORTS, 2 * LAMPORTS));
+    assert_eq!(indexer.open_orders(None).unwrap(), vec![order]);
+
+    // cancelling keeps the reserved part on the order until the reservation is released
+    r.record(|p| p.cancel_order(&lender, sn).unwrap());
+    indexer.index_all(&r.txs).unwrap();
+    let order = indexer.order(sn).unwrap().unwrap();
+    assert_eq!((order.balance, order.reserved, order.status.as_str()), (LAMPORTS, LAMPORTS, "cancelled"));
+    assert!(indexer.open_orders(None).unwrap().is_empty());
+
+    r.record(|p| {
+        p.env.warp(MULTISIG_TIMEOUT);
+        p.release_reservation(&lender, 1).unwrap();
+    });
+    indexer.index_all(&r.txs).unwrap();
+    let order = indexer.order(sn).unwrap().unwrap();
+    assert_eq!((order.balance, order.reserved, order.available()), (LAMPORTS, 0, LAMPORTS));
+    assert_eq!(count(&indexer, "reservations"), 0);
+
+    r.record(|p| p.cancel_order(&lender, sn).unwrap());
+    indexer.index_all(&r.txs).unwrap();
+    assert_eq!(indexer.order(sn).unwrap().unwrap().balance, 0);
+}
+
+#[test]
+fn replay_is_idempotent_and_order_independent() {
+    let (r, repaid, liquidated) = history();
+    let mut indexer = Indexer::open_in_memory().unwrap();
+    indexer.index_all(&r.txs).unwrap();
+    assert_eq!(indexer.index_all(&r.txs).unwrap(), 0);
+    let events = count(&indexer, "events");
+    assert_eq!(count(&indexer, "repayments"), 1);
+
+    // transactions delivered newest first rebuild the tables each time an older one arrives
+    let mut backfill = Indexer::open_in_memory().unwrap();
+    for tx in r.txs.iter().rev() {
+        assert!(backfill.index(tx).unwrap());
+    }
+    assert_eq!(count(&backfill, "events"), events);
+    assert_eq!(backfill.open_orders(None).unwrap(), indexer.open_orders(None).unwrap());
+    for sn in [repaid.receipt_sn, liquidated.receipt_sn] {
+        assert_eq!(backfill.receipt(sn).unwrap(), indexer.receipt(sn).unwrap());
+    }
+    assert_eq!(count(&backfill, "repayments"), 1);
+    assert_eq!(count(&backfill, "liquidations"), 1);
+}
+
+#[test]
+fn reads_transactions_from_a_file() {
+    let (r, ..) = history();
+    let path = std::env::temp_dir().join(format!("cclending-indexer-{}.jsonl", std::process::id()));
+    let lines: Vec<String> = r.txs.iter().map(|tx| serde_json::to_string(tx).unwrap()).collect();
+    std::fs::write(&path, lines.join("\n\n")).unwrap();
+
+    let txs = read_file(&path).unwrap();
+    assert_eq!(txs, r.txs);
+    let db = path.with_extension("sqlite");
+    let _ = std::fs::remove_file(&db);
+    assert_eq!(Indexer::open(&db).unwrap().index_all(&txs).unwrap(), txs.len());
+    // a reopened database keeps what was indexed
+    assert_eq!(Indexer::open(&db).unwrap().index_all(&txs).unwrap(), 0);
+}
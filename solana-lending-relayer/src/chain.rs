@@ -0,0 +1,114 @@
+//! The Solana side of the relayer: reading program accounts, sending instructions signed by the
+//! witness key and reading back the lending program's transaction logs.
+
+use std::str::FromStr;
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::Instruction;
+use solana_client::rpc_client::{GetConfirmedSignaturesForAddress2Config, RpcClient};
+use solana_client::rpc_config::RpcTransactionConfig;
+use solana_lending_client::ID;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::signature::{Keypair, Signature, Signer};
+use solana_sdk::transaction::Transaction;
+use solana_transaction_status::option_serializer::OptionSerializer;
+use solana_transaction_status::UiTransactionEncoding;
+
+use crate::{RelayerError, Result};
+
+pub trait LendingChain {
+    /// The witness key that signs and pays for the relayer's transactions.
+    fn payer(&self) -> Pubkey;
+
+    fn account(&self, key: &Pubkey) -> Result<Option<Vec<u8>>>;
+
+    /// Sends the instructions in one transaction and returns its signature.
+    fn send(&mut self, instructions: &[Instruction]) -> Result<String>;
+
+    /// Successful transactions of the lending program after `cursor`, oldest first, as
+    /// signature and log messages.
+    fn transactions_since(&mut self, cursor: Option<&str>) -> Result<Vec<(String, Vec<String>)>>;
+}
+
+pub struct RpcChain {
+    client: RpcClient,
+    witness: Keypair,
+}
+
+impl RpcChain {
+    pub fn new(url: &str, witness: Keypair) -> Self {
+        RpcChain {
+            client: RpcClient::new_with_commitment(url.to_string(), CommitmentConfig::confirmed()),
+            witness,
+        }
+    }
+}
+
+fn rpc_error<E: std::fmt::Display>(e: E) -> RelayerError {
+    RelayerError::Solana(e.to_string())
+}
+
+impl LendingChain for RpcChain {
+    fn payer(&self) -> Pubkey {
+        self.witness.pubkey()
+    }
+
+    fn account(&self, key: &Pubkey) -> Result<Option<Vec<u8>>> {
+        let account = self.client.get_account_with_commitment(key, self.client.commitment()).map_err(rpc_error)?.value;
+        Ok(account.map(|a| a.data))
+    }
+
+    fn send(&mut self, instructions: &[Instruction]) -> Result<String> {
+        let blockhash = self.client.get_latest_blockhash().map_err(rpc_error)?;
+        let tx = Transaction::new_signed_with_payer(instructions, Some(&self.witness.pubkey()), &[&self.witness], blockhash);
+        let signature = self.client.send_and_confirm_transaction(&tx).map_err(|e| RelayerError::Transaction(e.to_string()))?;
+        Ok(signature.to_string())
+    }
+
+    fn transactions_since(&mut self, cursor: Option<&str>) -> Result<Vec<(String, Vec<String>)>> {
+        let until = cursor.map(Signature::from_str).transpose().map_err(rpc_error)?;
+        let mut signatures = Vec::new();
+        let mut before = None;
+        // newest first, one page at a time back to the cursor
+        loop {
+            let page = self
+                .client
+                .get_signatures_for_address_with_config(
+                    &ID,
+                    GetConfirmedSignaturesForAddress2Config {
+                        before,
+                        until,
+                        limit: None,
+                        commitment: Some(self.client.commitment()),
+                    },
+                )
+                .map_err(rpc_error)?;
+            let Some(last) = page.last() else {
+                break;
+            };
+            before = Some(Signature::from_str(&last.signature).map_err(rpc_error)?);
+            signatures.extend(page.into_iter().filter(|s| s.err.is_none()).map(|s| s.signature));
+        }
+
+        let mut transactions = Vec::new();
+        for signature in signatures.into_iter().rev() {
+            let tx = self
+                .client
+                .get_transaction_with_config(
+                    &Signature::from_str(&signature).map_err(rpc_error)?,
+                    RpcTransactionConfig {
+                        encoding: Some(UiTransactionEncoding::Json),
+                        commitment: Some(self.client.commitment()),
+                        max_supported_transaction_version: Some(0),
+                    },
+                )
+                .map_err(rpc_error)?;
+            let logs = match tx.transaction.meta.map(|m| m.log_messages) {
+                Some(OptionSerializer::Some(logs)) => logs,
+                _ => vec![],
+            };
+            transactions.push((signature, logs));
+        }
+        Ok(transactions)
+    }
+}
@@ -0,0 +1,35 @@
+//! A reference witness relayer. It watches a source chain for collateral freezes, signs the
+//! matching `multisig_collateral` round on Solana, drives the round through `borrow` and
+//! `clear_collateral`, and forwards the outcome of every round and loan back to the source chain.
+
+pub mod chain;
+pub mod progress;
+pub mod relayer;
+pub mod source;
+
+use thiserror::Error;
+
+pub use chain::{LendingChain, RpcChain};
+pub use progress::{Progress, Round, RoundStatus};
+pub use relayer::Relayer;
+pub use source::{FreezeEvent, JsonRpcSource, MockSource, Settlement, SettlementKey, SourceChain};
+
+#[derive(Debug, Error)]
+pub enum RelayerError {
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("json error: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("solana rpc error: {0}")]
+    Solana(String),
+    #[error("source chain error: {0}")]
+    Source(String),
+    #[error("transaction failed: {0}")]
+    Transaction(String),
+    #[error("cannot decode account {0}")]
+    Decode(String),
+    #[error("config error: {0}")]
+    Config(String),
+}
+
+pub type Result<T> = std::result::Result<T, RelayerError>;
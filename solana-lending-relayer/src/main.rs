@@ -0,0 +1,66 @@
+//! Runs one witness relayer from a JSON config file:
+//!
+//! ```json
+//! {
+//!   "rpc_url": "http://127.0.0.1:8899",
+//!   "keypair": "witness.json",
+//!   "state": "relayer-state.json",
+//!   "poll_secs": 5,
+//!   "source": { "kind": "json_rpc", "chainid": 5, "url": "http://127.0.0.1:8545" }
+//! }
+//! ```
+//!
+//! `source` may also be `{ "kind": "mock", "chainid": 5, "path": "freezes.json" }`.
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use log::{error, info};
+use serde::Deserialize;
+use solana_lending_relayer::{JsonRpcSource, LendingChain, MockSource, Relayer, RelayerError, Result, RpcChain, SourceChain};
+use solana_sdk::signature::read_keypair_file;
+
+#[derive(Deserialize)]
+struct Config {
+    rpc_url: String,
+    keypair: PathBuf,
+    state: PathBuf,
+    #[serde(default = "default_poll_secs")]
+    poll_secs: u64,
+    source: Source,
+}
+
+fn default_poll_secs() -> u64 {
+    5
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum Source {
+    Mock { chainid: u32, path: PathBuf },
+    JsonRpc { chainid: u32, url: String },
+}
+
+fn run<S: SourceChain, L: LendingChain>(mut relayer: Relayer<S, L>, poll: Duration) -> ! {
+    loop {
+        if let Err(e) = relayer.tick() {
+            error!("{e}");
+        }
+        std::thread::sleep(poll);
+    }
+}
+
+fn main() -> Result<()> {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+    let path = std::env::args().nth(1).ok_or_else(|| RelayerError::Config("usage: solana-lending-relayer <config.json>".into()))?;
+    let config: Config = serde_json::from_slice(&std::fs::read(path)?)?;
+    let witness = read_keypair_file(&config.keypair).map_err(|e| RelayerError::Config(format!("{}: {e}", config.keypair.display())))?;
+    let chain = RpcChain::new(&config.rpc_url, witness);
+    info!("relaying as witness {}", chain.payer());
+
+    let poll = Duration::from_secs(config.poll_secs);
+    match config.source {
+        Source::Mock { chainid, path } => run(Relayer::new(MockSource::from_file(chainid, &path)?, chain, &config.state)?, poll),
+        Source::JsonRpc { chainid, url } => run(Relayer::new(JsonRpcSource::new(chainid, &url), chain, &config.state)?, poll),
+    }
+}
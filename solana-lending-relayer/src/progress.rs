@@ -0,0 +1,63 @@
+//! The relayer's local progress, saved as JSON after every pass so a restart resumes where it
+//! left off instead of re-signing rounds or re-forwarding settlements.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{FreezeEvent, Result, SettlementKey};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RoundStatus {
+    /// Signed by this witness; waiting for the others.
+    Signed,
+    /// The round reached consensus; `borrow` is pending.
+    Consensus,
+    /// The loan was made, or the round expired or failed; `clear_collateral` is pending.
+    Finished,
+    /// The round's branches were cleared; nothing left to do.
+    Cleared,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Round {
+    pub freeze: FreezeEvent,
+    pub status: RoundStatus,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Progress {
+    /// The next source-chain block to poll.
+    pub next_block: u64,
+    /// The last lending program transaction whose events were forwarded.
+    pub cursor: Option<String>,
+    /// Rounds keyed by collateral sn.
+    pub rounds: BTreeMap<u64, Round>,
+    /// Settlements forwarded to the source chain.
+    pub settled: BTreeSet<SettlementKey>,
+}
+
+impl Progress {
+    /// Loads the progress file, starting afresh when it does not exist yet.
+    pub fn load(path: &Path) -> Result<Self> {
+        match std::fs::read(path) {
+            Ok(data) => Ok(serde_json::from_slice(&data)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Progress::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    // written to a temporary file first so a crash never leaves a truncated file behind
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, serde_json::to_vec_pretty(self)?)?;
+        std::fs::rename(tmp, path)?;
+        Ok(())
+    }
+
+    pub fn pending(&self) -> impl Iterator<Item = u64> + '_ {
+        self.rounds.iter().filter(|(_, r)| r.status != RoundStatus::Cleared).map(|(sn, _)| *sn)
+    }
+}
@@ -0,0 +1,224 @@
+use std::path::{Path, PathBuf};
+
+use anchor_lang::prelude::Pubkey;
+use log::{info, warn};
+use solana_lending_client::events::{self, LendingEvent};
+use solana_lending_client::instructions as ix;
+use solana_lending_client::{decode_account, pda, CollateralWithMultisig, GlobalState, MultisigStatus, Order, Witnesses};
+
+use crate::{FreezeEvent, LendingChain, Progress, RelayerError, Result, Round, RoundStatus, Settlement, SourceChain};
+
+const TOKEN_PROGRAM: Pubkey = solana_sdk::pubkey!("TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA");
+const ASSOCIATED_TOKEN_PROGRAM: Pubkey = solana_sdk::pubkey!("ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL");
+
+/// Token loans are paid out to the borrower's associated token account.
+pub fn associated_token_address(owner: &Pubkey, mint: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[owner.as_ref(), TOKEN_PROGRAM.as_ref(), mint.as_ref()], &ASSOCIATED_TOKEN_PROGRAM).0
+}
+
+pub struct Relayer<S: SourceChain, L: LendingChain> {
+    pub source: S,
+    pub chain: L,
+    pub progress: Progress,
+    state: PathBuf,
+}
+
+impl<S: SourceChain, L: LendingChain> Relayer<S, L> {
+    pub fn new(source: S, chain: L, state: &Path) -> Result<Self> {
+        Ok(Relayer {
+            source,
+            chain,
+            progress: Progress::load(state)?,
+            state: state.to_path_buf(),
+        })
+    }
+
+    /// One pass: pick up new freezes, advance every open round and forward new settlements.
+    /// Failures of a single round are logged and retried on the next pass.
+    pub fn tick(&mut self) -> Result<()> {
+        self.poll_source()?;
+        for sn in self.progress.pending().collect::<Vec<_>>() {
+            if let Err(e) = self.advance(sn) {
+                warn!("collateral {sn}: {e}");
+            }
+        }
+        self.progress.save(&self.state)?;
+        self.forward_settlements()?;
+        self.progress.save(&self.state)
+    }
+
+    fn poll_source(&mut self) -> Result<()> {
+        let (freezes, next_block) = self.source.freeze_events(self.progress.next_block)?;
+        for freeze in freezes {
+            if freeze.chainid != self.source.chainid() || self.progress.rounds.contains_key(&freeze.collateral_sn) {
+                continue;
+            }
+            info!("collateral {}: frozen for order {}", freeze.collateral_sn, freeze.order_sn);
+            let sn = freeze.collateral_sn;
+            self.progress.rounds.insert(sn, Round { freeze, status: RoundStatus::Signed });
+            if let Err(e) = self.sign(sn) {
+                warn!("collateral {sn}: {e}");
+            }
+        }
+        self.progress.next_block = next_block;
+        Ok(())
+    }
+
+    fn multisig(&self, freeze: &FreezeEvent) -> Result<Option<CollateralWithMultisig>> {
+        let key = pda::collateral(freeze.chainid, freeze.collateral_sn);
+        match self.chain.account(&key)? {
+            Some(data) => decode_account(&data).map(Some).map_err(|e| RelayerError::Decode(format!("{key}: {e}"))),
+            None => Ok(None),
+        }
+    }
+
+    fn sign(&mut self, sn: u64) -> Result<()> {
+        let freeze = self.progress.rounds[&sn].freeze.clone();
+        let payer = self.chain.payer();
+        if let Some(multisig) = self.multisig(&freeze)? {
+            if multisig.branches.iter().any(|b| b.signers.contains(&payer)) {
+                return Ok(());
+            }
+        }
+        let signature = self.chain.send(&[ix::multisig_collateral(&payer, &freeze.collateral())])?;
+        info!("collateral {sn}: signed in {signature}");
+        Ok(())
+    }
+
+    fn advance(&mut self, sn: u64) -> Result<()> {
+        let freeze = self.progress.rounds[&sn].freeze.clone();
+        let Some(multisig) = self.multisig(&freeze)? else {
+            return self.sign(sn);
+        };
+        let payer = self.chain.payer();
+        let status = match multisig.status {
+            MultisigStatus::Starting | MultisigStatus::InProgress => {
+                self.sign(sn)?;
+                RoundStatus::Signed
+            }
+            // a witness removal can undo the consensus; recount so the round takes signatures again
+            MultisigStatus::ReachConsensus if !self.still_agreed(&multisig)? => {
+                let signature = self.chain.send(&[ix::recount_collateral(&payer, freeze.chainid, sn)])?;
+                info!("collateral {sn}: consensus lost to a witness change, recounted in {signature}");
+                RoundStatus::Signed
+            }
+            MultisigStatus::ReachConsensus => {
+                self.set_status(sn, RoundStatus::Consensus);
+                self.borrow(&multisig)?;
+                RoundStatus::Finished
+            }
+            MultisigStatus::Finished | MultisigStatus::Expired | MultisigStatus::Failed if multisig.branches.is_empty() => RoundStatus::Cleared,
+            MultisigStatus::Finished | MultisigStatus::Expired | MultisigStatus::Failed => {
+                self.set_status(sn, RoundStatus::Finished);
+                let signature = self.chain.send(&[ix::clear_collateral(&payer, freeze.chainid, sn)])?;
+                info!("collateral {sn}: cleared in {signature}");
+                RoundStatus::Cleared
+            }
+        };
+        self.set_status(sn, status);
+        Ok(())
+    }
+
+    fn set_status(&mut self, sn: u64, status: RoundStatus) {
+        if let Some(round) = self.progress.rounds.get_mut(&sn) {
+            round.status = status;
+        }
+    }
+
+    // the agreed collateral may differ from what this witness saw, so borrow against the branch
+    // that reached consensus; an order that is gone or cannot fill it fails the round instead
+    fn borrow(&mut self, multisig: &CollateralWithMultisig) -> Result<()> {
+        let payer = self.chain.payer();
+        let c = multisig.branches[0].collateral;
+        let global: GlobalState = self.decode(&pda::global_state())?;
+        let order = match self.chain.account(&pda::order(c.order_sn))? {
+            Some(data) => Some(decode_account::<Order>(&data).map_err(|e| RelayerError::Decode(format!("order {}: {e}", c.order_sn)))?),
+            None => None,
+        };
+        let Some(order) = order.filter(|o| o.available() + multisig.own_reservation() >= c.amount) else {
+            let signature = self.chain.send(&[ix::fail_borrow(&payer, &c)])?;
+            info!("collateral {}: order {} cannot fill, failed in {signature}", c.collateral_sn, c.order_sn);
+            return Ok(());
+        };
+        let instruction = if order.mint == Pubkey::default() {
+            ix::borrow(&payer, &c, global.curr_receipt_sn)
+        } else {
+            ix::borrow_token(&payer, &c, global.curr_receipt_sn, &order.mint, &associated_token_address(&c.borrower, &order.mint))
+        };
+        let signature = self.chain.send(&[instruction])?;
+        info!("collateral {}: borrowed receipt {} in {signature}", c.collateral_sn, global.curr_receipt_sn);
+        Ok(())
+    }
+
+    fn still_agreed(&self, multisig: &CollateralWithMultisig) -> Result<bool> {
+        let witnesses: Witnesses = self.decode(&pda::witnesses())?;
+        Ok(multisig.valid_signers(&multisig.branches[0].signers, &witnesses).len() as u64 >= multisig.threshold)
+    }
+
+    fn decode<T: anchor_lang::AccountDeserialize>(&self, key: &Pubkey) -> Result<T> {
+        let data = self.chain.account(key)?.ok_or_else(|| RelayerError::Decode(format!("{key}: account not found")))?;
+        decode_account(&data).map_err(|e| RelayerError::Decode(format!("{key}: {e}")))
+    }
+
+    fn forward_settlements(&mut self) -> Result<()> {
+        let transactions = self.chain.transactions_since(self.progress.cursor.as_deref())?;
+        for (signature, logs) in transactions {
+            for event in events::parse_logs(&logs) {
+                let settlement = match event {
+                    LendingEvent::BorrowFailed(e) => Settlement::BorrowFailed {
+                        chainid: e.chainid,
+                        collateral_sn: e.collateral_sn,
+                        order_sn: e.order_sn,
+                        amount: e.amount,
+                    },
+                    LendingEvent::CollateralRejected(e) => Settlement::Rejected {
+                        chainid: e.chainid,
+                        collateral_sn: e.collateral_sn,
+                    },
+                    LendingEvent::PartialRepay(e) => Settlement::PartiallyRepaid {
+                        chainid: e.chainid,
+                        collateral_sn: e.c_sn,
+                        receipt_sn: e.receipt_sn,
+                        source: e.source,
+                        repaid: e.repaid,
+                        remaining: e.remaining,
+                        released: e.released,
+                    },
+                    LendingEvent::ExtendLoan(e) => Settlement::Extended {
+                        chainid: e.chainid,
+                        collateral_sn: e.c_sn,
+                        receipt_sn: e.receipt_sn,
+                        source: e.source,
+                        expiry: e.expiry,
+                    },
+                    LendingEvent::RepaySuccess(e) => Settlement::Repaid {
+                        chainid: e.chainid,
+                        collateral_sn: e.c_sn,
+                        receipt_sn: e.receipt_sn,
+                        source: e.source,
+                        frozen: e.frozen,
+                    },
+                    LendingEvent::Liquidate(e) => Settlement::Liquidated {
+                        chainid: e.chainid,
+                        collateral_sn: e.c_sn,
+                        receipt_sn: e.receipt_sn,
+                        source: e.source,
+                        frozen: e.frozen,
+                        receiver: e.receiver,
+                    },
+                    _ => continue,
+                };
+                let key = settlement.key();
+                if settlement.chainid() != self.source.chainid() || self.progress.settled.contains(&key) {
+                    continue;
+                }
+                self.source.settle(&settlement)?;
+                info!("collateral {}: forwarded {settlement:?}", settlement.collateral_sn());
+                self.progress.settled.insert(key);
+                self.progress.save(&self.state)?;
+            }
+            self.progress.cursor = Some(signature);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,299 @@
+//! Source-chain backends. A backend reports the collateral freezes of one chain and accepts the
+//! settlements the witnesses relay back so the mortgage contract can unfreeze or liquidate.
+
+use std::path::{Path, PathBuf};
+
+use anchor_lang::prelude::Pubkey;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use solana_lending_client::Collateral;
+
+use crate::{RelayerError, Result};
+
+/// A collateral freeze on the source chain, as submitted to `multisig_collateral`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FreezeEvent {
+    pub chainid: u32,
+    pub collateral_sn: u64,
+    #[serde(with = "hex20")]
+    pub source: [u8; 20],
+    #[serde(with = "hex20")]
+    pub token: [u8; 20],
+    pub frozen: u64,
+    #[serde(with = "base58")]
+    pub borrower: Pubkey,
+    pub order_sn: u64,
+    pub amount: u64,
+}
+
+impl FreezeEvent {
+    pub fn collateral(&self) -> Collateral {
+        Collateral {
+            chainid: self.chainid,
+            collateral_sn: self.collateral_sn,
+            source: self.source,
+            token: self.token,
+            frozen: self.frozen,
+            borrower: self.borrower,
+            order_sn: self.order_sn,
+            amount: self.amount,
+        }
+    }
+}
+
+/// The outcome of a collateral round or loan, relayed back to the chain holding the collateral.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Settlement {
+    /// The round agreed on an order that could not fill it; the collateral can be unfrozen.
+    BorrowFailed {
+        chainid: u32,
+        collateral_sn: u64,
+        order_sn: u64,
+        amount: u64,
+    },
+    /// The round expired without consensus; the collateral can be unfrozen.
+    Rejected {
+        chainid: u32,
+        collateral_sn: u64,
+    },
+    /// Part of the loan was repaid; `released` of the frozen amount can be unfrozen.
+    PartiallyRepaid {
+        chainid: u32,
+        collateral_sn: u64,
+        receipt_sn: u64,
+        #[serde(with = "hex20")]
+        source: [u8; 20],
+        repaid: u64,
+        remaining: u64,
+        released: u64,
+    },
+    /// The loan was extended; the collateral stays frozen until `expiry`.
+    Extended {
+        chainid: u32,
+        collateral_sn: u64,
+        receipt_sn: u64,
+        #[serde(with = "hex20")]
+        source: [u8; 20],
+        expiry: u64,
+    },
+    Repaid {
+        chainid: u32,
+        collateral_sn: u64,
+        receipt_sn: u64,
+        #[serde(with = "hex20")]
+        source: [u8; 20],
+        frozen: u64,
+    },
+    Liquidated {
+        chainid: u32,
+        collateral_sn: u64,
+        receipt_sn: u64,
+        #[serde(with = "hex20")]
+        source: [u8; 20],
+        frozen: u64,
+        #[serde(with = "hex20")]
+        receiver: [u8; 20],
+    },
+}
+
+/// Identifies a forwarded settlement: chain id, collateral sn, kind, and the repaid total or new
+/// expiry that tells repeated partial repayments and extensions of one loan apart.
+pub type SettlementKey = (u32, u64, String, u64);
+
+impl Settlement {
+    pub fn chainid(&self) -> u32 {
+        match self {
+            Settlement::BorrowFailed { chainid, .. }
+            | Settlement::Rejected { chainid, .. }
+            | Settlement::PartiallyRepaid { chainid, .. }
+            | Settlement::Extended { chainid, .. }
+            | Settlement::Repaid { chainid, .. }
+            | Settlement::Liquidated { chainid, .. } => *chainid,
+        }
+    }
+
+    pub fn collateral_sn(&self) -> u64 {
+        match self {
+            Settlement::BorrowFailed { collateral_sn, .. }
+            | Settlement::Rejected { collateral_sn, .. }
+            | Settlement::PartiallyRepaid { collateral_sn, .. }
+            | Settlement::Extended { collateral_sn, .. }
+            | Settlement::Repaid { collateral_sn, .. }
+            | Settlement::Liquidated { collateral_sn, .. } => *collateral_sn,
+        }
+    }
+
+    pub fn key(&self) -> SettlementKey {
+        let (kind, seq) = match self {
+            Settlement::BorrowFailed { .. } => ("borrow_failed", 0),
+            Settlement::Rejected { .. } => ("rejected", 0),
+            Settlement::PartiallyRepaid { repaid, .. } => ("partially_repaid", *repaid),
+            Settlement::Extended { expiry, .. } => ("extended", *expiry),
+            Settlement::Repaid { .. } => ("repaid", 0),
+            Settlement::Liquidated { .. } => ("liquidated", 0),
+        };
+        (self.chainid(), self.collateral_sn(), kind.to_string(), seq)
+    }
+}
+
+pub trait SourceChain {
+    fn chainid(&self) -> u32;
+
+    /// Freezes from `from_block` on, and the block to continue from on the next poll.
+    fn freeze_events(&mut self, from_block: u64) -> Result<(Vec<FreezeEvent>, u64)>;
+
+    fn settle(&mut self, settlement: &Settlement) -> Result<()>;
+}
+
+/// An in-memory source chain for tests and local runs. Events are numbered by their position,
+/// which stands in for the block height. When loaded from a file, settlements are appended to
+/// `<file>.settled` as JSON lines.
+#[derive(Default)]
+pub struct MockSource {
+    pub chainid: u32,
+    pub events: Vec<FreezeEvent>,
+    pub settled: Vec<Settlement>,
+    path: Option<PathBuf>,
+}
+
+impl MockSource {
+    pub fn new(chainid: u32) -> Self {
+        MockSource {
+            chainid,
+            ..MockSource::default()
+        }
+    }
+
+    /// Reads a JSON array of freeze events; the file is re-read on every poll.
+    pub fn from_file(chainid: u32, path: &Path) -> Result<Self> {
+        let mut source = MockSource::new(chainid);
+        source.path = Some(path.to_path_buf());
+        source.reload()?;
+        Ok(source)
+    }
+
+    fn reload(&mut self) -> Result<()> {
+        if let Some(path) = &self.path {
+            self.events = serde_json::from_slice(&std::fs::read(path)?)?;
+        }
+        Ok(())
+    }
+}
+
+impl SourceChain for MockSource {
+    fn chainid(&self) -> u32 {
+        self.chainid
+    }
+
+    fn freeze_events(&mut self, from_block: u64) -> Result<(Vec<FreezeEvent>, u64)> {
+        self.reload()?;
+        let events = self.events.iter().skip(from_block as usize).filter(|e| e.chainid == self.chainid).cloned().collect();
+        Ok((events, self.events.len() as u64))
+    }
+
+    fn settle(&mut self, settlement: &Settlement) -> Result<()> {
+        if let Some(path) = &self.path {
+            use std::io::Write;
+            let mut out = std::fs::OpenOptions::new().create(true).append(true).open(path.with_extension("settled"))?;
+            writeln!(out, "{}", serde_json::to_string(settlement)?)?;
+        }
+        self.settled.push(settlement.clone());
+        Ok(())
+    }
+}
+
+/// A source chain behind a JSON-RPC endpoint exposing two methods:
+///
+/// * `cclending_freezeEvents(chainid, from_block)` returns `{"events": [FreezeEvent], "next_block": u64}`
+/// * `cclending_settle(chainid, Settlement)` submits a settlement to the mortgage contract
+///
+/// A small adapter in front of an EVM node maps these onto `eth_getLogs` and the mortgage
+/// contract's confirmation calls.
+pub struct JsonRpcSource {
+    pub chainid: u32,
+    pub url: String,
+    next_id: u64,
+}
+
+impl JsonRpcSource {
+    pub fn new(chainid: u32, url: &str) -> Self {
+        JsonRpcSource {
+            chainid,
+            url: url.to_string(),
+            next_id: 1,
+        }
+    }
+
+    fn call(&mut self, method: &str, params: Value) -> Result<Value> {
+        let id = self.next_id;
+        self.next_id += 1;
+        let request = json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+        let response: Value = ureq::post(&self.url)
+            .send_json(request)
+            .map_err(|e| RelayerError::Source(e.to_string()))?
+            .into_json()?;
+        if let Some(error) = response.get("error") {
+            return Err(RelayerError::Source(error.to_string()));
+        }
+        response.get("result").cloned().ok_or_else(|| RelayerError::Source(format!("{method}: missing result")))
+    }
+}
+
+#[derive(Deserialize)]
+struct FreezeEvents {
+    events: Vec<FreezeEvent>,
+    next_block: u64,
+}
+
+impl SourceChain for JsonRpcSource {
+    fn chainid(&self) -> u32 {
+        self.chainid
+    }
+
+    fn freeze_events(&mut self, from_block: u64) -> Result<(Vec<FreezeEvent>, u64)> {
+        let result = self.call("cclending_freezeEvents", json!([self.chainid, from_block]))?;
+        let FreezeEvents { events, next_block } = serde_json::from_value(result)?;
+        Ok((events, next_block))
+    }
+
+    fn settle(&mut self, settlement: &Settlement) -> Result<()> {
+        self.call("cclending_settle", json!([self.chainid, settlement]))?;
+        Ok(())
+    }
+}
+
+mod hex20 {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8; 20], s: S) -> Result<S::Ok, S::Error> {
+        let hex: String = bytes.iter().map(|b| format!("{b:02x}")).collect();
+        s.serialize_str(&format!("0x{hex}"))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<[u8; 20], D::Error> {
+        let s = String::deserialize(d)?;
+        let hex = s.strip_prefix("0x").unwrap_or(&s);
+        if hex.len() != 40 || !hex.is_ascii() {
+            return Err(D::Error::custom("expected 20 hex-encoded bytes"));
+        }
+        let mut bytes = [0; 20];
+        for (i, b) in bytes.iter_mut().enumerate() {
+            *b = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).map_err(D::Error::custom)?;
+        }
+        Ok(bytes)
+    }
+}
+
+mod base58 {
+    use anchor_lang::prelude::Pubkey;
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(key: &Pubkey, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_str(&key.to_string())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Pubkey, D::Error> {
+        String::deserialize(d)?.parse().map_err(D::Error::custom)
+    }
+}
@@ -0,0 +1,385 @@
+use std::cell::RefCell;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::Instruction;
+use solana_lending_testkit::{collateral, receipt, Protocol, CHAIN, DAY, GRACE, LAMPORTS, MULTISIG_TIMEOUT, SOURCE, TOKEN};
+use solana_lending_client::events::log_line;
+use solana_lending_client::{instructions as ix, CollateralWithMultisig, MultisigStatus, WitnessAction, ID};
+use solana_lending_relayer::*;
+
+const RATE: u64 = 500;
+const TERM: u64 = 30 * DAY;
+
+// The protocol and the lending program's transaction history, shared by every relayer in a test.
+struct Ledger {
+    p: Protocol,
+    transactions: Vec<(String, Vec<String>)>,
+}
+
+// A `LendingChain` over the native harness. Each sent instruction runs as its own transaction
+// and its events are logged the way the runtime logs them.
+struct HarnessChain {
+    ledger: Rc<RefCell<Ledger>>,
+    witness: Pubkey,
+}
+
+impl LendingChain for HarnessChain {
+    fn payer(&self) -> Pubkey {
+        self.witness
+    }
+
+    fn account(&self, key: &Pubkey) -> Result<Option<Vec<u8>>> {
+        Ok(self.ledger.borrow().p.env.accounts.get(key).map(|a| a.data.clone()))
+    }
+
+    fn send(&mut self, instructions: &[Instruction]) -> Result<String> {
+        let mut ledger = self.ledger.borrow_mut();
+        let mut logs = vec![];
+        for ix in instructions {
+            ledger.p.env.take_events();
+            ledger.p.env.process_metas(ix.accounts.clone(), ix.data.clone()).map_err(|e| RelayerError::Transaction(e.to_string()))?;
+            logs.push(format!("Program {ID} invoke [1]"));
+            logs.extend(ledger.p.env.take_events().iter().map(|data| log_line(data)));
+            logs.push(format!("Program {ID} success"));
+        }
+        let signature = format!("tx{}", ledger.transactions.len());
+        ledger.transactions.push((signature.clone(), logs));
+        Ok(signature)
+    }
+
+    fn transactions_since(&mut self, cursor: Option<&str>) -> Result<Vec<(String, Vec<String>)>> {
+        let transactions = &self.ledger.borrow().transactions;
+        let start = cursor.map_or(0, |c| transactions.iter().position(|(s, _)| s == c).unwrap() + 1);
+        Ok(transactions[start..].to_vec())
+    }
+}
+
+impl Ledger {
+    // Runs a user instruction outside the relayers, recording its events like `send` does.
+    fn run(&mut self, f: impl FnOnce(&mut Protocol)) {
+        self.p.env.take_events();
+        f(&mut self.p);
+        let mut logs = vec![format!("Program {ID} invoke [1]")];
+        logs.extend(self.p.env.take_events().iter().map(|data| log_line(data)));
+        logs.push(format!("Program {ID} success"));
+        let signature = format!("tx{}", self.transactions.len());
+        self.transactions.push((signature, logs));
+    }
+}
+
+fn state_path(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("cclending-relayer-{}-{name}.json", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+struct Setup {
+    ledger: Rc<RefCell<Ledger>>,
+    freeze: FreezeEvent,
+    lender: Pubkey,
+}
+
+fn setup() -> Setup {
+    let mut p = Protocol::new();
+    let (lender, order_sn) = p.lender_with_order(10 * LAMPORTS, RATE, TERM, 1);
+    let borrower = p.env.wallet();
+    let freeze = FreezeEvent {
+        chainid: CHAIN,
+        collateral_sn: p.next_collateral_sn(),
+        source: SOURCE,
+        token: TOKEN,
+        frozen: 10 * LAMPORTS,
+        borrower,
+        order_sn,
+        amount: LAMPORTS,
+    };
+    Setup {
+        ledger: Rc::new(RefCell::new(Ledger { p, transactions: vec![] })),
+        freeze,
+        lender,
+    }
+}
+
+fn relayer(s: &Setup, witness: usize, state: &Path) -> Relayer<MockSource, HarnessChain> {
+    let mut source = MockSource::new(CHAIN);
+    source.events.push(s.freeze.clone());
+    let chain = HarnessChain {
+        ledger: s.ledger.clone(),
+        witness: s.ledger.borrow().p.witnesses[witness],
+    };
+    Relayer::new(source, chain, state).unwrap()
+}
+
+fn multisig(s: &Setup) -> CollateralWithMultisig {
+    s.ledger.borrow().p.env.get(&collateral(CHAIN, s.freeze.collateral_sn))
+}
+
+#[test]
+fn relays_a_loan_from_freeze_to_repayment() {
+    let s = setup();
+    let (state_a, state_b) = (state_path("repay-a"), state_path("repay-b"));
+    let mut a = relayer(&s, 0, &state_a);
+    let mut b = relayer(&s, 1, &state_b);
+    let before = s.ledger.borrow().p.env.lamports(&s.freeze.borrower);
+
+    a.tick().unwrap();
+    assert!(multisig(&s).status == MultisigStatus::InProgress);
+    assert_eq!(a.progress.rounds[&s.freeze.collateral_sn].status, RoundStatus::Signed);
+    assert_eq!(a.progress.next_block, 1);
+
+    // the second signature reaches consensus and the same pass borrows
+    b.tick().unwrap();
+    assert!(multisig(&s).status == MultisigStatus::Finished);
+    assert_eq!(b.progress.rounds[&s.freeze.collateral_sn].status, RoundStatus::Finished);
+    assert_eq!(s.ledger.borrow().p.env.lamports(&s.freeze.borrower), before + LAMPORTS);
+
+    a.tick().unwrap();
+    assert!(multisig(&s).branches.is_empty());
+    assert_eq!(a.progress.rounds[&s.freeze.collateral_sn].status, RoundStatus::Cleared);
+    b.tick().unwrap();
+    assert_eq!(b.progress.pending().count(), 0);
+
+    let receipt_sn = s.ledger.borrow().p.global_state().curr_receipt_sn - 1;
+    let borrower = s.freeze.borrower;
+    s.ledger.borrow_mut().run(|p| p.repay(&borrower, receipt_sn).unwrap());
+    let expected = Settlement::Repaid {
+        chainid: CHAIN,
+        collateral_sn: s.freeze.collateral_sn,
+        receipt_sn,
+        source: SOURCE,
+        frozen: s.freeze.frozen,
+    };
+    for r in [&mut a, &mut b] {
+        r.tick().unwrap();
+        r.tick().unwrap();
+        assert_eq!(r.source.settled, vec![expected.clone()]);
+    }
+}
+
+#[test]
+fn resumes_from_saved_progress() {
+    let s = setup();
+    let (state_a, state_b) = (state_path("resume-a"), state_path("resume-b"));
+    relayer(&s, 0, &state_a).tick().unwrap();
+
+    // a restarted relayer neither re-polls the freeze nor signs twice
+    let mut a = relayer(&s, 0, &state_a);
+    assert_eq!(a.progress.next_block, 1);
+    a.tick().unwrap();
+    assert_eq!(multisig(&s).branches[0].signers.len(), 1);
+
+    let mut b = relayer(&s, 1, &state_b);
+    b.tick().unwrap();
+    b.tick().unwrap();
+    assert_eq!(b.progress.pending().count(), 0);
+
+    let receipt_sn = s.ledger.borrow().p.global_state().curr_receipt_sn - 1;
+    let lender = s.lender;
+    s.ledger.borrow_mut().run(|p| {
+        p.env.warp(TERM + GRACE + 1);
+        p.liquidate(&lender, receipt_sn).unwrap();
+    });
+    assert!(!s.ledger.borrow().p.env.exists(&receipt(receipt_sn)));
+    b.tick().unwrap();
+    assert_eq!(
+        b.source.settled,
+        vec![Settlement::Liquidated {
+            chainid: CHAIN,
+            collateral_sn: s.freeze.collateral_sn,
+            receipt_sn,
+            source: SOURCE,
+            frozen: s.freeze.frozen,
+            receiver: SOURCE,
+        }]
+    );
+
+    // the liquidation was forwarded before the restart, so it is not forwarded again
+    let mut b = relayer(&s, 1, &state_b);
+    b.tick().unwrap();
+    assert!(b.source.settled.is_empty());
+    assert!(b.progress.settled.contains(&(CHAIN, s.freeze.collateral_sn, "liquidated".to_string(), 0)));
+}
+
+#[test]
+fn forwards_extensions_and_partial_repayments() {
+    let s = setup();
+    let mut a = relayer(&s, 0, &state_path("partial-a"));
+    let mut b = relayer(&s, 1, &state_path("partial-b"));
+    a.tick().unwrap();
+    b.tick().unwrap();
+    let receipt_sn = s.ledger.borrow().p.global_state().curr_receipt_sn - 1;
+    let borrower = s.freeze.borrower;
+    let time = s.ledger.borrow().p.env.now();
+
+    s.ledger.borrow_mut().run(|p| p.extend_loan(&borrower, receipt_sn, RATE, None).unwrap());
+    s.ledger.borrow_mut().run(|p| p.extend_loan(&borrower, receipt_sn, RATE, None).unwrap());
+    s.ledger.borrow_mut().run(|p| p.repay_partial(&borrower, receipt_sn, LAMPORTS / 4).unwrap());
+    s.ledger.borrow_mut().run(|p| p.repay_partial(&borrower, receipt_sn, LAMPORTS / 4).unwrap());
+    let extended = |n: u64| Settlement::Extended {
+        chainid: CHAIN,
+        collateral_sn: s.freeze.collateral_sn,
+        receipt_sn,
+        source: SOURCE,
+        expiry: time + (n + 1) * TERM + GRACE,
+    };
+    // collateral is released in proportion to the repaid share of the scheduled total
+    let total = LAMPORTS + LAMPORTS * RATE / 10000;
+    let unfrozen = |n: u64| s.freeze.frozen * (n * LAMPORTS / 4) / total;
+    let repaid = |n: u64| Settlement::PartiallyRepaid {
+        chainid: CHAIN,
+        collateral_sn: s.freeze.collateral_sn,
+        receipt_sn,
+        source: SOURCE,
+        repaid: n * LAMPORTS / 4,
+        remaining: total - n * LAMPORTS / 4,
+        released: unfrozen(n) - unfrozen(n - 1),
+    };
+
+    // every extension and partial repayment of the loan is forwarded once
+    a.tick().unwrap();
+    a.tick().unwrap();
+    assert_eq!(a.source.settled, vec![extended(1), extended(2), repaid(1), repaid(2)]);
+}
+
+#[test]
+fn forwards_rejected_rounds() {
+    let s = setup();
+    let mut a = relayer(&s, 0, &state_path("rejected-a"));
+    a.tick().unwrap();
+    let sn = s.freeze.collateral_sn;
+    s.ledger.borrow_mut().run(|p| {
+        p.env.warp(MULTISIG_TIMEOUT);
+        let payer = p.env.wallet();
+        p.expire_collateral(&payer, sn).unwrap();
+    });
+
+    a.tick().unwrap();
+    assert_eq!(a.source.settled, vec![Settlement::Rejected { chainid: CHAIN, collateral_sn: sn }]);
+    assert!(multisig(&s).branches.is_empty());
+}
+
+#[test]
+fn fails_a_round_whose_order_was_cancelled() {
+    let s = setup();
+    let mut a = relayer(&s, 0, &state_path("failed-a"));
+    let mut b = relayer(&s, 1, &state_path("failed-b"));
+    let (lender, order_sn) = (s.lender, s.freeze.order_sn);
+    s.ledger.borrow_mut().run(|p| p.cancel_order(&lender, order_sn).unwrap());
+
+    a.tick().unwrap();
+    b.tick().unwrap();
+    assert!(multisig(&s).status == MultisigStatus::Failed);
+    a.tick().unwrap();
+    assert!(multisig(&s).branches.is_empty());
+    assert_eq!(a.progress.rounds[&s.freeze.collateral_sn].status, RoundStatus::Cleared);
+    assert_eq!(
+        a.source.settled,
+        vec![Settlement::BorrowFailed {
+            chainid: CHAIN,
+            collateral_sn: s.freeze.collateral_sn,
+            order_sn: s.freeze.order_sn,
+            amount: s.freeze.amount,
+        }]
+    );
+}
+
+#[test]
+fn recounts_a_round_whose_signer_was_removed() {
+    let s = setup();
+    let mut b = relayer(&s, 1, &state_path("recount-b"));
+    let freeze = s.freeze.clone();
+    s.ledger.borrow_mut().run(|p| {
+        let [w0, w1, w2] = [p.witnesses[0], p.witnesses[1], p.witnesses[2]];
+        for w in [w0, w1] {
+            p.multisig_collateral(&w, freeze.collateral_sn, freeze.order_sn, &freeze.borrower, freeze.amount).unwrap();
+        }
+        let sn = p.global_state().curr_proposal_sn;
+        let propose = ix::propose_witness_change(&w1, sn, WitnessAction::Remove, w0, false);
+        p.env.process_metas(propose.accounts, propose.data).unwrap();
+        let approve = ix::approve_witness_change(&w2, sn, &w0, false);
+        p.env.process_metas(approve.accounts, approve.data).unwrap();
+    });
+
+    // w0's signature no longer counts, so b recounts instead of borrowing and w2's signature
+    // restores the consensus
+    b.tick().unwrap();
+    assert!(multisig(&s).status == MultisigStatus::InProgress);
+    assert_eq!(b.progress.rounds[&s.freeze.collateral_sn].status, RoundStatus::Signed);
+    relayer(&s, 2, &state_path("recount-c")).tick().unwrap();
+    assert!(multisig(&s).status == MultisigStatus::Finished);
+}
+
+#[test]
+fn ignores_other_chains() {
+    let mut s = setup();
+    s.freeze.chainid = CHAIN + 1;
+    let mut a = relayer(&s, 0, &state_path("other-chain"));
+    a.tick().unwrap();
+    assert!(a.progress.rounds.is_empty());
+    assert!(!s.ledger.borrow().p.env.exists(&collateral(CHAIN, s.freeze.collateral_sn)));
+}
+
+// Serves one canned JSON-RPC result per request and hands back the request bodies.
+fn serve(results: Vec<serde_json::Value>) -> (String, std::thread::JoinHandle<Vec<serde_json::Value>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let handle = std::thread::spawn(move || {
+        let mut requests = vec![];
+        for result in results {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line == "\r\n" {
+                    break;
+                }
+                if let Some((name, value)) = line.split_once(':') {
+                    if name.eq_ignore_ascii_case("content-length") {
+                        length = value.trim().parse().unwrap();
+                    }
+                }
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            let request: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            let response = serde_json::json!({ "jsonrpc": "2.0", "id": request["id"], "result": result }).to_string();
+            write!(reader.get_mut(), "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{response}", response.len()).unwrap();
+            requests.push(request);
+        }
+        requests
+    });
+    (url, handle)
+}
+
+#[test]
+fn json_rpc_source() {
+    let s = setup();
+    let settlement = Settlement::Repaid {
+        chainid: CHAIN,
+        collateral_sn: 1,
+        receipt_sn: 1,
+        source: SOURCE,
+        frozen: 10,
+    };
+    let (url, server) = serve(vec![
+        serde_json::json!({ "events": [s.freeze], "next_block": 42 }),
+        serde_json::Value::Null,
+    ]);
+    let mut source = JsonRpcSource::new(CHAIN, &url);
+    assert_eq!(source.freeze_events(7).unwrap(), (vec![s.freeze.clone()], 42));
+    source.settle(&settlement).unwrap();
+
+    let requests = server.join().unwrap();
+    assert_eq!(requests[0]["method"], "cclending_freezeEvents");
+    assert_eq!(requests[0]["params"], serde_json::json!([CHAIN, 7]));
+    assert_eq!(requests[1]["method"], "cclending_settle");
+    assert_eq!(requests[1]["params"][1]["kind"], "repaid");
+    assert_eq!(requests[1]["params"][1]["source"], format!("0x{}", "01".repeat(20)));
+    assert_eq!(serde_json::from_value::<Settlement>(requests[1]["params"][1].clone()).unwrap(), settlement);
+}
@@ -0,0 +1,1047 @@
+//! A native harness for the lending program. Instructions are serialized into the same input
+//! layout the SBF loader uses and run through `entry`; CPIs into the system and
+//! SPL token programs are served by syscall stubs, so the suite runs offline with `cargo test`.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::Once;
+
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::entrypoint::{self, BPF_ALIGN_OF_U128, MAX_PERMITTED_DATA_INCREASE, NON_DUP_MARKER};
+use anchor_lang::solana_program::instruction::{AccountMeta, Instruction};
+use anchor_lang::solana_program::program_error::ProgramError;
+use anchor_lang::solana_program::program_pack::Pack;
+use anchor_lang::solana_program::program_stubs::{self, SyscallStubs};
+use anchor_lang::solana_program::system_instruction::SystemInstruction;
+use anchor_lang::solana_program::bpf_loader_upgradeable::{self, UpgradeableLoaderState};
+use anchor_lang::solana_program::{bpf_loader, system_program, sysvar};
+use anchor_lang::{InstructionData, ToAccountMetas};
+use ::solana_lending::*;
+
+pub const LAMPORTS: u64 = 1_000_000_000;
+pub const DAY: u64 = 86400;
+pub const CHAIN: u32 = 5;
+pub const SOURCE: [u8; 20] = [1; 20];
+pub const TOKEN: [u8; 20] = [2; 20];
+
+thread_local! {
+    static CLOCK: RefCell<Clock> = RefCell::new(Clock::default());
+    static RETURN_DATA: RefCell<Option<(Pubkey, Vec<u8>)>> = const { RefCell::new(None) };
+    static EVENTS: RefCell<Vec<Vec<u8>>> = const { RefCell::new(Vec::new()) };
+}
+
+struct Stubs;
+
+impl SyscallStubs for Stubs {
+    fn sol_log(&self, _message: &str) {}
+
+    fn sol_log_data(&self, fields: &[&[u8]]) {
+        EVENTS.with(|e| e.borrow_mut().push(fields.concat()));
+    }
+
+    fn sol_get_clock_sysvar(&self, var_addr: *mut u8) -> u64 {
+        CLOCK.with(|c| unsafe { *(var_addr as *mut Clock) = c.borrow().clone() });
+        entrypoint::SUCCESS
+    }
+
+    fn sol_get_rent_sysvar(&self, var_addr: *mut u8) -> u64 {
+        unsafe { *(var_addr as *mut Rent) = Rent::default() };
+        entrypoint::SUCCESS
+    }
+
+    fn sol_get_return_data(&self) -> Option<(Pubkey, Vec<u8>)> {
+        RETURN_DATA.with(|r| r.borrow().clone())
+    }
+
+    fn sol_set_return_data(&self, data: &[u8]) {
+        RETURN_DATA.with(|r| *r.borrow_mut() = Some((ID, data.to_vec())));
+    }
+
+    fn sol_invoke_signed(&self, instruction: &Instruction, account_infos: &[AccountInfo], signers_seeds: &[&[&[u8]]]) -> entrypoint::ProgramResult {
+        let mut accounts = Vec::with_capacity(instruction.accounts.len());
+        for meta in instruction.accounts.iter() {
+            let mut info = account_infos.iter().find(|i| *i.key == meta.pubkey).ok_or(ProgramError::NotEnoughAccountKeys)?.clone();
+            let pda_signed = signers_seeds
+                .iter()
+                .any(|seeds| matches!(Pubkey::create_program_address(seeds, &ID), Ok(key) if key == meta.pubkey));
+            if meta.is_signer && !info.is_signer && !pda_signed {
+                return Err(ProgramError::MissingRequiredSignature);
+            }
+            info.is_signer = info.is_signer || pda_signed;
+            accounts.push(info);
+        }
+        if instruction.program_id == system_program::ID {
+            process_system(&accounts, &instruction.data)
+        } else if instruction.program_id == spl_token::ID {
+            spl_token::processor::Processor::process(&instruction.program_id, &accounts, &instruction.data)
+        } else {
+            Err(ProgramError::IncorrectProgramId)
+        }
+    }
+}
+
+fn debit(from: &AccountInfo, lamports: u64) -> entrypoint::ProgramResult {
+    if from.lamports() < lamports {
+        return Err(ProgramError::InsufficientFunds);
+    }
+    **from.try_borrow_mut_lamports()? -= lamports;
+    Ok(())
+}
+
+fn process_system(accounts: &[AccountInfo], data: &[u8]) -> entrypoint::ProgramResult {
+    let instruction: SystemInstruction = bincode::deserialize(data).map_err(|_| ProgramError::InvalidInstructionData)?;
+    match instruction {
+        SystemInstruction::CreateAccount { lamports, space, owner } => {
+            let (from, to) = (&accounts[0], &accounts[1]);
+            if !from.is_signer || !to.is_signer {
+                return Err(ProgramError::MissingRequiredSignature);
+            }
+            if to.lamports() > 0 || !to.data_is_empty() {
+                return Err(ProgramError::AccountAlreadyInitialized);
+            }
+            debit(from, lamports)?;
+            **to.try_borrow_mut_lamports()? += lamports;
+            to.realloc(space as usize, true)?;
+            to.assign(&owner);
+        }
+        SystemInstruction::Transfer { lamports } => {
+            let (from, to) = (&accounts[0], &accounts[1]);
+            if !from.is_signer {
+                return Err(ProgramError::MissingRequiredSignature);
+            }
+            if !from.data_is_empty() || *from.owner != system_program::ID {
+                return Err(ProgramError::InvalidArgument);
+            }
+            debit(from, lamports)?;
+            **to.try_borrow_mut_lamports()? += lamports;
+        }
+        SystemInstruction::Allocate { space } => {
+            if !accounts[0].is_signer {
+                return Err(ProgramError::MissingRequiredSignature);
+            }
+            if !accounts[0].data_is_empty() || *accounts[0].owner != system_program::ID {
+                return Err(ProgramError::AccountAlreadyInitialized);
+            }
+            accounts[0].realloc(space as usize, true)?;
+        }
+        SystemInstruction::Assign { owner } => {
+            if !accounts[0].is_signer {
+                return Err(ProgramError::MissingRequiredSignature);
+            }
+            if *accounts[0].owner != system_program::ID && *accounts[0].owner != owner {
+                return Err(ProgramError::IncorrectProgramId);
+            }
+            accounts[0].assign(&owner);
+        }
+        _ => return Err(ProgramError::InvalidInstructionData),
+    }
+    Ok(())
+}
+
+#[derive(Clone, Default)]
+pub struct AccountState {
+    pub lamports: u64,
+    pub data: Vec<u8>,
+    pub owner: Pubkey,
+    pub executable: bool,
+}
+
+pub struct Env {
+    pub accounts: HashMap<Pubkey, AccountState>,
+    pub clock: Clock,
+}
+
+impl Default for Env {
+    fn default() -> Self {
+        Env::new()
+    }
+}
+
+impl Env {
+    pub fn new() -> Self {
+        static STUBS: Once = Once::new();
+        STUBS.call_once(|| {
+            program_stubs::set_syscall_stubs(Box::new(Stubs));
+        });
+
+        let mut env = Env {
+            accounts: HashMap::new(),
+            clock: Clock {
+                unix_timestamp: 1_700_000_000,
+                ..Clock::default()
+            },
+        };
+        env.add_program(system_program::ID, "NativeLoader1111111111111111111111111111111".parse().unwrap());
+        env.add_program(spl_token::ID, bpf_loader::ID);
+        env.add_program(ID, bpf_loader::ID);
+        let rent = bincode::serialize(&Rent::default()).unwrap();
+        env.set_account(sysvar::rent::ID, AccountState {
+            lamports: 1,
+            data: rent,
+            owner: sysvar::ID,
+            executable: false,
+        });
+        env
+    }
+
+    fn add_program(&mut self, id: Pubkey, loader: Pubkey) {
+        self.set_account(id, AccountState {
+            lamports: 1,
+            data: vec![],
+            owner: loader,
+            executable: true,
+        });
+    }
+
+    // Records `authority` as the upgrade authority in the program's ProgramData account.
+    pub fn set_upgrade_authority(&mut self, authority: &Pubkey) {
+        let state = UpgradeableLoaderState::ProgramData {
+            slot: 0,
+            upgrade_authority_address: Some(*authority),
+        };
+        self.set_account(program_data(), AccountState {
+            lamports: 1,
+            data: bincode::serialize(&state).unwrap(),
+            owner: bpf_loader_upgradeable::ID,
+            executable: false,
+        });
+    }
+
+    pub fn set_account(&mut self, key: Pubkey, account: AccountState) {
+        self.accounts.insert(key, account);
+    }
+
+    pub fn now(&self) -> u64 {
+        self.clock.unix_timestamp as u64
+    }
+
+    pub fn warp(&mut self, seconds: u64) {
+        self.clock.unix_timestamp += seconds as i64;
+    }
+
+    pub fn wallet(&mut self) -> Pubkey {
+        let key = Pubkey::new_unique();
+        self.airdrop(&key, 100 * LAMPORTS);
+        key
+    }
+
+    pub fn airdrop(&mut self, key: &Pubkey, lamports: u64) {
+        let account = self.accounts.entry(*key).or_insert_with(|| AccountState {
+            owner: system_program::ID,
+            ..AccountState::default()
+        });
+        account.lamports += lamports;
+    }
+
+    pub fn exists(&self, key: &Pubkey) -> bool {
+        self.accounts.contains_key(key)
+    }
+
+    pub fn lamports(&self, key: &Pubkey) -> u64 {
+        self.accounts.get(key).map_or(0, |a| a.lamports)
+    }
+
+    pub fn get<T: AccountDeserialize>(&self, key: &Pubkey) -> T {
+        let account = self.accounts.get(key).unwrap_or_else(|| panic!("account {key} does not exist"));
+        T::try_deserialize(&mut account.data.as_slice()).unwrap()
+    }
+
+    pub fn return_data(&self) -> Option<Vec<u8>> {
+        RETURN_DATA.with(|r| r.borrow().clone()).map(|(_, data)| data)
+    }
+
+    /// Drains the data logged by `emit!` since the last call.
+    pub fn take_events(&self) -> Vec<Vec<u8>> {
+        EVENTS.with(|e| e.take())
+    }
+
+    pub fn create_mint(&mut self, decimals: u8) -> Pubkey {
+        let key = Pubkey::new_unique();
+        let mut data = vec![0; spl_token::state::Mint::LEN];
+        spl_token::state::Mint {
+            mint_authority: Some(Pubkey::new_unique()).into(),
+            supply: u64::MAX / 2,
+            decimals,
+            is_initialized: true,
+            freeze_authority: None.into(),
+        }
+        .pack_into_slice(&mut data);
+        self.set_account(key, AccountState {
+            lamports: Rent::default().minimum_balance(data.len()),
+            data,
+            owner: spl_token::ID,
+            executable: false,
+        });
+        key
+    }
+
+    pub fn create_token_account(&mut self, mint: &Pubkey, owner: &Pubkey, amount: u64) -> Pubkey {
+        let key = Pubkey::new_unique();
+        let mut data = vec![0; spl_token::state::Account::LEN];
+        spl_token::state::Account {
+            mint: *mint,
+            owner: *owner,
+            amount,
+            state: spl_token::state::AccountState::Initialized,
+            ..spl_token::state::Account::default()
+        }
+        .pack_into_slice(&mut data);
+        self.set_account(key, AccountState {
+            lamports: Rent::default().minimum_balance(data.len()),
+            data,
+            owner: spl_token::ID,
+            executable: false,
+        });
+        key
+    }
+
+    pub fn token_balance(&self, key: &Pubkey) -> u64 {
+        spl_token::state::Account::unpack(&self.accounts[key].data).unwrap().amount
+    }
+
+    pub fn process<A: ToAccountMetas, D: InstructionData>(&mut self, accounts: A, data: D) -> std::result::Result<(), ProgramError> {
+        self.process_metas(accounts.to_account_metas(None), data.data())
+    }
+
+    // Runs one instruction as its own transaction: account changes are only kept on success.
+    pub fn process_metas(&mut self, mut metas: Vec<AccountMeta>, data: Vec<u8>) -> std::result::Result<(), ProgramError> {
+        let clock = bincode::serialize(&self.clock).unwrap();
+        self.set_account(sysvar::clock::ID, AccountState {
+            lamports: 1,
+            data: clock,
+            owner: sysvar::ID,
+            executable: false,
+        });
+        CLOCK.with(|c| *c.borrow_mut() = self.clock.clone());
+        RETURN_DATA.with(|r| *r.borrow_mut() = None);
+
+        // Duplicate keys share the flags of all their occurrences, as in a transaction.
+        for i in 0..metas.len() {
+            let (signer, writable) = metas
+                .iter()
+                .filter(|m| m.pubkey == metas[i].pubkey)
+                .fold((false, false), |(s, w), m| (s || m.is_signer, w || m.is_writable));
+            metas[i].is_signer = signer;
+            metas[i].is_writable = writable;
+        }
+
+        let (mut buffer, keys) = self.serialize(&metas, &data);
+        let before: u64 = keys.iter().enumerate().filter(|(i, k)| !keys[..*i].contains(k)).map(|(_, k)| self.lamports(k)).sum();
+        let (program_id, infos, instruction_data) = unsafe { entrypoint::deserialize(buffer.as_mut_ptr() as *mut u8) };
+        entry(program_id, &infos, instruction_data)?;
+
+        let mut updates = Vec::new();
+        for (i, info) in infos.iter().enumerate() {
+            if keys[..i].contains(info.key) {
+                continue;
+            }
+            let data = info.data.borrow().to_vec();
+            let lamports = info.lamports();
+            if *info.owner == ID && lamports > 0 && lamports < Rent::default().minimum_balance(data.len()) {
+                return Err(ProgramError::AccountNotRentExempt);
+            }
+            updates.push((*info.key, AccountState {
+                lamports,
+                data,
+                owner: *info.owner,
+                executable: info.executable,
+            }));
+        }
+        let after: u64 = updates.iter().map(|(_, a)| a.lamports).sum();
+        assert_eq!(before, after, "instruction did not preserve the total lamports");
+
+        drop(infos);
+        for (key, account) in updates {
+            if account.lamports == 0 {
+                self.accounts.remove(&key);
+            } else {
+                self.accounts.insert(key, account);
+            }
+        }
+        Ok(())
+    }
+
+    // Lays the accounts out exactly like the SBF loader's input buffer.
+    fn serialize(&self, metas: &[AccountMeta], data: &[u8]) -> (Vec<u64>, Vec<Pubkey>) {
+        let mut bytes: Vec<u8> = Vec::new();
+        let mut keys: Vec<Pubkey> = Vec::new();
+        bytes.extend_from_slice(&(metas.len() as u64).to_le_bytes());
+        for meta in metas {
+            if let Some(index) = keys.iter().position(|k| *k == meta.pubkey) {
+                bytes.push(index as u8);
+                bytes.extend_from_slice(&[0; 7]);
+                keys.push(meta.pubkey);
+                continue;
+            }
+            keys.push(meta.pubkey);
+            let account = self.accounts.get(&meta.pubkey).cloned().unwrap_or_else(|| AccountState {
+                owner: system_program::ID,
+                ..AccountState::default()
+            });
+            bytes.push(NON_DUP_MARKER);
+            bytes.push(meta.is_signer as u8);
+            bytes.push(meta.is_writable as u8);
+            bytes.push(account.executable as u8);
+            bytes.extend_from_slice(&[0; 4]);
+            bytes.extend_from_slice(meta.pubkey.as_ref());
+            bytes.extend_from_slice(account.owner.as_ref());
+            bytes.extend_from_slice(&account.lamports.to_le_bytes());
+            bytes.extend_from_slice(&(account.data.len() as u64).to_le_bytes());
+            bytes.extend_from_slice(&account.data);
+            bytes.resize(bytes.len() + MAX_PERMITTED_DATA_INCREASE, 0);
+            bytes.resize(bytes.len().next_multiple_of(BPF_ALIGN_OF_U128), 0);
+            bytes.extend_from_slice(&u64::MAX.to_le_bytes());
+        }
+        bytes.extend_from_slice(&(data.len() as u64).to_le_bytes());
+        bytes.extend_from_slice(data);
+        bytes.extend_from_slice(ID.as_ref());
+
+        let mut buffer = vec![0u64; bytes.len().div_ceil(8)];
+        unsafe { std::ptr::copy_nonoverlapping(bytes.as_ptr(), buffer.as_mut_ptr() as *mut u8, bytes.len()) };
+        (buffer, keys)
+    }
+}
+
+pub fn error(e: MyError) -> ProgramError {
+    ProgramError::Custom(e.into())
+}
+
+pub fn anchor_error(e: anchor_lang::error::ErrorCode) -> ProgramError {
+    ProgramError::Custom(e.into())
+}
+
+pub fn pda(seeds: &[&[u8]]) -> Pubkey {
+    Pubkey::find_program_address(seeds, &ID).0
+}
+
+pub fn program_data() -> Pubkey {
+    Pubkey::find_program_address(&[ID.as_ref()], &bpf_loader_upgradeable::ID).0
+}
+
+pub fn global() -> Pubkey {
+    pda(&[PREFIX_STATE.as_bytes()])
+}
+
+pub fn config() -> Pubkey {
+    pda(&[PREFIX_CONFIG.as_bytes()])
+}
+
+pub fn roles() -> Pubkey {
+    pda(&[PREFIX_ROLES.as_bytes()])
+}
+
+pub fn witnesses() -> Pubkey {
+    pda(&[PREFIX_WITNESSES.as_bytes()])
+}
+
+pub fn reward_pool() -> Pubkey {
+    pda(&[PREFIX_REWARD.as_bytes()])
+}
+
+pub fn reward(witness: &Pubkey) -> Pubkey {
+    pda(&[PREFIX_REWARD.as_bytes(), witness.as_ref()])
+}
+
+pub fn treasury() -> Pubkey {
+    pda(&[PREFIX_TREASURY.as_bytes()])
+}
+
+pub fn stake_config() -> Pubkey {
+    pda(&[PREFIX_STAKE_CONFIG.as_bytes()])
+}
+
+pub fn pending_config() -> Pubkey {
+    pda(&[PREFIX_PENDING.as_bytes(), PREFIX_CONFIG.as_bytes()])
+}
+
+pub fn ccfee(chainid: u32) -> Pubkey {
+    pda(&[PREFIX_CCFEE.as_bytes(), chainid.to_le_bytes().as_ref()])
+}
+
+pub fn pending_ccfee(chainid: u32) -> Pubkey {
+    pda(&[PREFIX_PENDING.as_bytes(), PREFIX_CCFEE.as_bytes(), chainid.to_le_bytes().as_ref()])
+}
+
+pub fn balance(owner: &Pubkey) -> Pubkey {
+    pda(&[PREFIX_BALANCE.as_bytes(), owner.as_ref()])
+}
+
+pub fn token_balance(owner: &Pubkey, mint: &Pubkey) -> Pubkey {
+    pda(&[PREFIX_BALANCE.as_bytes(), owner.as_ref(), mint.as_ref()])
+}
+
+pub fn vault(mint: &Pubkey) -> Pubkey {
+    pda(&[PREFIX_VAULT.as_bytes(), mint.as_ref()])
+}
+
+pub fn order(sn: u64) -> Pubkey {
+    pda(&[PREFIX_ORDER.as_bytes(), sn.to_le_bytes().as_ref()])
+}
+
+pub fn receipt(sn: u64) -> Pubkey {
+    pda(&[PREFIX_RECEIPT.as_bytes(), sn.to_le_bytes().as_ref()])
+}
+
+pub fn collateral(chainid: u32, sn: u64) -> Pubkey {
+    pda(&[PREFIX_COLLATERAL.as_bytes(), chainid.to_le_bytes().as_ref(), sn.to_le_bytes().as_ref()])
+}
+
+pub fn reinvest(lender: &Pubkey, mint: &Pubkey) -> Pubkey {
+    pda(&[PREFIX_REINVEST.as_bytes(), lender.as_ref(), mint.as_ref()])
+}
+
+pub fn proposal(sn: u64) -> Pubkey {
+    pda(&[PREFIX_PROPOSAL.as_bytes(), sn.to_le_bytes().as_ref()])
+}
+
+pub fn stake(witness: &Pubkey) -> Pubkey {
+    pda(&[PREFIX_STAKE.as_bytes(), witness.as_ref()])
+}
+
+pub fn price_feed(chainid: u32, token: &[u8; 20]) -> Pubkey {
+    pda(&[PREFIX_PRICE.as_bytes(), chainid.to_le_bytes().as_ref(), token.as_ref()])
+}
+
+pub fn mortgage(owner: &Pubkey, mint: &Pubkey) -> Pubkey {
+    pda(&[PREFIX_MORTGAGE.as_bytes(), owner.as_ref(), mint.as_ref()])
+}
+
+pub fn freeze(sn: u64) -> Pubkey {
+    pda(&[PREFIX_FREEZE.as_bytes(), sn.to_le_bytes().as_ref()])
+}
+
+pub fn release(sn: u64) -> Pubkey {
+    pda(&[PREFIX_RELEASE.as_bytes(), sn.to_le_bytes().as_ref()])
+}
+
+pub fn withdrawal(sn: u64) -> Pubkey {
+    pda(&[PREFIX_WITHDRAWAL.as_bytes(), sn.to_le_bytes().as_ref()])
+}
+
+pub const MIN_IR: u64 = 100;
+pub const MAX_IR: u64 = 2000;
+pub const PENALTY_IR: u64 = 1;
+pub const PENALTY_DAYS: u64 = 30;
+pub const COMMISSION_RATE: u64 = 10;
+pub const MIN_TERM: u64 = DAY;
+pub const MAX_TERM: u64 = 365 * DAY;
+pub const GRACE: u64 = 3 * DAY;
+pub const MULTISIG_TIMEOUT: u64 = DAY;
+pub const FEE: u64 = 3_000_000;
+
+// A deployed protocol: the admin holds every role, the config is applied, three witnesses are
+// registered and cross-chain fees are set for CHAIN.
+pub struct Protocol {
+    pub env: Env,
+    pub admin: Pubkey,
+    pub witnesses: Vec<Pubkey>,
+}
+
+impl Default for Protocol {
+    fn default() -> Self {
+        Protocol::new()
+    }
+}
+
+impl Protocol {
+    pub fn new() -> Self {
+        let mut p = Protocol::bare();
+        let admin = p.admin;
+        p.queue_config(&admin, p.default_config()).unwrap();
+        p.apply_config(&admin).unwrap();
+        let witnesses: Vec<Pubkey> = (0..3).map(|_| p.env.wallet()).collect();
+        p.init_witnesses(&admin, witnesses.clone()).unwrap();
+        p.witnesses = witnesses;
+        p.queue_ccfee(&admin, CHAIN, FEE).unwrap();
+        p.env.warp(CONFIG_DELAY);
+        p.apply_ccfee(&admin, CHAIN).unwrap();
+        p
+    }
+
+    // An initialized protocol whose admin holds every role, without config, witnesses or fees.
+    pub fn bare() -> Self {
+        let mut env = Env::new();
+        let admin = env.wallet();
+        env.set_upgrade_authority(&admin);
+        let mut p = Protocol {
+            env,
+            admin,
+            witnesses: vec![],
+        };
+        p.initialize(&admin).unwrap();
+        for role in [Role::ConfigManager, Role::FeeManager, Role::WitnessManager, Role::Pauser, Role::TreasuryWithdrawer] {
+            p.grant_role(&admin, role, &admin).unwrap();
+        }
+        p
+    }
+
+    pub fn default_config(&self) -> ConfigParams {
+        ConfigParams {
+            min_ir: MIN_IR,
+            max_ir: MAX_IR,
+            penalty_ir: PENALTY_IR,
+            penalty_days: PENALTY_DAYS,
+            commission_rate: COMMISSION_RATE,
+            min_term: MIN_TERM,
+            max_term: MAX_TERM,
+            grace: GRACE,
+            multisig_timeout: MULTISIG_TIMEOUT,
+        }
+    }
+
+    pub fn global_state(&self) -> GlobalState {
+        self.env.get(&global())
+    }
+
+    pub fn initialize(&mut self, payer: &Pubkey) -> std::result::Result<(), ProgramError> {
+        self.env.process(
+            accounts::Initialize {
+                global_state: global(),
+                reward_pool: reward_pool(),
+                treasury: treasury(),
+                program_data: program_data(),
+                payer: *payer,
+                system_program: system_program::ID,
+            },
+            instruction::Initialize {},
+        )
+    }
+
+    pub fn grant_role(&mut self, payer: &Pubkey, role: Role, key: &Pubkey) -> std::result::Result<(), ProgramError> {
+        self.env.process(
+            accounts::UpdateRoles {
+                roles: roles(),
+                global: global(),
+                payer: *payer,
+                system_program: system_program::ID,
+            },
+            instruction::GrantRole { role, key: *key },
+        )
+    }
+
+    pub fn revoke_role(&mut self, payer: &Pubkey, role: Role, key: &Pubkey) -> std::result::Result<(), ProgramError> {
+        self.env.process(
+            accounts::UpdateRoles {
+                roles: roles(),
+                global: global(),
+                payer: *payer,
+                system_program: system_program::ID,
+            },
+            instruction::RevokeRole { role, key: *key },
+        )
+    }
+
+    pub fn queue_config(&mut self, payer: &Pubkey, params: ConfigParams) -> std::result::Result<(), ProgramError> {
+        self.env.process(
+            accounts::QueueConfig {
+                pending_config: pending_config(),
+                config: config(),
+                roles: roles(),
+                payer: *payer,
+                system_program: system_program::ID,
+                clock: sysvar::clock::ID,
+            },
+            instruction::QueueConfig { params },
+        )
+    }
+
+    pub fn apply_config(&mut self, payer: &Pubkey) -> std::result::Result<(), ProgramError> {
+        self.env.process(
+            accounts::ApplyConfig {
+                pending_config: pending_config(),
+                config: config(),
+                roles: roles(),
+                payer: *payer,
+                clock: sysvar::clock::ID,
+            },
+            instruction::ApplyConfig {},
+        )
+    }
+
+    pub fn queue_ccfee(&mut self, payer: &Pubkey, chainid: u32, fee: u64) -> std::result::Result<(), ProgramError> {
+        self.env.process(
+            accounts::QueueCCFee {
+                pending_ccfee: pending_ccfee(chainid),
+                roles: roles(),
+                payer: *payer,
+                system_program: system_program::ID,
+                clock: sysvar::clock::ID,
+            },
+            instruction::QueueCcfee { chainid, fee },
+        )
+    }
+
+    pub fn apply_ccfee(&mut self, payer: &Pubkey, chainid: u32) -> std::result::Result<(), ProgramError> {
+        self.env.process(
+            accounts::ApplyCCFee {
+                pending_ccfee: pending_ccfee(chainid),
+                cc_fee: ccfee(chainid),
+                roles: roles(),
+                payer: *payer,
+                system_program: system_program::ID,
+                clock: sysvar::clock::ID,
+            },
+            instruction::ApplyCcfee { chainid },
+        )
+    }
+
+    pub fn init_witnesses(&mut self, payer: &Pubkey, v: Vec<Pubkey>) -> std::result::Result<(), ProgramError> {
+        self.env.process(
+            accounts::UpdateWitnesses {
+                witnesses: witnesses(),
+                roles: roles(),
+                payer: *payer,
+                system_program: system_program::ID,
+            },
+            instruction::InitWitnesses { v },
+        )
+    }
+
+    pub fn set_pause(&mut self, payer: &Pubkey, paused: u64) -> std::result::Result<(), ProgramError> {
+        self.env.process(
+            accounts::SetPause {
+                global: global(),
+                roles: roles(),
+                payer: *payer,
+            },
+            instruction::SetPause { paused },
+        )
+    }
+
+    pub fn deposit(&mut self, payer: &Pubkey, amount: u64) -> std::result::Result<(), ProgramError> {
+        self.env.process(
+            accounts::Deposit {
+                user_balance: balance(payer),
+                payer: *payer,
+                system_program: system_program::ID,
+            },
+            instruction::Deposit { amount },
+        )
+    }
+
+    pub fn withdraw(&mut self, payer: &Pubkey, amount: u64) -> std::result::Result<(), ProgramError> {
+        self.env.process(
+            accounts::Withdraw {
+                user_balance: balance(payer),
+                payer: *payer,
+                system_program: system_program::ID,
+            },
+            instruction::Withdraw { amount },
+        )
+    }
+
+    pub fn place_order(&mut self, payer: &Pubkey, amount: u64, rate: u64, term: u64, installments: u8) -> std::result::Result<u64, ProgramError> {
+        let sn = self.global_state().curr_order_sn;
+        self.env.process(
+            accounts::PlaceOrder {
+                order: order(sn),
+                user_balance: balance(payer),
+                global: global(),
+                config: config(),
+                payer: *payer,
+                system_program: system_program::ID,
+            },
+            instruction::PlaceOrder { amount, rate, term, installments },
+        )?;
+        Ok(sn)
+    }
+
+    pub fn cancel_order(&mut self, payer: &Pubkey, order_sn: u64) -> std::result::Result<(), ProgramError> {
+        self.env.process(
+            accounts::CancelOrder {
+                order: order(order_sn),
+                user_balance: balance(payer),
+                payer: *payer,
+                system_program: system_program::ID,
+            },
+            instruction::CancelOrder { order_sn },
+        )
+    }
+
+    // A lender with `amount` deposited and placed in a single order.
+    pub fn lender_with_order(&mut self, amount: u64, rate: u64, term: u64, installments: u8) -> (Pubkey, u64) {
+        let lender = self.env.wallet();
+        self.deposit(&lender, amount).unwrap();
+        let sn = self.place_order(&lender, amount, rate, term, installments).unwrap();
+        (lender, sn)
+    }
+
+    pub fn multisig_collateral(&mut self, witness: &Pubkey, collateral_sn: u64, order_sn: u64, borrower: &Pubkey, amount: u64) -> std::result::Result<(), ProgramError> {
+        self.env.process(
+            accounts::MultisigCollateral {
+                multisig: collateral(CHAIN, collateral_sn),
+                order: order(order_sn),
+                config: config(),
+                witnesses: witnesses(),
+                global: global(),
+                reward: reward(witness),
+                payer: *witness,
+                system_program: system_program::ID,
+                clock: sysvar::clock::ID,
+            },
+            instruction::MultisigCollateral {
+                chainid: CHAIN,
+                collateral_sn,
+                source: SOURCE,
+                token: TOKEN,
+                frozen: 10 * amount,
+                order_sn,
+                borrower: *borrower,
+                amount,
+            },
+        )
+    }
+
+    pub fn recount_collateral(&mut self, payer: &Pubkey, collateral_sn: u64) -> std::result::Result<(), ProgramError> {
+        self.env.process(
+            accounts::RecountCollateral {
+                multisig: collateral(CHAIN, collateral_sn),
+                witnesses: witnesses(),
+                payer: *payer,
+            },
+            instruction::RecountCollateral {
+                chainid: CHAIN,
+                collateral_sn,
+            },
+        )
+    }
+
+    pub fn revoke_collateral_signature(&mut self, witness: &Pubkey, collateral_sn: u64) -> std::result::Result<(), ProgramError> {
+        self.env.process(
+            accounts::RevokeCollateralSignature {
+                multisig: collateral(CHAIN, collateral_sn),
+                witnesses: witnesses(),
+                payer: *witness,
+            },
+            instruction::RevokeCollateralSignature {
+                chainid: CHAIN,
+                collateral_sn,
+            },
+        )
+    }
+
+    pub fn reserve_order(&mut self, borrower: &Pubkey, collateral_sn: u64, order_sn: u64, amount: u64) -> std::result::Result<(), ProgramError> {
+        self.env.process(
+            accounts::ReserveOrder {
+                multisig: collateral(CHAIN, collateral_sn),
+                order: order(order_sn),
+                config: config(),
+                payer: *borrower,
+                clock: sysvar::clock::ID,
+            },
+            instruction::ReserveOrder {
+                chainid: CHAIN,
+                collateral_sn,
+                _order_sn: order_sn,
+                amount,
+            },
+        )
+    }
+
+    pub fn release_reservation(&mut self, payer: &Pubkey, collateral_sn: u64) -> std::result::Result<(), ProgramError> {
+        let multisig: CollateralWithMultisig = self.env.get(&collateral(CHAIN, collateral_sn));
+        self.env.process(
+            accounts::ReleaseReservation {
+                multisig: collateral(CHAIN, collateral_sn),
+                order: order(multisig.reservation.order_sn),
+                payer: *payer,
+                clock: sysvar::clock::ID,
+            },
+            instruction::ReleaseReservation {
+                chainid: CHAIN,
+                collateral_sn,
+            },
+        )
+    }
+
+    pub fn fail_borrow(&mut self, payer: &Pubkey, collateral_sn: u64) -> std::result::Result<(), ProgramError> {
+        let multisig: CollateralWithMultisig = self.env.get(&collateral(CHAIN, collateral_sn));
+        self.env.process(
+            accounts::FailBorrow {
+                multisig: collateral(CHAIN, collateral_sn),
+                order: order(multisig.branches[0].collateral.order_sn),
+                payer: *payer,
+            },
+            instruction::FailBorrow {
+                _chainid: CHAIN,
+                _collateral_sn: collateral_sn,
+            },
+        )
+    }
+
+    pub fn expire_collateral(&mut self, payer: &Pubkey, collateral_sn: u64) -> std::result::Result<(), ProgramError> {
+        self.env.process(
+            accounts::ExpireCollateral {
+                multisig: collateral(CHAIN, collateral_sn),
+                config: config(),
+                witnesses: witnesses(),
+                payer: *payer,
+                clock: sysvar::clock::ID,
+            },
+            instruction::ExpireCollateral {
+                chainid: CHAIN,
+                collateral_sn,
+            },
+        )
+    }
+
+    pub fn clear_collateral(&mut self, payer: &Pubkey, collateral_sn: u64) -> std::result::Result<(), ProgramError> {
+        self.env.process(
+            accounts::ClearCollateral {
+                multisig: collateral(CHAIN, collateral_sn),
+                payer: *payer,
+                system_program: system_program::ID,
+            },
+            instruction::ClearCollateral {
+                _chainid: CHAIN,
+                _collateral_sn: collateral_sn,
+            },
+        )
+    }
+
+    pub fn reach_consensus(&mut self, collateral_sn: u64, order_sn: u64, borrower: &Pubkey, amount: u64) {
+        let threshold = self.env.get::<Witnesses>(&witnesses()).multisig_threshold;
+        for witness in self.witnesses.clone().iter().take(threshold) {
+            self.multisig_collateral(witness, collateral_sn, order_sn, borrower, amount).unwrap();
+        }
+    }
+
+    pub fn borrow(&mut self, payer: &Pubkey, collateral_sn: u64, recipient: &Pubkey) -> std::result::Result<u64, ProgramError> {
+        let sn = self.global_state().curr_receipt_sn;
+        let multisig: CollateralWithMultisig = self.env.get(&collateral(CHAIN, collateral_sn));
+        let order_sn = multisig.branches[0].collateral.order_sn;
+        self.env.process(
+            accounts::Borrow {
+                multisig: collateral(CHAIN, collateral_sn),
+                witnesses: witnesses(),
+                receipt: receipt(sn),
+                order: order(order_sn),
+                global: global(),
+                config: config(),
+                cc_fee: ccfee(CHAIN),
+                recipient: *recipient,
+                payer: *payer,
+                system_program: system_program::ID,
+                clock: sysvar::clock::ID,
+            },
+            instruction::Borrow {
+                _chainid: CHAIN,
+                _collateral_sn: collateral_sn,
+            },
+        )?;
+        Ok(sn)
+    }
+
+    // A borrower holding a native loan of `amount` taken from a fresh order.
+    pub fn loan(&mut self, amount: u64, rate: u64, term: u64, installments: u8) -> Loan {
+        let (lender, order_sn) = self.lender_with_order(10 * amount, rate, term, installments);
+        let borrower = self.env.wallet();
+        let collateral_sn = self.next_collateral_sn();
+        self.reach_consensus(collateral_sn, order_sn, &borrower, amount);
+        let receipt_sn = self.borrow(&borrower, collateral_sn, &borrower).unwrap();
+        Loan {
+            lender,
+            borrower,
+            order_sn,
+            collateral_sn,
+            receipt_sn,
+        }
+    }
+
+    pub fn next_collateral_sn(&mut self) -> u64 {
+        (1..).find(|sn| !self.env.exists(&collateral(CHAIN, *sn))).unwrap()
+    }
+
+    pub fn repay_accounts(&self, payer: &Pubkey, receipt_sn: u64) -> accounts::Repay {
+        let r: LoanReceipt = self.env.get(&receipt(receipt_sn));
+        let reinvest_order = if self.env.exists(&reinvest(&r.lender, &r.mint)) {
+            order(self.env.get::<AutoReinvest>(&reinvest(&r.lender, &r.mint)).order_sn)
+        } else {
+            order(0)
+        };
+        accounts::Repay {
+            receipt: receipt(receipt_sn),
+            lender_balance: balance(&r.lender),
+            reinvest: reinvest(&r.lender, &r.mint),
+            reinvest_order,
+            config: config(),
+            treasury: treasury(),
+            cc_fee: ccfee(r.chainid),
+            reward_pool: reward_pool(),
+            payer: *payer,
+            system_program: system_program::ID,
+            clock: sysvar::clock::ID,
+        }
+    }
+
+    // The named accounts followed by the reward accounts of the receipt's signers.
+    pub fn with_rewards<A: ToAccountMetas>(&self, accounts: A, receipt_sn: u64) -> Vec<AccountMeta> {
+        let r: LoanReceipt = self.env.get(&receipt(receipt_sn));
+        let mut metas = accounts.to_account_metas(None);
+        metas.extend(r.signers.iter().map(|s| AccountMeta::new(reward(s), false)));
+        metas
+    }
+
+    pub fn repay(&mut self, payer: &Pubkey, receipt_sn: u64) -> std::result::Result<(), ProgramError> {
+        let metas = self.with_rewards(self.repay_accounts(payer, receipt_sn), receipt_sn);
+        self.env.process_metas(metas, instruction::Repay { receipt_sn }.data())
+    }
+
+    pub fn repay_partial(&mut self, payer: &Pubkey, receipt_sn: u64, amount: u64) -> std::result::Result<(), ProgramError> {
+        let metas = self.with_rewards(self.repay_accounts(payer, receipt_sn), receipt_sn);
+        self.env.process_metas(metas, instruction::RepayPartial { receipt_sn, amount }.data())
+    }
+
+    pub fn extend_loan(&mut self, payer: &Pubkey, receipt_sn: u64, rate: u64, lender: Option<Pubkey>) -> std::result::Result<(), ProgramError> {
+        let r: LoanReceipt = self.env.get(&receipt(receipt_sn));
+        let metas = self.with_rewards(
+            accounts::ExtendLoan {
+                receipt: receipt(receipt_sn),
+                lender_balance: balance(&r.lender),
+                config: config(),
+                global: global(),
+                treasury: treasury(),
+                cc_fee: ccfee(r.chainid),
+                reward_pool: reward_pool(),
+                lender,
+                payer: *payer,
+                system_program: system_program::ID,
+                clock: sysvar::clock::ID,
+            },
+            receipt_sn,
+        );
+        self.env.process_metas(metas, instruction::ExtendLoan { receipt_sn, rate }.data())
+    }
+
+    pub fn liquidate(&mut self, payer: &Pubkey, receipt_sn: u64) -> std::result::Result<(), ProgramError> {
+        let r: LoanReceipt = self.env.get(&receipt(receipt_sn));
+        let metas = self.with_rewards(
+            accounts::Liquidate {
+                receipt: receipt(receipt_sn),
+                treasury: treasury(),
+                cc_fee: ccfee(r.chainid),
+                reward_pool: reward_pool(),
+                payer: *payer,
+                system_program: system_program::ID,
+                clock: sysvar::clock::ID,
+            },
+            receipt_sn,
+        );
+        self.env.process_metas(
+            metas,
+            instruction::Liquidate {
+                receipt_sn,
+                receiver: SOURCE,
+            }
+            .data(),
+        )
+    }
+}
+
+pub struct Loan {
+    pub lender: Pubkey,
+    pub borrower: Pubkey,
+    pub order_sn: u64,
+    pub collateral_sn: u64,
+    pub receipt_sn: u64,
+}